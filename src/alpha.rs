@@ -0,0 +1,401 @@
+//! 透明图片的合成。
+//!
+//! 屏幕不能显示透明度，带 alpha 通道的图片要先铺在底色（matte）上再编码，
+//! 否则透明像素会显示出文件里残留的任意 RGB 值。底色可以是：
+//!
+//! - 颜色：`#rrggbb`、`#rgb` 或 `black`、`white`、`gray`；
+//! - 渐变：`gradient:#起始色,#结束色[,horizontal]`，默认从上到下；
+//! - 图片：`image:路径`，按 `cover` 方式铺满屏幕。
+//!
+//! 缩放和合成都在预乘 alpha 下进行，透明像素的颜色不会渗到边缘。
+//! `contain` 和 `center` 的留白（[`crate::fit::Background`]）铺在底色之上，
+//! 透明像素露出的是留白的颜色；没有留白的地方才露出底色。
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use image::imageops::FilterType;
+use image::{ImageReader, Rgba, RgbaImage};
+
+use crate::fit::{cover, parse_color, Gravity};
+
+/// 图片是否完全不透明，是则不需要合成。
+pub fn is_opaque(image: &RgbaImage) -> bool {
+    image.pixels().all(|p| p[3] == 255)
+}
+
+/// 把颜色乘以 alpha。
+pub fn premultiply(image: &RgbaImage) -> RgbaImage {
+    let mut out = image.clone();
+    for pixel in out.pixels_mut() {
+        let a = pixel[3] as u32;
+        for c in 0..3 {
+            pixel[c] = ((pixel[c] as u32 * a + 127) / 255) as u8;
+        }
+    }
+    out
+}
+
+/// 把预乘 alpha 的 `src` 叠加到同样预乘的 `dst` 上，左上角位于 `(x, y)`。
+pub fn over(dst: &mut RgbaImage, src: &RgbaImage, x: i64, y: i64) {
+    let (dst_w, dst_h) = (dst.width() as i64, dst.height() as i64);
+    for (sx, sy, s) in src.enumerate_pixels() {
+        let (dx, dy) = (x + sx as i64, y + sy as i64);
+        if dx < 0 || dy < 0 || dx >= dst_w || dy >= dst_h {
+            continue;
+        }
+        let d = dst.get_pixel_mut(dx as u32, dy as u32);
+        let rest = 255 - s[3] as u32;
+        for c in 0..4 {
+            d[c] = (s[c] as u32 + (d[c] as u32 * rest + 127) / 255).min(255) as u8;
+        }
+    }
+}
+
+/// 透明像素下方的底色，见模块文档。
+#[derive(Debug, Clone)]
+pub enum Matte {
+    Color(Rgba<u8>),
+    /// 从 `from` 渐变到 `to`，`horizontal` 为 `false` 时从上到下。
+    Gradient {
+        from: Rgba<u8>,
+        to: Rgba<u8>,
+        horizontal: bool,
+    },
+    Image(Arc<MatteImage>),
+}
+
+impl Default for Matte {
+    fn default() -> Matte {
+        Matte::Color(Rgba([0, 0, 0, 255]))
+    }
+}
+
+impl PartialEq for Matte {
+    fn eq(&self, other: &Matte) -> bool {
+        match (self, other) {
+            (Matte::Color(a), Matte::Color(b)) => a == b,
+            (
+                Matte::Gradient {
+                    from: a,
+                    to: b,
+                    horizontal: h,
+                },
+                Matte::Gradient {
+                    from: c,
+                    to: d,
+                    horizontal: k,
+                },
+            ) => (a, b, h) == (c, d, k),
+            (Matte::Image(a), Matte::Image(b)) => a.checksum == b.checksum,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Matte {}
+
+/// 用于缓存键和日志的简短描述。
+impl fmt::Display for Matte {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |Rgba([r, g, b, _]): &Rgba<u8>| format!("#{:02x}{:02x}{:02x}", r, g, b);
+        match self {
+            Matte::Color(color) => write!(f, "{}", hex(color)),
+            Matte::Gradient {
+                from,
+                to,
+                horizontal,
+            } => {
+                let direction = if *horizontal {
+                    "horizontal"
+                } else {
+                    "vertical"
+                };
+                write!(f, "gradient:{},{},{}", hex(from), hex(to), direction)
+            }
+            Matte::Image(image) => write!(f, "image:{:08x}", image.checksum),
+        }
+    }
+}
+
+impl FromStr for Matte {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Matte> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix("image:") {
+            return Ok(Matte::Image(Arc::new(MatteImage::load(path.trim())?)));
+        }
+        let Some(spec) = s.strip_prefix("gradient:") else {
+            return Ok(Matte::Color(parse_color(s)?));
+        };
+        let fields: Vec<&str> = spec.split(',').map(str::trim).collect();
+        if !(2..=3).contains(&fields.len()) {
+            bail!("渐变格式应为 gradient:#起始色,#结束色[,horizontal]: {}", s);
+        }
+        let horizontal = match fields.get(2).map(|d| d.to_ascii_lowercase()).as_deref() {
+            None | Some("vertical") => false,
+            Some("horizontal") => true,
+            Some(other) => bail!("未知的渐变方向: {}（可选 vertical、horizontal）", other),
+        };
+        Ok(Matte::Gradient {
+            from: parse_color(fields[0])?,
+            to: parse_color(fields[1])?,
+            horizontal,
+        })
+    }
+}
+
+impl Matte {
+    /// 把预乘 alpha 的画面铺到底色上，结果完全不透明。
+    pub fn flatten(&self, canvas: &mut RgbaImage) {
+        let layer = self.layer(canvas.width(), canvas.height());
+        for (pixel, under) in canvas.pixels_mut().zip(layer.pixels()) {
+            let rest = 255 - pixel[3] as u32;
+            for c in 0..3 {
+                pixel[c] = (pixel[c] as u32 + (under[c] as u32 * rest + 127) / 255).min(255) as u8;
+            }
+            pixel[3] = 255;
+        }
+    }
+
+    /// `width` x `height` 的底色画面。
+    fn layer(&self, width: u32, height: u32) -> Arc<RgbaImage> {
+        match self {
+            Matte::Color(color) => Arc::new(RgbaImage::from_pixel(width, height, *color)),
+            Matte::Gradient {
+                from,
+                to,
+                horizontal,
+            } => {
+                let span = if *horizontal { width } else { height }
+                    .saturating_sub(1)
+                    .max(1);
+                Arc::new(RgbaImage::from_fn(width, height, |x, y| {
+                    let t = if *horizontal { x } else { y } as f32 / span as f32;
+                    let mix = |c: usize| {
+                        (from[c] as f32 + (to[c] as f32 - from[c] as f32) * t).round() as u8
+                    };
+                    Rgba([mix(0), mix(1), mix(2), 255])
+                }))
+            }
+            Matte::Image(image) => image.scaled(width, height),
+        }
+    }
+}
+
+/// 作为底色的图片，按屏幕尺寸缩放后的结果会被保留。
+#[derive(Debug)]
+pub struct MatteImage {
+    pub path: PathBuf,
+    image: RgbaImage,
+    /// 像素的校验和，图片内容变化后缓存随之失效。
+    checksum: u32,
+    scaled: Mutex<Option<Arc<RgbaImage>>>,
+}
+
+impl MatteImage {
+    pub fn load(path: impl Into<PathBuf>) -> Result<MatteImage> {
+        let path = path.into();
+        let image = ImageReader::open(&path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(anyhow::Error::from)
+            .and_then(|reader| Ok(reader.decode()?))
+            .with_context(|| format!("无法加载底色图片 {}", path.display()))?
+            .to_rgba8();
+        // 底色本身的透明部分按黑色处理
+        let mut image = premultiply(&image);
+        for pixel in image.pixels_mut() {
+            pixel[3] = 255;
+        }
+        let checksum = crc32fast::hash(image.as_raw());
+        Ok(MatteImage {
+            path,
+            image,
+            checksum,
+            scaled: Mutex::new(None),
+        })
+    }
+
+    /// 按 `cover` 方式铺满 `width` x `height`。
+    fn scaled(&self, width: u32, height: u32) -> Arc<RgbaImage> {
+        let mut scaled = self.scaled.lock().unwrap();
+        if let Some(image) = scaled
+            .as_ref()
+            .filter(|i| i.dimensions() == (width, height))
+        {
+            return image.clone();
+        }
+        let size = (width, height);
+        let image = Arc::new(cover(
+            &self.image,
+            size,
+            FilterType::Triangle,
+            Gravity::Center,
+        ));
+        *scaled = Some(image.clone());
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::imageops;
+
+    fn pixel(image: &RgbaImage, x: u32, y: u32) -> [u8; 4] {
+        image.get_pixel(x, y).0
+    }
+
+    #[test]
+    fn premultiplies_with_rounding() {
+        let image = RgbaImage::from_fn(3, 1, |x, _| {
+            [
+                Rgba([200, 100, 50, 128]),
+                Rgba([255, 0, 255, 0]),
+                Rgba([1, 2, 3, 255]),
+            ][x as usize]
+        });
+        let out = premultiply(&image);
+        assert_eq!(pixel(&out, 0, 0), [100, 50, 25, 128]);
+        assert_eq!(pixel(&out, 1, 0), [0, 0, 0, 0]);
+        assert_eq!(pixel(&out, 2, 0), [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn blends_half_alpha_over_solid_matte() {
+        // 50% 的红色铺在蓝色上：红 128，蓝 255 * 127 / 255 ≈ 127
+        let mut canvas = premultiply(&RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 128])));
+        Matte::Color(Rgba([0, 0, 255, 255])).flatten(&mut canvas);
+        assert_eq!(pixel(&canvas, 0, 0), [128, 0, 127, 255]);
+
+        // over 与 flatten 的算法一致，alpha 也按同样方式合成
+        let mut dst = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 255, 255]));
+        let src = premultiply(&RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 128])));
+        over(&mut dst, &src, 1, 1);
+        assert_eq!(pixel(&dst, 1, 1), [128, 0, 127, 255]);
+        assert_eq!(pixel(&dst, 0, 0), [0, 0, 255, 255]);
+        // 超出画面的部分被裁掉
+        over(&mut dst, &src, -1, 0);
+        over(&mut dst, &src, 2, 2);
+        assert_eq!(pixel(&dst, 0, 0), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn transparent_pixels_show_matte_without_bleed() {
+        // 透明像素下残留着品红，缩放后也不能渗到相邻的像素里
+        let image = RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([0, 255, 0, 255])
+            } else {
+                Rgba([255, 0, 255, 0])
+            }
+        });
+        let matte = Matte::Color(Rgba([10, 20, 30, 255]));
+
+        let mut canvas = premultiply(&image);
+        matte.flatten(&mut canvas);
+        assert_eq!(pixel(&canvas, 1, 0), [10, 20, 30, 255]);
+        assert_eq!(pixel(&canvas, 0, 0), [0, 255, 0, 255]);
+
+        let mut scaled = imageops::resize(&premultiply(&image), 8, 1, FilterType::Triangle);
+        matte.flatten(&mut scaled);
+        for (x, _, p) in scaled.enumerate_pixels() {
+            // 只可能是绿色与底色的混合，红、蓝不超过底色
+            assert!(p[0] <= 10 && p[2] <= 30, "x={} {:?}", x, p.0);
+        }
+        assert_eq!(pixel(&scaled, 7, 0), [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn opaque_images_pass_through() {
+        let image = RgbaImage::from_fn(7, 5, |x, y| {
+            Rgba([(x * 37) as u8, (y * 51) as u8, (x * y) as u8, 255])
+        });
+        assert!(is_opaque(&image));
+        let mut canvas = premultiply(&image);
+        assert_eq!(canvas, image);
+        Matte::Color(Rgba([255, 0, 255, 255])).flatten(&mut canvas);
+        assert_eq!(canvas, image);
+
+        let mut translucent = image.clone();
+        translucent.get_pixel_mut(3, 3)[3] = 254;
+        assert!(!is_opaque(&translucent));
+    }
+
+    #[test]
+    fn samples_gradient_by_position() {
+        let transparent = || RgbaImage::new(5, 5);
+        let vertical: Matte = "gradient:#000,#fff".parse().unwrap();
+        let mut canvas = transparent();
+        vertical.flatten(&mut canvas);
+        for (y, level) in [0, 64, 128, 191, 255].into_iter().enumerate() {
+            assert_eq!(pixel(&canvas, 0, y as u32), [level, level, level, 255]);
+            assert_eq!(pixel(&canvas, 4, y as u32), [level, level, level, 255]);
+        }
+
+        let horizontal: Matte = "gradient:#ff0000, #0000ff, Horizontal".parse().unwrap();
+        let mut canvas = transparent();
+        horizontal.flatten(&mut canvas);
+        assert_eq!(pixel(&canvas, 0, 4), [255, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 2, 0), [128, 0, 128, 255]);
+        assert_eq!(pixel(&canvas, 4, 2), [0, 0, 255, 255]);
+        assert_eq!(
+            horizontal.to_string(),
+            "gradient:#ff0000,#0000ff,horizontal"
+        );
+
+        // 一行或一列的画面不会除以零
+        let mut line = RgbaImage::new(3, 1);
+        vertical.flatten(&mut line);
+        assert_eq!(pixel(&line, 1, 0), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn image_matte_covers_canvas() {
+        let path =
+            std::env::temp_dir().join(format!("usb-screen-matte-{}.png", std::process::id()));
+        // 16x8：左半红、右半蓝，铺满 8x8 时裁掉两边各 4 列
+        RgbaImage::from_fn(16, 8, |x, _| {
+            if x < 8 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        })
+        .save(&path)
+        .unwrap();
+        let matte: Matte = format!("image:{}", path.display()).parse().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut canvas = RgbaImage::new(8, 8);
+        matte.flatten(&mut canvas);
+        for y in [0, 7] {
+            assert_eq!(pixel(&canvas, 0, y), [255, 0, 0, 255]);
+            assert_eq!(pixel(&canvas, 2, y), [255, 0, 0, 255]);
+            assert_eq!(pixel(&canvas, 5, y), [0, 0, 255, 255]);
+            assert_eq!(pixel(&canvas, 7, y), [0, 0, 255, 255]);
+        }
+        // 同一尺寸复用缩放结果
+        let Matte::Image(image) = &matte else {
+            unreachable!()
+        };
+        assert!(Arc::ptr_eq(&image.scaled(8, 8), &image.scaled(8, 8)));
+        assert_eq!(image.scaled(4, 2).dimensions(), (4, 2));
+    }
+
+    #[test]
+    fn parses_mattes() {
+        assert_eq!(
+            "white".parse::<Matte>().unwrap(),
+            Matte::Color(Rgba([255; 4]))
+        );
+        assert_eq!(Matte::default().to_string(), "#000000");
+        assert!("gradient:#000".parse::<Matte>().is_err());
+        assert!("gradient:#000,#fff,diagonal".parse::<Matte>().is_err());
+        assert!("image:/nonexistent/matte.png".parse::<Matte>().is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! 动画图片：GIF、APNG 和动态 WebP。
+//!
+//! 加载分为两步：[`decode_media`] 解码出原始尺寸的 RGBA 帧，
+//! [`Decoded::convert`] 缩放并编码为屏幕的像素格式，两步可以放在不同线程里。
+//! 所有帧一次性转换好，播放时直接发送，帧间隔使用文件中记录的延时。
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::metadata::LoopCount;
+use image::{AnimationDecoder, Frames, ImageFormat, ImageReader, RgbaImage};
+
+use crate::format::PixelFormat;
+use crate::render::{RenderOptions, Target};
+
+/// 浏览器的通行做法：不超过 10ms 的帧延时按 100ms 处理，否则很多 GIF 会快得不正常。
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(10);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// 一帧已编码好的画面。
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    pub pixels: Vec<u8>,
+    pub delay: Duration,
+}
+
+/// 解码后的动画。
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub frames: Vec<AnimationFrame>,
+    /// 播放次数，`None` 表示无限循环。
+    pub loop_count: Option<u32>,
+}
+
+impl Animation {
+    /// 播放一遍的总时长。
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|f| f.delay).sum()
+    }
+}
+
+/// 一个播放列表条目加载后的内容，已编码为可直接发送的显存数据。
+#[derive(Debug, Clone)]
+pub enum Media {
+    Still {
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        pixels: Vec<u8>,
+    },
+    Animated(Animation),
+}
+
+impl Media {
+    /// 画面尺寸 `(宽, 高)`。
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Media::Still { width, height, .. } => (*width, *height),
+            Media::Animated(animation) => (animation.width, animation.height),
+        }
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        match self {
+            Media::Still { format, .. } => *format,
+            Media::Animated(animation) => animation.format,
+        }
+    }
+
+    /// 占用的内存字节数。
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            Media::Still { pixels, .. } => pixels.len(),
+            Media::Animated(animation) => animation.frames.iter().map(|f| f.pixels.len()).sum(),
+        }
+    }
+}
+
+/// 解码得到的原始尺寸画面，尚未缩放。
+pub struct Decoded {
+    /// 每帧画面及其延时；静态图片只有一帧，延时为零。
+    pub frames: Vec<(RgbaImage, Duration)>,
+    pub loop_count: Option<u32>,
+    pub animated: bool,
+}
+
+impl Decoded {
+    /// 按 `render` 渲染为 `target` 的尺寸并编码。
+    pub fn convert(self, target: &Target, render: &RenderOptions) -> Media {
+        let (width, height) = target.size();
+        let format = target.encoder.format();
+        let mut frames: Vec<AnimationFrame> = self
+            .frames
+            .into_iter()
+            .map(|(image, delay)| AnimationFrame {
+                pixels: target.encode(&render.render(&image, target)),
+                delay,
+            })
+            .collect();
+        if self.animated {
+            Media::Animated(Animation {
+                width,
+                height,
+                format,
+                frames,
+                loop_count: self.loop_count,
+            })
+        } else {
+            Media::Still {
+                width,
+                height,
+                format,
+                pixels: frames.remove(0).pixels,
+            }
+        }
+    }
+}
+
+/// 加载图片；多帧的 GIF、APNG 和 WebP 作为动画返回，其余作为静态图片。
+pub fn load_media(
+    path: impl AsRef<Path>,
+    target: &Target,
+    render: &RenderOptions,
+) -> Result<Media> {
+    Ok(decode_media(path)?.convert(target, render))
+}
+
+/// 解码图片的所有帧，不缩放。
+pub fn decode_media(path: impl AsRef<Path>) -> Result<Decoded> {
+    let path = path.as_ref();
+    if let Some((frames, loop_count)) = decode_animation(path)? {
+        return Ok(Decoded {
+            frames,
+            loop_count,
+            animated: true,
+        });
+    }
+    let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+    Ok(Decoded {
+        frames: vec![(image.to_rgba8(), Duration::ZERO)],
+        loop_count: None,
+        animated: false,
+    })
+}
+
+type DecodedFrames = (Vec<(RgbaImage, Duration)>, Option<u32>);
+
+/// 按动画解码，文件不是动画或只有一帧时返回 `None`。
+fn decode_animation(path: &Path) -> Result<Option<DecodedFrames>> {
+    let format = ImageReader::open(path)?.with_guessed_format()?.format();
+    let reader = || -> Result<BufReader<File>> { Ok(BufReader::new(File::open(path)?)) };
+    let (frames, loop_count) = match format {
+        Some(ImageFormat::Gif) => into_frames(GifDecoder::new(reader()?)?),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader()?)?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            into_frames(decoder.apng()?)
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader()?)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            into_frames(decoder)
+        }
+        _ => return Ok(None),
+    };
+
+    let frames = frames
+        .collect_frames()
+        .with_context(|| format!("无法解码动画 {}", path.display()))?;
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+    let frames = frames
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay = Duration::from_micros(numer as u64 * 1000 / denom.max(1) as u64);
+            (frame.into_buffer(), frame_delay(delay))
+        })
+        .collect();
+    Ok(Some((frames, loop_count)))
+}
+
+/// 按 [`MIN_FRAME_DELAY`] 修正文件中记录的帧延时。
+fn frame_delay(delay: Duration) -> Duration {
+    if delay <= MIN_FRAME_DELAY {
+        DEFAULT_FRAME_DELAY
+    } else {
+        delay
+    }
+}
+
+fn into_frames<'a>(decoder: impl AnimationDecoder<'a>) -> (Frames<'a>, Option<u32>) {
+    let loop_count = match decoder.loop_count() {
+        LoopCount::Infinite => None,
+        LoopCount::Finite(n) => Some(n.get()),
+    };
+    (decoder.into_frames(), loop_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, Frame, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn clamps_short_delays_like_browsers() {
+        assert_eq!(frame_delay(Duration::ZERO), DEFAULT_FRAME_DELAY);
+        assert_eq!(frame_delay(Duration::from_millis(10)), DEFAULT_FRAME_DELAY);
+        assert_eq!(
+            frame_delay(Duration::from_millis(20)),
+            Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn decodes_gif_timing_and_loops() {
+        let path = std::env::temp_dir().join(format!("usb-screen-anim-{}.gif", std::process::id()));
+        {
+            let mut encoder = GifEncoder::new(File::create(&path).unwrap());
+            encoder.set_repeat(Repeat::Finite(3)).unwrap();
+            for (i, ms) in [0, 10, 50].into_iter().enumerate() {
+                let image = RgbaImage::from_pixel(4, 4, Rgba([i as u8 * 100, 0, 0, 255]));
+                let delay = Delay::from_numer_denom_ms(ms, 1);
+                encoder
+                    .encode_frame(Frame::from_parts(image, 0, 0, delay))
+                    .unwrap();
+            }
+        }
+        let decoded = decode_media(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(decoded.animated);
+        assert_eq!(decoded.loop_count, Some(3));
+        let delays: Vec<u64> = decoded
+            .frames
+            .iter()
+            .map(|(_, d)| d.as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [100, 100, 50]);
+        assert_eq!(decoded.frames[2].0.get_pixel(0, 0)[0], 200);
+    }
+}
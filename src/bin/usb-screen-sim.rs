@@ -0,0 +1,295 @@
+//! RP2040 屏幕模拟器：创建一个伪终端，解析主机发来的数据包并把画面还原成 PNG。
+//!
+//! 用法：`usb-screen-sim [--out 目录] [--all] [--link 路径] [--size 宽x高] [--format 格式,...]`
+//!
+//! 启动后打印伪终端路径，主机端用 `USB_SCREEN_PORT=<路径>` 连接即可。
+//! `--format` 限制握手时报告的像素格式，默认支持全部格式。
+
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    sim::run()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("模拟器仅支持 Linux"))
+}
+
+#[cfg(target_os = "linux")]
+mod sim {
+    use std::ffi::CStr;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::os::fd::{FromRawFd, OwnedFd};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use log::{info, warn};
+    use usb_screen::handshake::DeviceInfo;
+    use usb_screen::protocol::{
+        decode_rect, Command, DecoderStats, Packet, PacketDecoder, FLAG_FRAME_END,
+    };
+    use usb_screen::{dirty, Encoder, Palette, PixelFormat, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    /// 模拟的屏幕显存。
+    struct Framebuffer {
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        pixels: Vec<u8>,
+    }
+
+    impl Framebuffer {
+        fn new(width: u32, height: u32, format: PixelFormat) -> Framebuffer {
+            Framebuffer {
+                width,
+                height,
+                format,
+                pixels: vec![0; format.frame_len(width, height)],
+            }
+        }
+    }
+
+    struct Options {
+        out_dir: PathBuf,
+        keep_all: bool,
+        link: Option<PathBuf>,
+        width: u32,
+        height: u32,
+        /// 握手时报告的格式位图。
+        formats: u16,
+    }
+
+    fn parse_args() -> Result<Options> {
+        let mut options = Options {
+            out_dir: PathBuf::from("./sim-output"),
+            keep_all: false,
+            link: None,
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            formats: PixelFormat::ALL
+                .iter()
+                .fold(0, |bits, f| bits | 1 << f.id()),
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => options.out_dir = args.next().context("--out 缺少参数")?.into(),
+                "--all" => options.keep_all = true,
+                "--link" => options.link = Some(args.next().context("--link 缺少参数")?.into()),
+                "--size" => {
+                    let size = args.next().context("--size 缺少参数")?;
+                    let (w, h) = size
+                        .split_once('x')
+                        .ok_or_else(|| anyhow!("--size 格式应为 宽x高: {}", size))?;
+                    options.width = w.parse()?;
+                    options.height = h.parse()?;
+                }
+                "--format" => {
+                    let list = args.next().context("--format 缺少参数")?;
+                    options.formats = 0;
+                    for name in list.split(',') {
+                        let format: PixelFormat = name.parse()?;
+                        options.formats |= 1 << format.id();
+                    }
+                }
+                other => bail!("未知参数: {}", other),
+            }
+        }
+        Ok(options)
+    }
+
+    /// 打开伪终端主端，返回主端文件、从端路径以及保持打开的从端句柄。
+    ///
+    /// 从端句柄一直持有，这样主机断开重连时主端读取不会收到 EIO。
+    fn open_pty() -> Result<(File, String, OwnedFd)> {
+        unsafe {
+            let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            if master < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let master_file = File::from_raw_fd(master);
+            if libc::grantpt(master) != 0 || libc::unlockpt(master) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let mut name = [0 as libc::c_char; 128];
+            if libc::ptsname_r(master, name.as_mut_ptr(), name.len()) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let path = CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+
+            let slave = libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY);
+            if slave < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let slave = OwnedFd::from_raw_fd(slave);
+
+            // 原始模式，避免行规程改写 0x0D/0x0A 等字节
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(master, &mut termios) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(master, libc::TCSANOW, &termios) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok((master_file, path, slave))
+        }
+    }
+
+    pub fn run() -> Result<()> {
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+        let options = parse_args()?;
+        fs::create_dir_all(&options.out_dir)?;
+
+        let (mut master, path, _slave) = open_pty()?;
+        if let Some(link) = &options.link {
+            let _ = fs::remove_file(link);
+            std::os::unix::fs::symlink(&path, link)?;
+            info!("伪终端链接: {} -> {}", link.display(), path);
+        }
+        info!("模拟器已启动，伪终端: {}", path);
+        println!("{}", path);
+
+        let mut framebuffer = Framebuffer::new(0, 0, PixelFormat::default());
+        let mut palette = Arc::new(Palette::default());
+        let mut frames = 0u64;
+        let mut decoder = PacketDecoder::new();
+        let mut reported = DecoderStats::default();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = master.read(&mut buf)?;
+            if n == 0 {
+                continue;
+            }
+            decoder.push(&buf[..n]);
+            while let Some(packet) = decoder.next_packet() {
+                if packet.header.command == Command::Hello {
+                    reply_hello(&mut master, &options)?;
+                    continue;
+                }
+                if packet.header.command == Command::Palette {
+                    match packet
+                        .decoded_payload()
+                        .and_then(|p| Palette::from_bytes(&p))
+                    {
+                        Ok(received) => {
+                            info!("收到调色板: {} 种颜色", received.colors().len());
+                            palette = Arc::new(received);
+                        }
+                        Err(err) => warn!("调色板无效: {:?}", err),
+                    }
+                    continue;
+                }
+                if let Err(err) = apply_packet(&mut framebuffer, &packet) {
+                    warn!("序号 {} 的包无法显示: {:?}", packet.header.seq, err);
+                    continue;
+                }
+                if packet.header.flags & FLAG_FRAME_END != 0 {
+                    frames += 1;
+                    save_frame(&options, &framebuffer, &palette, frames)?;
+                }
+            }
+            let stats = decoder.stats();
+            if stats.crc_errors != reported.crc_errors
+                || stats.lost_packets != reported.lost_packets
+            {
+                warn!(
+                    "传输错误: CRC 失败 {} 次，丢包 {} 个，丢弃 {} 字节",
+                    stats.crc_errors, stats.lost_packets, stats.skipped_bytes
+                );
+            }
+            reported = stats;
+        }
+    }
+
+    /// 按模拟的面板参数应答握手。
+    fn reply_hello(master: &mut File, options: &Options) -> Result<()> {
+        let info = DeviceInfo {
+            width: options.width,
+            height: options.height,
+            formats: options.formats,
+            lz4: true,
+            partial_update: true,
+            firmware: (
+                env!("CARGO_PKG_VERSION_MAJOR").parse()?,
+                env!("CARGO_PKG_VERSION_MINOR").parse()?,
+                env!("CARGO_PKG_VERSION_PATCH").parse()?,
+            ),
+        };
+        let reply = Packet::new(
+            Command::HelloReply,
+            info.width as u16,
+            info.height as u16,
+            0,
+            info.encode(),
+        );
+        master.write_all(&reply.encode())?;
+        info!("已应答握手: {}", info);
+        Ok(())
+    }
+
+    /// 把一个包的内容写入显存。
+    fn apply_packet(framebuffer: &mut Framebuffer, packet: &Packet) -> Result<()> {
+        let (width, height) = (packet.header.width as u32, packet.header.height as u32);
+        let format = PixelFormat::from_id(packet.header.format)
+            .ok_or_else(|| anyhow!("未知的像素格式: {}", packet.header.format))?;
+        if (framebuffer.width, framebuffer.height, framebuffer.format) != (width, height, format) {
+            *framebuffer = Framebuffer::new(width, height, format);
+        }
+        let payload = packet.decoded_payload()?;
+        match packet.header.command {
+            Command::Frame => {
+                if payload.len() != framebuffer.pixels.len() {
+                    bail!("整帧长度异常: {}", payload.len());
+                }
+                framebuffer.pixels.copy_from_slice(&payload);
+            }
+            Command::PartialFrame => {
+                let bytes_per_pixel = format
+                    .bytes_per_pixel()
+                    .ok_or_else(|| anyhow!("{} 格式不支持局部更新", format))?;
+                let (rect, pixels) = decode_rect(&payload)?;
+                if rect.x + rect.width > width
+                    || rect.y + rect.height > height
+                    || pixels.len() as u64 != rect.area() * bytes_per_pixel as u64
+                {
+                    bail!("局部更新越界: {:?}", rect);
+                }
+                dirty::blit(
+                    &mut framebuffer.pixels,
+                    width,
+                    bytes_per_pixel,
+                    &rect,
+                    pixels,
+                );
+            }
+            Command::Hello | Command::HelloReply | Command::Palette => {}
+        }
+        Ok(())
+    }
+
+    fn save_frame(
+        options: &Options,
+        framebuffer: &Framebuffer,
+        palette: &Arc<Palette>,
+        count: u64,
+    ) -> Result<()> {
+        let encoder = Encoder::new(framebuffer.format, palette.clone());
+        let Some(image) =
+            encoder.decode(&framebuffer.pixels, framebuffer.width, framebuffer.height)
+        else {
+            bail!("显存尺寸异常");
+        };
+        if options.keep_all {
+            image.save(options.out_dir.join(format!("frame-{:06}.png", count)))?;
+        }
+        // 先写临时文件再改名，读取方不会看到写了一半的 latest.png
+        let tmp = options.out_dir.join(".latest.png.tmp");
+        image.save_with_format(&tmp, image::ImageFormat::Png)?;
+        fs::rename(&tmp, options.out_dir.join("latest.png"))?;
+        info!("收到第 {} 帧", count);
+        Ok(())
+    }
+}
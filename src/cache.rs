@@ -0,0 +1,573 @@
+//! 解码结果缓存。
+//!
+//! 播放列表每轮都会用到同一批图片，解码和缩放比发送还慢。缓存以文件路径、
+//! 修改时间、文件大小、目标尺寸、像素格式和渲染参数为键，保存可直接发送的显存数据；
+//! 内存占用超出预算时淘汰最久未用的条目。
+//!
+//! 开启持久化后每个条目还会写入缓存目录（LZ4 压缩），重启后无需重新解码。
+//! 磁盘文件名只取决于路径和渲染参数，图片修改后旧文件会被覆盖。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+
+use crate::animation::{load_media, Animation, AnimationFrame, Media};
+use crate::compress::{compress_lz4, decompress_lz4};
+use crate::format::PixelFormat;
+use crate::render::{RenderOptions, Target};
+
+/// 默认内存预算。
+pub const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+const DISK_MAGIC: &[u8; 4] = b"USCC";
+const DISK_VERSION: u8 = 1;
+const KIND_STILL: u8 = 0;
+const KIND_ANIMATED: u8 = 1;
+
+/// 临时文件编号，多个线程同时写同一条目时互不干扰。
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// 缓存设置。
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// 内存预算（字节），为 0 时不在内存中缓存。
+    pub memory_budget: usize,
+    /// 持久化目录，`None` 表示不写磁盘。
+    pub disk_dir: Option<PathBuf>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> CacheConfig {
+        CacheConfig {
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            disk_dir: None,
+        }
+    }
+}
+
+/// 命中统计。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "命中 {}，磁盘命中 {}，解码 {}，{} 项共 {:.1} MiB",
+            self.hits,
+            self.disk_hits,
+            self.misses,
+            self.entries,
+            self.bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+/// 缓存键：渲染参数加上文件的修改时间和大小。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    /// 编码和渲染参数的描述。
+    render: String,
+    mtime: Duration,
+    len: u64,
+}
+
+impl Key {
+    fn new(path: &Path, target: &Target, render: &RenderOptions) -> Result<Key> {
+        let metadata =
+            fs::metadata(path).with_context(|| format!("无法读取 {}", path.display()))?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(Key {
+            path: path.to_path_buf(),
+            width: target.width,
+            height: target.height,
+            format: target.encoder.format(),
+            render: format!(
+                "{}-{}-{}-{}",
+                target.encoder.id(),
+                target.orientation,
+                target.calibration,
+                render
+            ),
+            mtime,
+            len: metadata.len(),
+        })
+    }
+
+    /// 磁盘文件名，不含修改时间，图片更新后覆盖旧文件。
+    fn file_name(&self) -> String {
+        format!(
+            "{:08x}-{}x{}-{:08x}.bin",
+            crc32fast::hash(self.path.to_string_lossy().as_bytes()),
+            self.width,
+            self.height,
+            crc32fast::hash(self.render.as_bytes())
+        )
+    }
+
+    /// 写在磁盘文件开头，读取时逐项核对。
+    fn describe(&self) -> String {
+        format!(
+            "{}\n{}x{}\n{}\n{}.{:09}\n{}",
+            self.path.display(),
+            self.width,
+            self.height,
+            self.render,
+            self.mtime.as_secs(),
+            self.mtime.subsec_nanos(),
+            self.len
+        )
+    }
+}
+
+struct Entry {
+    media: Arc<Media>,
+    bytes: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Key, Entry>,
+    bytes: usize,
+    tick: u64,
+    stats: CacheStats,
+}
+
+/// 多个播放线程共享的解码缓存。
+pub struct FrameCache {
+    config: CacheConfig,
+    inner: Mutex<Inner>,
+}
+
+impl FrameCache {
+    pub fn new(config: CacheConfig) -> FrameCache {
+        if let Some(dir) = &config.disk_dir {
+            if let Err(err) = fs::create_dir_all(dir) {
+                warn!("无法创建缓存目录 {}: {}", dir.display(), err);
+            }
+        }
+        FrameCache {
+            config,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// 取出图片的解码结果，未缓存时解码并放入缓存。
+    pub fn load(
+        &self,
+        path: impl AsRef<Path>,
+        target: &Target,
+        render: &RenderOptions,
+    ) -> Result<Arc<Media>> {
+        let path = path.as_ref();
+        if let Some(media) = self.get(path, target, render)? {
+            return Ok(media);
+        }
+        let media = load_media(path, target, render)?;
+        self.put(path, target, render, media)
+    }
+
+    /// 只查询缓存（内存和磁盘），不解码。
+    pub fn get(
+        &self,
+        path: impl AsRef<Path>,
+        target: &Target,
+        render: &RenderOptions,
+    ) -> Result<Option<Arc<Media>>> {
+        let key = Key::new(path.as_ref(), target, render)?;
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.tick += 1;
+            let tick = inner.tick;
+            if let Some(entry) = inner.entries.get_mut(&key) {
+                entry.last_used = tick;
+                let media = entry.media.clone();
+                inner.stats.hits += 1;
+                return Ok(Some(media));
+            }
+        }
+        // 读磁盘不持有锁，其他线程可以同时读取缓存
+        let Some(media) = self.read_disk(&key) else {
+            return Ok(None);
+        };
+        self.inner.lock().unwrap().stats.disk_hits += 1;
+        let media = Arc::new(media);
+        self.insert(key, media.clone());
+        Ok(Some(media))
+    }
+
+    /// 放入新解码的结果，开启持久化时同时写入磁盘。
+    pub fn put(
+        &self,
+        path: impl AsRef<Path>,
+        target: &Target,
+        render: &RenderOptions,
+        media: Media,
+    ) -> Result<Arc<Media>> {
+        let key = Key::new(path.as_ref(), target, render)?;
+        self.inner.lock().unwrap().stats.misses += 1;
+        self.write_disk(&key, &media);
+        let media = Arc::new(media);
+        self.insert(key, media.clone());
+        Ok(media)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            entries: inner.entries.len(),
+            bytes: inner.bytes,
+            ..inner.stats
+        }
+    }
+
+    fn insert(&self, key: Key, media: Arc<Media>) {
+        let bytes = media.size_bytes();
+        if bytes > self.config.memory_budget {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        while inner.bytes + bytes > self.config.memory_budget {
+            let Some(oldest) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = inner.entries.remove(&oldest) {
+                inner.bytes -= entry.bytes;
+                debug!("缓存淘汰 {}", oldest.path.display());
+            }
+        }
+        inner.tick += 1;
+        let entry = Entry {
+            media,
+            bytes,
+            last_used: inner.tick,
+        };
+        if let Some(old) = inner.entries.insert(key, entry) {
+            inner.bytes -= old.bytes;
+        }
+        inner.bytes += bytes;
+    }
+
+    fn read_disk(&self, key: &Key) -> Option<Media> {
+        let path = self.config.disk_dir.as_ref()?.join(key.file_name());
+        let file = File::open(&path).ok()?;
+        match read_entry(&mut BufReader::new(file), key) {
+            Ok(media) => media,
+            Err(err) => {
+                warn!("缓存文件 {} 已损坏: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    fn write_disk(&self, key: &Key, media: &Media) {
+        let Some(dir) = &self.config.disk_dir else {
+            return;
+        };
+        let path = dir.join(key.file_name());
+        // 先写临时文件再改名，中途退出不会留下半个文件
+        let tmp = path.with_extension(format!(
+            "{}.tmp",
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = File::create(&tmp)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| write_entry(&mut BufWriter::new(file), key, media))
+            .and_then(|()| fs::rename(&tmp, &path).map_err(anyhow::Error::from));
+        if let Err(err) = result {
+            warn!("无法写入缓存文件 {}: {}", path.display(), err);
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
+
+/// 磁盘格式：魔数、版本、键描述，然后是类型、播放次数（0 为无限）、
+/// 帧数以及每帧的延时（毫秒）和 LZ4 压缩后的数据，整数均为大端。
+fn write_entry(out: &mut impl Write, key: &Key, media: &Media) -> Result<()> {
+    out.write_all(DISK_MAGIC)?;
+    out.write_all(&[DISK_VERSION])?;
+    write_bytes(out, key.describe().as_bytes())?;
+    match media {
+        Media::Still { pixels, .. } => {
+            out.write_all(&[KIND_STILL])?;
+            out.write_all(&0u32.to_be_bytes())?;
+            out.write_all(&1u32.to_be_bytes())?;
+            out.write_all(&0u32.to_be_bytes())?;
+            write_bytes(out, &compress_lz4(pixels))?;
+        }
+        Media::Animated(animation) => {
+            out.write_all(&[KIND_ANIMATED])?;
+            out.write_all(&animation.loop_count.unwrap_or(0).to_be_bytes())?;
+            out.write_all(&(animation.frames.len() as u32).to_be_bytes())?;
+            for frame in &animation.frames {
+                out.write_all(&(frame.delay.as_millis() as u32).to_be_bytes())?;
+                write_bytes(out, &compress_lz4(&frame.pixels))?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// 读取磁盘条目，键不一致（图片已修改）时返回 `None`。
+fn read_entry(input: &mut impl Read, key: &Key) -> Result<Option<Media>> {
+    let mut magic = [0u8; 5];
+    input.read_exact(&mut magic)?;
+    if &magic[..4] != DISK_MAGIC || magic[4] != DISK_VERSION {
+        bail!("文件头不符");
+    }
+    if read_bytes(input)? != key.describe().as_bytes() {
+        return Ok(None);
+    }
+    let mut kind = [0u8; 1];
+    input.read_exact(&mut kind)?;
+    let loop_count = read_u32(input)?;
+    let count = read_u32(input)?;
+    let frame_len = key.format.frame_len(key.width, key.height);
+    let mut frames = Vec::with_capacity(count.min(1024) as usize);
+    for _ in 0..count {
+        let delay = Duration::from_millis(read_u32(input)? as u64);
+        let pixels = decompress_lz4(&read_bytes(input)?)?;
+        if pixels.len() != frame_len {
+            bail!("帧长度 {} 与尺寸不符", pixels.len());
+        }
+        frames.push(AnimationFrame { pixels, delay });
+    }
+    match kind[0] {
+        KIND_STILL if frames.len() == 1 => Ok(Some(Media::Still {
+            width: key.width,
+            height: key.height,
+            format: key.format,
+            pixels: frames.remove(0).pixels,
+        })),
+        KIND_ANIMATED if !frames.is_empty() => Ok(Some(Media::Animated(Animation {
+            width: key.width,
+            height: key.height,
+            format: key.format,
+            frames,
+            loop_count: (loop_count != 0).then_some(loop_count),
+        }))),
+        other => bail!("未知的条目类型 {}", other),
+    }
+}
+
+fn write_bytes(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(data)?;
+    Ok(())
+}
+
+fn read_u32(input: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_bytes(input: &mut impl Read) -> Result<Vec<u8>> {
+    let len = read_u32(input)? as usize;
+    let mut data = Vec::new();
+    input.take(len as u64).read_to_end(&mut data)?;
+    if data.len() != len {
+        bail!("数据被截断");
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Encoder;
+
+    const WIDTH: u32 = 4;
+    const HEIGHT: u32 = 2;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("usb-screen-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn target() -> Target {
+        Target::new(WIDTH, HEIGHT, Encoder::default())
+    }
+
+    /// 缓存键只看文件元数据，内容无所谓。
+    fn source(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, name).unwrap();
+        path
+    }
+
+    fn still(value: u8) -> Media {
+        Media::Still {
+            width: WIDTH,
+            height: HEIGHT,
+            format: PixelFormat::Rgb565,
+            pixels: vec![value; PixelFormat::Rgb565.frame_len(WIDTH, HEIGHT)],
+        }
+    }
+
+    fn pixels(media: &Media) -> Vec<&[u8]> {
+        match media {
+            Media::Still { pixels, .. } => vec![pixels],
+            Media::Animated(a) => a.frames.iter().map(|f| &f.pixels[..]).collect(),
+        }
+    }
+
+    fn encoded(key: &Key, media: &Media) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_entry(&mut data, key, media).unwrap();
+        data
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let dir = temp_dir("round-trip");
+        let key = Key::new(&source(&dir, "a.png"), &target(), &RenderOptions::default()).unwrap();
+
+        let data = encoded(&key, &still(7));
+        let media = read_entry(&mut &data[..], &key).unwrap().unwrap();
+        assert!(matches!(
+            media,
+            Media::Still {
+                width: WIDTH,
+                height: HEIGHT,
+                ..
+            }
+        ));
+        assert_eq!(pixels(&media), pixels(&still(7)));
+
+        let frame_len = PixelFormat::Rgb565.frame_len(WIDTH, HEIGHT);
+        let animation = Media::Animated(Animation {
+            width: WIDTH,
+            height: HEIGHT,
+            format: PixelFormat::Rgb565,
+            frames: (0..3u8)
+                .map(|i| AnimationFrame {
+                    pixels: vec![i; frame_len],
+                    delay: Duration::from_millis(40 * (i as u64 + 1)),
+                })
+                .collect(),
+            loop_count: Some(2),
+        });
+        let data = encoded(&key, &animation);
+        let Some(Media::Animated(read)) = read_entry(&mut &data[..], &key).unwrap() else {
+            panic!("应读出动画");
+        };
+        assert_eq!(read.loop_count, Some(2));
+        let delays: Vec<u64> = read
+            .frames
+            .iter()
+            .map(|f| f.delay.as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [40, 80, 120]);
+        assert_eq!(pixels(&Media::Animated(read)), pixels(&animation));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_corrupted_entries() {
+        let dir = temp_dir("corrupted");
+        let key = Key::new(&source(&dir, "a.png"), &target(), &RenderOptions::default()).unwrap();
+        let data = encoded(&key, &still(7));
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] ^= 0xFF;
+        assert!(read_entry(&mut &bad_magic[..], &key).is_err());
+        let truncated = &data[..data.len() - 3];
+        assert!(read_entry(&mut &truncated[..], &key).is_err());
+        let mut bad_kind = data.clone();
+        let kind = 5 + 4 + key.describe().len();
+        bad_kind[kind] = 9;
+        assert!(read_entry(&mut &bad_kind[..], &key).is_err());
+
+        // 图片修改后键不一致，不是错误，只是不命中
+        let other = Key {
+            len: key.len + 1,
+            ..key.clone()
+        };
+        assert!(read_entry(&mut &data[..], &other).unwrap().is_none());
+
+        // 磁盘上损坏的文件当作未缓存处理
+        let cache = FrameCache::new(CacheConfig {
+            memory_budget: 0,
+            disk_dir: Some(dir.join("cache")),
+        });
+        cache
+            .put(&key.path, &target(), &RenderOptions::default(), still(7))
+            .unwrap();
+        let file = dir.join("cache").join(key.file_name());
+        assert!(cache
+            .get(&key.path, &target(), &RenderOptions::default())
+            .unwrap()
+            .is_some());
+        fs::write(&file, truncated).unwrap();
+        assert!(cache
+            .get(&key.path, &target(), &RenderOptions::default())
+            .unwrap()
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = temp_dir("lru");
+        let frame_len = PixelFormat::Rgb565.frame_len(WIDTH, HEIGHT);
+        let cache = FrameCache::new(CacheConfig {
+            memory_budget: frame_len * 2,
+            disk_dir: None,
+        });
+        let (target, render) = (target(), RenderOptions::default());
+        let [a, b, c] = ["a.png", "b.png", "c.png"].map(|name| source(&dir, name));
+        let cached = |path: &Path| cache.get(path, &target, &render).unwrap().is_some();
+
+        cache.put(&a, &target, &render, still(1)).unwrap();
+        cache.put(&b, &target, &render, still(2)).unwrap();
+        assert!(cached(&a));
+        cache.put(&c, &target, &render, still(3)).unwrap();
+        assert!(cached(&a) && cached(&c));
+        assert!(!cached(&b));
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes), (2, frame_len * 2));
+        assert_eq!((stats.hits, stats.misses), (3, 3));
+
+        // 超出预算的条目不进内存
+        let small = FrameCache::new(CacheConfig {
+            memory_budget: frame_len - 1,
+            disk_dir: None,
+        });
+        small.put(&a, &target, &render, still(1)).unwrap();
+        assert_eq!(small.stats().entries, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
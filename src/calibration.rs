@@ -0,0 +1,418 @@
+//! 面板的颜色校准。
+//!
+//! 廉价 TFT 的 gamma 和色偏各不相同，画面在抖动和编码之前按以下顺序校准：
+//!
+//! 1. 亮度、对比度：`(v - 0.5) * contrast + 0.5 + brightness`；
+//! 2. 饱和度：向 BT.601 亮度靠拢（小于 1）或远离（大于 1）；
+//! 3. 每通道增益、偏移：`v * gain + offset`；
+//! 4. 每通道 gamma：`v ^ (1 / gamma)`，大于 1 提亮中间调；
+//! 5. 可选的 3D 查找表（`.cube` 文件），三线性插值。
+//!
+//! 以上数值都以 0 到 1 表示颜色。调整时可以配合 `calibrate` 命令和
+//! [`crate::pattern::Pattern::Calibration`] 测试图案。
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context, Result};
+use image::RgbaImage;
+
+/// `.cube` 文件允许的最大边长。
+const MAX_LUT_SIZE: usize = 256;
+
+/// 一块面板的校准参数。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub gamma: [f32; 3],
+    pub gain: [f32; 3],
+    pub offset: [f32; 3],
+    pub brightness: f32,
+    pub contrast: f32,
+    pub saturation: f32,
+    pub lut: Option<Arc<CubeLut>>,
+}
+
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration {
+            gamma: [1.0; 3],
+            gain: [1.0; 3],
+            offset: [0.0; 3],
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            lut: None,
+        }
+    }
+}
+
+impl Calibration {
+    /// 不做任何修改时可以跳过整个阶段。
+    pub fn is_identity(&self) -> bool {
+        *self == Calibration::default()
+    }
+
+    /// 检查取值范围。
+    pub fn validate(&self) -> Result<()> {
+        for (name, values) in [("gamma", self.gamma), ("gain", self.gain)] {
+            if values.iter().any(|v| !v.is_finite() || *v <= 0.0) {
+                bail!("{} 应大于 0: {:?}", name, values);
+            }
+        }
+        if self.offset.iter().any(|v| !v.is_finite()) || !self.brightness.is_finite() {
+            bail!("offset、brightness 应为有限数值");
+        }
+        if !(self.contrast.is_finite() && self.contrast >= 0.0) {
+            bail!("contrast 不能为负数: {}", self.contrast);
+        }
+        if !(self.saturation.is_finite() && self.saturation >= 0.0) {
+            bail!("saturation 不能为负数: {}", self.saturation);
+        }
+        Ok(())
+    }
+
+    /// 按命令行方式修改一项参数，例如 `gamma 2.2` 或 `gain 1 0.95 0.9`。
+    ///
+    /// 供 `calibrate` 命令交互调整；`lut` 的参数为文件路径或 `none`。
+    pub fn set(&mut self, key: &str, args: &[&str]) -> Result<()> {
+        let numbers = || -> Result<Vec<f32>> {
+            args.iter()
+                .map(|a| {
+                    a.parse::<f32>()
+                        .with_context(|| format!("无效的数值: {}", a))
+                })
+                .collect()
+        };
+        let channels = || -> Result<[f32; 3]> {
+            match numbers()?.as_slice() {
+                [v] => Ok([*v; 3]),
+                [r, g, b] => Ok([*r, *g, *b]),
+                _ => bail!("{} 需要 1 个或 3 个数值", key),
+            }
+        };
+        let single = || -> Result<f32> {
+            match numbers()?.as_slice() {
+                [v] => Ok(*v),
+                _ => bail!("{} 需要 1 个数值", key),
+            }
+        };
+        let mut next = self.clone();
+        match key {
+            "gamma" => next.gamma = channels()?,
+            "gain" => next.gain = channels()?,
+            "offset" => next.offset = channels()?,
+            "brightness" => next.brightness = single()?,
+            "contrast" => next.contrast = single()?,
+            "saturation" => next.saturation = single()?,
+            "lut" => match args {
+                ["none"] => next.lut = None,
+                [path] => next.lut = Some(Arc::new(CubeLut::load(path)?)),
+                _ => bail!("lut 需要一个文件路径或 none"),
+            },
+            other => bail!(
+                "未知的校准参数: {}（可选 gamma、gain、offset、brightness、contrast、saturation、lut）",
+                other
+            ),
+        }
+        next.validate()?;
+        *self = next;
+        Ok(())
+    }
+
+    /// 原地校准图片，alpha 通道不变。
+    pub fn apply(&self, image: &mut RgbaImage) {
+        if self.is_identity() {
+            return;
+        }
+        // 亮度和对比度只与输入值有关，先算成表
+        let tone: Vec<f32> = (0..256)
+            .map(|v| (v as f32 / 255.0 - 0.5) * self.contrast + 0.5 + self.brightness)
+            .collect();
+        for pixel in image.pixels_mut() {
+            let mut rgb = [0, 1, 2].map(|c| tone[pixel[c] as usize]);
+            if self.saturation != 1.0 {
+                let luma = 0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2];
+                rgb = rgb.map(|v| luma + (v - luma) * self.saturation);
+            }
+            for (c, value) in rgb.iter_mut().enumerate() {
+                let v = (*value * self.gain[c] + self.offset[c]).clamp(0.0, 1.0);
+                *value = if self.gamma[c] == 1.0 {
+                    v
+                } else {
+                    v.powf(1.0 / self.gamma[c])
+                };
+            }
+            if let Some(lut) = &self.lut {
+                rgb = lut.sample(rgb);
+            }
+            for (c, value) in rgb.into_iter().enumerate() {
+                pixel[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+/// 用于缓存键和日志的简短描述。
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_identity() {
+            return write!(f, "none");
+        }
+        write!(
+            f,
+            "gamma{:?}-gain{:?}-offset{:?}-b{}-c{}-s{}",
+            self.gamma, self.gain, self.offset, self.brightness, self.contrast, self.saturation
+        )?;
+        if let Some(lut) = &self.lut {
+            write!(f, "-lut{:08x}", lut.checksum)?;
+        }
+        Ok(())
+    }
+}
+
+/// `.cube` 格式的 3D 查找表。
+#[derive(Debug)]
+pub struct CubeLut {
+    pub path: PathBuf,
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    /// 红色变化最快，其次绿色、蓝色。
+    table: Vec<[f32; 3]>,
+    checksum: u32,
+}
+
+impl PartialEq for CubeLut {
+    fn eq(&self, other: &CubeLut) -> bool {
+        self.checksum == other.checksum && self.size == other.size
+    }
+}
+
+impl CubeLut {
+    /// 读取 Adobe/Resolve 的 `.cube` 文件，只支持 3D 表。
+    pub fn load(path: impl AsRef<Path>) -> Result<CubeLut> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("无法读取查找表 {}", path.display()))?;
+        CubeLut::parse(&text)
+            .map(|lut| CubeLut {
+                path: path.to_path_buf(),
+                ..lut
+            })
+            .with_context(|| format!("查找表 {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<CubeLut> {
+        let mut size = 0;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+        let triple = |fields: &[&str], line: usize| -> Result<[f32; 3]> {
+            ensure!(fields.len() == 3, "第 {} 行应有 3 个数值", line);
+            let mut out = [0.0; 3];
+            for (out, field) in out.iter_mut().zip(fields) {
+                *out = field
+                    .parse()
+                    .with_context(|| format!("第 {} 行: 无效的数值: {}", line, field))?;
+            }
+            Ok(out)
+        };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[0] {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    size = fields
+                        .get(1)
+                        .and_then(|s| s.parse().ok())
+                        .filter(|n| (2..=MAX_LUT_SIZE).contains(n))
+                        .with_context(|| format!("第 {} 行: 无效的 LUT_3D_SIZE", i + 1))?;
+                }
+                "DOMAIN_MIN" => domain_min = triple(&fields[1..], i + 1)?,
+                "DOMAIN_MAX" => domain_max = triple(&fields[1..], i + 1)?,
+                "LUT_1D_SIZE" => bail!("不支持 1D 查找表"),
+                _ => table.push(triple(&fields, i + 1)?),
+            }
+        }
+        ensure!(size > 0, "缺少 LUT_3D_SIZE");
+        ensure!(
+            table.len() == size * size * size,
+            "数据行数 {} 与 LUT_3D_SIZE {} 不符",
+            table.len(),
+            size
+        );
+        ensure!(
+            (0..3).all(|c| domain_max[c] > domain_min[c]),
+            "DOMAIN_MAX 应大于 DOMAIN_MIN"
+        );
+        let bytes: Vec<u8> = table
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        Ok(CubeLut {
+            path: PathBuf::new(),
+            size,
+            domain_min,
+            domain_max,
+            checksum: crc32fast::hash(&bytes),
+            table,
+        })
+    }
+
+    /// 三线性插值查表。
+    pub fn sample(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let n = self.size;
+        let max = (n - 1) as f32;
+        let mut base = [0usize; 3];
+        let mut frac = [0.0f32; 3];
+        for c in 0..3 {
+            let t = (rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c]);
+            let pos = (t * max).clamp(0.0, max);
+            base[c] = (pos.floor() as usize).min(n - 2);
+            frac[c] = pos - base[c] as f32;
+        }
+        let at = |r: usize, g: usize, b: usize| self.table[(b * n + g) * n + r];
+        let mut out = [0.0; 3];
+        // 立方体的 8 个顶点，按到采样点的距离加权
+        for i in 0..8 {
+            let corner = [i & 1, (i >> 1) & 1, (i >> 2) & 1];
+            let weight: f32 = (0..3)
+                .map(|c| {
+                    if corner[c] == 1 {
+                        frac[c]
+                    } else {
+                        1.0 - frac[c]
+                    }
+                })
+                .product();
+            let value = at(
+                base[0] + corner[0],
+                base[1] + corner[1],
+                base[2] + corner[2],
+            );
+            for c in 0..3 {
+                out[c] += value[c] * weight;
+            }
+        }
+        out.map(|v| v.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按 `f` 生成边长为 `size` 的查找表，红色变化最快。
+    fn cube(size: usize, f: impl Fn([f32; 3]) -> [f32; 3]) -> String {
+        let mut text = format!("LUT_3D_SIZE {}\n", size);
+        let max = (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let [x, y, z] = f([r as f32 / max, g as f32 / max, b as f32 / max]);
+                    text += &format!("{} {} {}\n", x, y, z);
+                }
+            }
+        }
+        text
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for c in 0..3 {
+            assert!(
+                (actual[c] - expected[c]).abs() < 1e-5,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    /// 非线性的映射，插值不会恰好碰对。
+    fn warp([r, g, b]: [f32; 3]) -> [f32; 3] {
+        [r * r, 1.0 - g, (b + r) / 2.0]
+    }
+
+    #[test]
+    fn parses_comments_title_and_domain() {
+        let text = "# 面板校准\nTITLE \"panel\"\n\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n"
+            .to_string()
+            + &cube(2, |rgb| rgb).replace("\n0 0 0\n", "\n  # 注释\n0 0 0\n");
+        let lut = CubeLut::parse(&text).unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.domain_max, [2.0; 3]);
+        // 定义域为 0 到 2，输入 1 落在立方体中心
+        assert_close(lut.sample([1.0, 0.5, 2.0]), [0.5, 0.25, 1.0]);
+
+        let same = CubeLut::parse(&cube(2, |rgb| rgb)).unwrap();
+        assert_eq!(lut, same);
+        assert_ne!(same, CubeLut::parse(&cube(2, warp)).unwrap());
+    }
+
+    #[test]
+    fn rejects_malformed_cubes() {
+        let identity = cube(2, |rgb| rgb);
+        let cases = [
+            identity.replace("LUT_3D_SIZE 2\n", ""),
+            identity.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 3"),
+            identity.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 1"),
+            identity.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 300"),
+            identity.replace("LUT_3D_SIZE 2", "LUT_1D_SIZE 2"),
+            identity.replacen("1 1 1", "1 1", 1),
+            identity.replacen("1 1 1", "1 x 1", 1),
+            "DOMAIN_MIN 1 0 0\nDOMAIN_MAX 1 1 1\n".to_string() + &identity,
+        ];
+        for text in cases {
+            assert!(CubeLut::parse(&text).is_err(), "应拒绝:\n{}", text);
+        }
+        let err = CubeLut::parse(&identity.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 3")).unwrap_err();
+        assert!(err.to_string().contains("与 LUT_3D_SIZE 3 不符"), "{}", err);
+    }
+
+    #[test]
+    fn samples_grid_points_exactly() {
+        let lut = CubeLut::parse(&cube(3, warp)).unwrap();
+        for i in 0..27 {
+            let corner = [i % 3, i / 3 % 3, i / 9].map(|v| v as f32 / 2.0);
+            assert_close(lut.sample(corner), warp(corner));
+        }
+        // 超出定义域的输入取边上的值
+        assert_close(lut.sample([-1.0, 2.0, 0.0]), warp([0.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn interpolates_trilinearly() {
+        // 2x2x2 的表内插值是三线性的，仿射映射应被精确还原
+        let affine = |[r, g, b]: [f32; 3]| [0.2 + 0.5 * r, 1.0 - g, (r + b) / 2.0];
+        let lut = CubeLut::parse(&cube(2, affine)).unwrap();
+        for rgb in [[0.25, 0.5, 0.75], [0.1, 0.9, 0.3], [0.5; 3]] {
+            assert_close(lut.sample(rgb), affine(rgb));
+        }
+        // 非仿射时在格点之间按 8 个顶点加权：r² 在 0.5 处取两端平均 0.5
+        let lut = CubeLut::parse(&cube(2, warp)).unwrap();
+        assert_close(lut.sample([0.5, 0.0, 0.0]), [0.5, 1.0, 0.25]);
+    }
+
+    #[test]
+    fn set_validates_before_applying() {
+        let mut calibration = Calibration::default();
+        assert!(calibration.is_identity());
+        calibration.set("gain", &["1", "0.95", "0.9"]).unwrap();
+        assert_eq!(calibration.gain, [1.0, 0.95, 0.9]);
+        assert!(calibration.set("gamma", &["0"]).is_err());
+        assert!(calibration.set("gamma", &["1", "2"]).is_err());
+        assert!(calibration.set("hue", &["1"]).is_err());
+        assert_eq!(calibration.gamma, [1.0; 3]);
+
+        let mut image = RgbaImage::from_pixel(1, 1, image::Rgba([200, 200, 200, 7]));
+        calibration.apply(&mut image);
+        assert_eq!(image.get_pixel(0, 0).0, [200, 190, 180, 7]);
+    }
+}
@@ -0,0 +1,197 @@
+//! 帧负载的 LZ4 压缩。
+//!
+//! 压缩后的负载为 LZ4 块格式，前面附加 4 字节小端的原始长度，
+//! 与 `lz4_flex::block::compress_prepend_size` 的输出一致。包头的
+//! [`FLAG_LZ4`](crate::protocol::FLAG_LZ4) 标志位表明该帧是否压缩，因此可以逐帧切换。
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+use crate::protocol::MAX_PAYLOAD_LEN;
+
+/// 压缩策略。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// 不压缩。
+    None,
+    /// 总是压缩。
+    Lz4,
+    /// 压缩后更小才使用压缩结果。
+    #[default]
+    Auto,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Compression> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "lz4" => Ok(Compression::Lz4),
+            "auto" => Ok(Compression::Auto),
+            other => bail!("未知的压缩策略: {}（可选 none、lz4、auto）", other),
+        }
+    }
+}
+
+pub fn compress_lz4(data: &[u8]) -> Vec<u8> {
+    lz4_flex::block::compress_prepend_size(data)
+}
+
+pub fn decompress_lz4(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 4 {
+        bail!("LZ4 负载长度不足: {}", data.len());
+    }
+    // 先检查声明的原始长度，避免损坏的数据导致巨量分配
+    let raw_len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if raw_len > MAX_PAYLOAD_LEN {
+        bail!("LZ4 原始长度过大: {}", raw_len);
+    }
+    Ok(lz4_flex::block::decompress_size_prepended(data)?)
+}
+
+/// 累计的压缩统计。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// 发送的数据包数。
+    pub packets: u64,
+    /// 其中负载经过压缩的包数。
+    pub compressed_packets: u64,
+    /// 压缩前的负载字节数。
+    pub raw_bytes: u64,
+    /// 实际发送的负载字节数。
+    pub wire_bytes: u64,
+}
+
+impl CompressionStats {
+    pub fn record(&mut self, raw_len: usize, wire_len: usize, compressed: bool) {
+        self.packets += 1;
+        self.compressed_packets += compressed as u64;
+        self.raw_bytes += raw_len as u64;
+        self.wire_bytes += wire_len as u64;
+    }
+
+    /// 压缩比（原始字节 / 实际字节），没有数据时为 1。
+    pub fn ratio(&self) -> f64 {
+        if self.wire_bytes == 0 {
+            1.0
+        } else {
+            self.raw_bytes as f64 / self.wire_bytes as f64
+        }
+    }
+}
+
+impl fmt::Display for CompressionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} 个包（压缩 {} 个），原始 {} 字节，实际 {} 字节，压缩比 {:.2}",
+            self.packets,
+            self.compressed_packets,
+            self.raw_bytes,
+            self.wire_bytes,
+            self.ratio()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handshake::DeviceInfo;
+    use crate::protocol::{Packet, PacketDecoder, FLAG_LZ4};
+    use crate::screen::Screen;
+    use crate::transport::MemoryTransport;
+
+    /// 不可压缩的伪随机数据。
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// 以给定策略发送一帧，返回解出的包。
+    fn send_frame(lz4: bool, compression: Compression, pixels: &[u8]) -> (Packet, Screen) {
+        let transport = MemoryTransport::new();
+        let info = DeviceInfo {
+            width: 64,
+            height: 32,
+            lz4,
+            ..DeviceInfo::legacy()
+        };
+        let mut screen = Screen::with_info(Box::new(transport.clone()), info).unwrap();
+        screen.set_compression(compression);
+        screen.draw_pixels(pixels).unwrap();
+        let mut decoder = PacketDecoder::new();
+        decoder.push(&transport.take());
+        (decoder.next_packet().unwrap(), screen)
+    }
+
+    #[test]
+    fn lz4_round_trip() {
+        let data = [vec![7; 1000], noise(100)].concat();
+        let compressed = compress_lz4(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress_lz4(&compressed).unwrap(), data);
+        assert_eq!(
+            decompress_lz4(&compress_lz4(&[])).unwrap(),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_payloads() {
+        assert!(decompress_lz4(&[1, 2]).is_err());
+        let mut huge = compress_lz4(&[0; 16]);
+        huge[..4].copy_from_slice(&(MAX_PAYLOAD_LEN as u32 + 1).to_le_bytes());
+        assert!(decompress_lz4(&huge).is_err());
+    }
+
+    #[test]
+    fn sets_flag_and_round_trips_through_screen() {
+        let pixels = vec![0x42; 64 * 32 * 2];
+        let (packet, screen) = send_frame(true, Compression::Lz4, &pixels);
+        assert_ne!(packet.header.flags & FLAG_LZ4, 0);
+        assert!(packet.payload.len() < pixels.len());
+        assert_eq!(packet.decoded_payload().unwrap(), pixels);
+        let stats = screen.stats();
+        assert_eq!((stats.packets, stats.compressed_packets), (1, 1));
+        assert!(stats.ratio() > 1.0);
+    }
+
+    #[test]
+    fn auto_falls_back_to_raw() {
+        let pixels = noise(64 * 32 * 2);
+        let (packet, screen) = send_frame(true, Compression::Auto, &pixels);
+        assert_eq!(packet.header.flags & FLAG_LZ4, 0);
+        assert_eq!(packet.payload, pixels);
+        assert_eq!(screen.stats().compressed_packets, 0);
+
+        let pixels = vec![0; 64 * 32 * 2];
+        let (packet, _) = send_frame(true, Compression::Auto, &pixels);
+        assert_ne!(packet.header.flags & FLAG_LZ4, 0);
+    }
+
+    #[test]
+    fn never_compresses_for_legacy_devices() {
+        let pixels = vec![0; 64 * 32 * 2];
+        let (packet, _) = send_frame(false, Compression::Lz4, &pixels);
+        assert_eq!(packet.header.flags & FLAG_LZ4, 0);
+        assert_eq!(packet.payload, pixels);
+    }
+
+    #[test]
+    fn parses_strategy() {
+        assert_eq!(" LZ4 ".parse::<Compression>().unwrap(), Compression::Lz4);
+        assert_eq!("none".parse::<Compression>().unwrap(), Compression::None);
+        assert!("zstd".parse::<Compression>().is_err());
+    }
+}
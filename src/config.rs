@@ -0,0 +1,810 @@
+//! 配置文件 `usb-screen.toml`。
+//!
+//! 依次查找当前目录和用户配置目录（`$XDG_CONFIG_HOME/usb-screen/`，
+//! 未设置时为 `~/.config/usb-screen/`，Windows 上为 `%APPDATA%\usb-screen\`），
+//! 使用找到的第一个文件。所有字段都可省略，命令行参数优先于配置文件；
+//! 图片来源、调色板、底色图片、查找表和缓存目录中的相对路径相对配置文件所在目录。
+//!
+//! ```toml
+//! [device]
+//! match = ["rp2040", "serial=E660583883"]  # 写法同 --device
+//! baud = 115200
+//! compression = "auto"                     # none | lz4 | auto
+//! partial_update = true
+//! pixel_format = "auto"                    # auto | rgb565 | rgb666 | rgb888 | indexed8 | gray8 | gray4 | mono 等
+//! palette = "./palette.hex"                # indexed8 使用的调色板，默认 RGB332
+//! rotation = 90                            # 安装方向：0 | 90 | 180 | 270，顺时针
+//! flip = "none"                            # none | horizontal | vertical | both，先于旋转
+//!
+//! [display]
+//! width = 320                              # 设备未应答握手时使用
+//! height = 240
+//! fps = 24
+//! filter = "lanczos3"                      # nearest | triangle | catmullrom | gaussian | lanczos3
+//! fit = "contain"                          # stretch | contain | cover | center | tile
+//! background = "#000000"                   # contain/center 的留白：颜色或 "blur"
+//! gravity = "center"                       # center | top | bottom | left | right | top-left 等
+//! dither = "bayer8"                        # none | round | bayer4 | bayer8 | floyd-steinberg | blue-noise
+//! matte = "#000000"                        # 透明像素的底色：颜色、"gradient:#000000,#203040" 或 "image:./bg.png"
+//!
+//! [playlist]
+//! sources = ["./images"]                   # 目录或单个图片
+//! shuffle = false
+//! repeat = true
+//! recursive = true                         # 进入子目录
+//! include = ["*.png", "*.gif"]             # 按相对路径过滤，忽略大小写
+//! exclude = ["drafts/**"]
+//! hold = 5.0                               # 每张静态图片显示的秒数
+//!
+//! [[playlist.item]]                        # 按完整路径或文件名单独设置
+//! match = "*.title.png"
+//! hold = 10.0
+//! fit = "cover"                            # fit、background、gravity 均可单独指定
+//!
+//! [cache]
+//! memory_mb = 64                           # 解码结果的内存预算
+//! persist = false                          # 写入磁盘，重启后无需重新解码
+//!
+//! [pipeline]
+//! queue_depth = 4                          # 解码、转换、发送之间的队列深度
+//! backpressure = "block"                   # block | drop-oldest
+//!
+//! [calibration]                            # 颜色校准，可用 calibrate 命令对照测试图案调整
+//! gamma = 1.0                              # 单个数值或 [r, g, b]，大于 1 提亮中间调
+//! gain = [1.0, 0.96, 0.92]                 # v * gain + offset
+//! offset = 0.0
+//! brightness = 0.0                         # -1 到 1
+//! contrast = 1.0
+//! saturation = 1.0                         # 0 为灰度
+//! lut = "./panel.cube"                     # 可选的 3D 查找表，最后应用
+//!
+//! [screens.E660583883]                     # 按设备别名单独指定内容、安装方向和校准
+//! sources = ["./left"]                     # 省略时使用 playlist.sources
+//! rotation = 180
+//! flip = "horizontal"
+//! calibration = { gamma = 1.1 }            # 整体替换 [calibration]
+//!
+//! [wall]
+//! cols = 2
+//! rows = 1
+//! tile_width = 320
+//! tile_height = 240
+//! bezel_x = 20
+//! tiles = [
+//!     { alias = "E660583883", col = 0, row = 0 },
+//!     { alias = "E660583884", col = 1, row = 0, rotation = 180 },
+//! ]
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::alpha::{Matte, MatteImage};
+use crate::cache::{CacheConfig, DEFAULT_MEMORY_BUDGET};
+use crate::calibration::{Calibration, CubeLut};
+use crate::compress::Compression;
+use crate::dirty::DirtyConfig;
+use crate::fit::Fit;
+use crate::format::PixelFormat;
+use crate::loader::{parse_filter, ScanOptions};
+use crate::palette::Palette;
+use crate::pipeline::{Backpressure, DEFAULT_QUEUE_DEPTH};
+use crate::player::{ItemRule, PlayOptions, DEFAULT_FPS};
+use crate::screen::ScreenOptions;
+use crate::transform::{Orientation, Rotation};
+use crate::wall::{WallLayout, WallTile};
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const CONFIG_FILE_NAME: &str = "usb-screen.toml";
+const MAX_FPS: u32 = 240;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub device: DeviceSection,
+    pub display: DisplaySection,
+    pub playlist: PlaylistSection,
+    /// 设备别名到内容的映射。
+    pub screens: BTreeMap<String, ScreenSection>,
+    pub wall: Option<WallSection>,
+    pub cache: CacheSection,
+    pub pipeline: PipelineSection,
+    pub calibration: CalibrationSection,
+    /// 已加载的调色板、查找表和底色图片，校验和生成选项时不重复读取。
+    #[serde(skip)]
+    resources: Resources,
+}
+
+/// 按路径缓存的外部文件，克隆出的配置共享同一份。
+#[derive(Debug, Clone, Default)]
+struct Resources {
+    palettes: Arc<Mutex<HashMap<PathBuf, Arc<Palette>>>>,
+    luts: Arc<Mutex<HashMap<PathBuf, Arc<CubeLut>>>>,
+    mattes: Arc<Mutex<HashMap<PathBuf, Arc<MatteImage>>>>,
+}
+
+/// 取出 `path` 对应的缓存，没有时用 `load` 加载；加载失败不缓存。
+fn cached<T>(
+    cache: &Mutex<HashMap<PathBuf, Arc<T>>>,
+    path: &Path,
+    load: impl FnOnce() -> Result<T>,
+) -> Result<Arc<T>> {
+    if let Some(value) = cache.lock().unwrap().get(path) {
+        return Ok(value.clone());
+    }
+    let value = Arc::new(load()?);
+    cache
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), value.clone());
+    Ok(value)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceSection {
+    /// 设备匹配规则，写法见 [`crate::device`]。
+    #[serde(rename = "match")]
+    pub specs: Vec<String>,
+    pub baud: u32,
+    pub compression: String,
+    pub partial_update: bool,
+    /// `auto` 表示按设备能力选择。
+    pub pixel_format: String,
+    /// 调色板文件，格式见 [`crate::palette`]。
+    pub palette: Option<PathBuf>,
+    /// 顺时针旋转角度，见 [`crate::transform::Orientation`]。
+    pub rotation: u32,
+    pub flip: String,
+}
+
+impl Default for DeviceSection {
+    fn default() -> DeviceSection {
+        DeviceSection {
+            specs: Vec::new(),
+            baud: 115_200,
+            compression: "auto".to_string(),
+            partial_update: true,
+            pixel_format: "auto".to_string(),
+            palette: None,
+            rotation: 0,
+            flip: "none".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplaySection {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub filter: String,
+    /// 缩放方式，见 [`crate::fit`]。
+    pub fit: String,
+    /// `contain` 和 `center` 的背景：颜色或 `blur`。
+    pub background: String,
+    pub gravity: String,
+    /// 降低色深时的抖动方式，见 [`crate::dither`]。
+    pub dither: String,
+    /// 透明像素的底色，见 [`crate::alpha`]。
+    pub matte: String,
+}
+
+impl Default for DisplaySection {
+    fn default() -> DisplaySection {
+        DisplaySection {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            fps: DEFAULT_FPS,
+            filter: "lanczos3".to_string(),
+            fit: "stretch".to_string(),
+            background: "#000000".to_string(),
+            gravity: "center".to_string(),
+            dither: "none".to_string(),
+            matte: "#000000".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaylistSection {
+    pub sources: Vec<PathBuf>,
+    pub shuffle: bool,
+    pub repeat: bool,
+    /// 是否进入子目录。
+    pub recursive: bool,
+    /// 只播放相对路径匹配这些 glob 的文件。
+    pub include: Vec<String>,
+    /// 跳过相对路径匹配这些 glob 的文件。
+    pub exclude: Vec<String>,
+    /// 每张静态图片显示的秒数，省略时只显示一帧。
+    pub hold: Option<f64>,
+    /// 按文件单独指定的设置，即 `[[playlist.item]]`。
+    #[serde(rename = "item")]
+    pub items: Vec<ItemSection>,
+}
+
+/// 播放列表中的单项设置。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemSection {
+    /// 与完整路径或文件名匹配的 glob。
+    #[serde(rename = "match")]
+    pub pattern: String,
+    pub hold: Option<f64>,
+    pub fit: Option<String>,
+    pub background: Option<String>,
+    pub gravity: Option<String>,
+}
+
+impl Default for PlaylistSection {
+    fn default() -> PlaylistSection {
+        PlaylistSection {
+            sources: vec![PathBuf::from("./images")],
+            shuffle: false,
+            repeat: true,
+            recursive: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            hold: None,
+            items: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineSection {
+    pub queue_depth: usize,
+    pub backpressure: String,
+}
+
+impl Default for PipelineSection {
+    fn default() -> PipelineSection {
+        PipelineSection {
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            backpressure: Backpressure::default().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheSection {
+    /// 内存预算（MiB），0 表示不缓存。
+    pub memory_mb: u64,
+    /// 是否把解码结果写入磁盘。
+    pub persist: bool,
+    /// 持久化目录，默认为用户缓存目录下的 `usb-screen`。
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for CacheSection {
+    fn default() -> CacheSection {
+        CacheSection {
+            memory_mb: (DEFAULT_MEMORY_BUDGET / (1024 * 1024)) as u64,
+            persist: false,
+            dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScreenSection {
+    pub sources: Vec<PathBuf>,
+    /// 覆盖 `device.rotation`。
+    pub rotation: Option<u32>,
+    /// 覆盖 `device.flip`。
+    pub flip: Option<String>,
+    /// 整体替换 `[calibration]`。
+    pub calibration: Option<CalibrationSection>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationSection {
+    pub gamma: Channels,
+    pub gain: Channels,
+    pub offset: Channels,
+    pub brightness: f32,
+    pub contrast: f32,
+    pub saturation: f32,
+    pub lut: Option<PathBuf>,
+}
+
+impl Default for CalibrationSection {
+    fn default() -> Self {
+        CalibrationSection {
+            gamma: Channels::All(1.0),
+            gain: Channels::All(1.0),
+            offset: Channels::All(0.0),
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            lut: None,
+        }
+    }
+}
+
+impl CalibrationSection {
+    /// `key` 为这一节的名字，用于错误信息。
+    fn build(&self, key: &str, resources: &Resources) -> Result<Calibration> {
+        let lut = match &self.lut {
+            Some(path) => Some(
+                cached(&resources.luts, path, || CubeLut::load(path))
+                    .with_context(|| format!("{}.lut", key))?,
+            ),
+            None => None,
+        };
+        let calibration = Calibration {
+            gamma: self.gamma.values(),
+            gain: self.gain.values(),
+            offset: self.offset.values(),
+            brightness: self.brightness,
+            contrast: self.contrast,
+            saturation: self.saturation,
+            lut,
+        };
+        calibration.validate().context(key.to_string())?;
+        Ok(calibration)
+    }
+}
+
+/// 三个通道相同时可以只写一个数值。
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum Channels {
+    All(f32),
+    Each([f32; 3]),
+}
+
+impl Channels {
+    pub fn values(self) -> [f32; 3] {
+        match self {
+            Channels::All(v) => [v; 3],
+            Channels::Each(rgb) => rgb,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WallSection {
+    pub cols: u32,
+    pub rows: u32,
+    #[serde(default = "default_tile_width")]
+    pub tile_width: u32,
+    #[serde(default = "default_tile_height")]
+    pub tile_height: u32,
+    #[serde(default)]
+    pub bezel_x: u32,
+    #[serde(default)]
+    pub bezel_y: u32,
+    pub tiles: Vec<WallTileSection>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WallTileSection {
+    pub alias: String,
+    pub col: u32,
+    pub row: u32,
+    #[serde(default)]
+    pub rotation: u32,
+}
+
+/// 把配置中的秒数转为时长。
+fn seconds(key: &str, secs: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .with_context(|| format!("{}: 时长无效: {}", key, secs))
+}
+
+/// 解析单项设置中的可选字段。
+fn parse_opt<T>(key: &str, field: &str, value: &Option<String>) -> Result<Option<T>>
+where
+    T: FromStr<Err = anyhow::Error>,
+{
+    value
+        .as_deref()
+        .map(|value| value.parse().with_context(|| format!("{}.{}", key, field)))
+        .transpose()
+}
+
+fn default_tile_width() -> u32 {
+    SCREEN_WIDTH
+}
+
+fn default_tile_height() -> u32 {
+    SCREEN_HEIGHT
+}
+
+impl Config {
+    /// 按模块文档中的顺序查找配置文件。
+    pub fn find() -> Option<PathBuf> {
+        let mut candidates = vec![PathBuf::from(CONFIG_FILE_NAME)];
+        if let Some(dir) = config_dir() {
+            candidates.push(dir.join("usb-screen").join(CONFIG_FILE_NAME));
+        }
+        candidates.into_iter().find(|path| path.is_file())
+    }
+
+    /// 读取并校验配置文件，错误信息包含文件路径和出错的键。
+    pub fn load(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("无法读取配置文件 {}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(&text).with_context(|| format!("配置文件 {} 有误", path.display()))?;
+        if let Some(base) = path.parent() {
+            config.resolve_paths(base);
+        }
+        config
+            .validate()
+            .with_context(|| format!("配置文件 {} 有误", path.display()))?;
+        Ok(config)
+    }
+
+    /// 把图片来源、调色板、底色图片、查找表和缓存目录中的相对路径改为相对配置文件所在目录。
+    fn resolve_paths(&mut self, base: &Path) {
+        let screens = self.screens.values_mut().flat_map(|screen| {
+            let lut = screen.calibration.as_mut().and_then(|c| c.lut.as_mut());
+            screen.sources.iter_mut().chain(lut)
+        });
+        let files = self
+            .cache
+            .dir
+            .as_mut()
+            .into_iter()
+            .chain(self.device.palette.as_mut())
+            .chain(self.calibration.lut.as_mut());
+        for source in screens.chain(self.playlist.sources.iter_mut()).chain(files) {
+            if source.is_relative() {
+                *source = base.join(&*source);
+            }
+        }
+        if let Some(path) = self.display.matte.trim().strip_prefix("image:") {
+            let path = Path::new(path.trim());
+            if path.is_relative() {
+                self.display.matte = format!("image:{}", base.join(path).display());
+            }
+        }
+    }
+
+    /// 检查取值范围，错误信息以出错的键开头。
+    pub fn validate(&self) -> Result<()> {
+        #[cfg(feature = "usb-serial")]
+        self.matcher()?;
+        if self.device.baud == 0 {
+            bail!("device.baud: 波特率不能为 0");
+        }
+        self.screen_options()?;
+        if self.display.width == 0 || self.display.height == 0 {
+            bail!(
+                "display.width/display.height: 分辨率无效: {}x{}",
+                self.display.width,
+                self.display.height
+            );
+        }
+        if !(1..=MAX_FPS).contains(&self.display.fps) {
+            bail!(
+                "display.fps: 帧率应在 1 到 {} 之间，当前为 {}",
+                MAX_FPS,
+                self.display.fps
+            );
+        }
+        for alias in self.screens.keys() {
+            self.screen_options_for(alias)?;
+        }
+        self.play_options()?;
+        self.scan_options()?;
+        self.wall_layout()?;
+        Ok(())
+    }
+
+    /// 设备匹配条件；未配置规则时只匹配 RP2040。
+    #[cfg(feature = "usb-serial")]
+    pub fn matcher(&self) -> Result<crate::device::DeviceMatcher> {
+        let mut matcher = crate::device::DeviceMatcher::default();
+        for (i, spec) in self.device.specs.iter().enumerate() {
+            matcher
+                .add_spec(spec)
+                .with_context(|| format!("device.match[{}]", i))?;
+        }
+        if self.device.specs.is_empty() {
+            matcher = crate::device::DeviceMatcher::rp2040();
+        }
+        Ok(matcher)
+    }
+
+    pub fn screen_options(&self) -> Result<ScreenOptions> {
+        let compression: Compression = self
+            .device
+            .compression
+            .parse()
+            .context("device.compression")?;
+        let palette = match &self.device.palette {
+            Some(path) => cached(&self.resources.palettes, path, || Palette::load(path))
+                .context("device.palette")?,
+            None => Arc::default(),
+        };
+        Ok(ScreenOptions {
+            fallback_size: (self.display.width, self.display.height),
+            compression,
+            dirty: self.device.partial_update.then(DirtyConfig::default),
+            format: self.pixel_format()?,
+            palette,
+            orientation: self.orientation()?,
+            calibration: Arc::new(self.calibration.build("calibration", &self.resources)?),
+        })
+    }
+
+    /// 某块屏幕的设备选项，安装方向和校准按 `[screens.别名]` 覆盖。
+    pub fn screen_options_for(&self, alias: &str) -> Result<ScreenOptions> {
+        Ok(ScreenOptions {
+            orientation: self.orientation_for(alias)?,
+            calibration: self.calibration_for(alias)?,
+            ..self.screen_options()?
+        })
+    }
+
+    /// 某块屏幕的颜色校准，`[screens.别名.calibration]` 整体替换 `[calibration]`。
+    pub fn calibration_for(&self, alias: &str) -> Result<Arc<Calibration>> {
+        let section = self.screens.get(alias).and_then(|s| s.calibration.as_ref());
+        Ok(Arc::new(match section {
+            Some(section) => {
+                let key = format!("screens.{}.calibration", alias);
+                section.build(&key, &self.resources)?
+            }
+            None => self.calibration.build("calibration", &self.resources)?,
+        }))
+    }
+
+    /// 透明像素的底色，图片底色只加载一次。
+    fn matte(&self) -> Result<Matte> {
+        match self.display.matte.trim().strip_prefix("image:") {
+            Some(path) => {
+                let path = Path::new(path.trim());
+                Ok(Matte::Image(cached(&self.resources.mattes, path, || {
+                    MatteImage::load(path)
+                })?))
+            }
+            None => self.display.matte.parse(),
+        }
+    }
+
+    /// `[device]` 中的安装方向。
+    pub fn orientation(&self) -> Result<Orientation> {
+        Ok(Orientation::new(
+            Rotation::from_degrees(self.device.rotation).context("device.rotation")?,
+            self.device.flip.parse().context("device.flip")?,
+        ))
+    }
+
+    /// 某块屏幕的安装方向，`[screens.别名]` 中未指定的部分使用 `[device]` 的设置。
+    pub fn orientation_for(&self, alias: &str) -> Result<Orientation> {
+        let mut orientation = self.orientation()?;
+        if let Some(screen) = self.screens.get(alias) {
+            let key = format!("screens.{}", alias);
+            if let Some(degrees) = screen.rotation {
+                orientation.rotation =
+                    Rotation::from_degrees(degrees).with_context(|| format!("{}.rotation", key))?;
+            }
+            if let Some(flip) = parse_opt(&key, "flip", &screen.flip)? {
+                orientation.flip = flip;
+            }
+        }
+        Ok(orientation)
+    }
+
+    /// 配置的像素格式，`auto` 时为 `None`。
+    pub fn pixel_format(&self) -> Result<Option<PixelFormat>> {
+        let name = self.device.pixel_format.trim();
+        if name.eq_ignore_ascii_case("auto") {
+            return Ok(None);
+        }
+        Ok(Some(name.parse().context("device.pixel_format")?))
+    }
+
+    pub fn play_options(&self) -> Result<PlayOptions> {
+        if self.pipeline.queue_depth == 0 {
+            bail!("pipeline.queue_depth: 队列深度不能为 0");
+        }
+        let mut items = Vec::with_capacity(self.playlist.items.len());
+        for (i, item) in self.playlist.items.iter().enumerate() {
+            let key = format!("playlist.item[{}]", i);
+            let pattern = glob::Pattern::new(&item.pattern)
+                .with_context(|| format!("{}.match: 无效的 glob: {}", key, item.pattern))?;
+            let hold = item
+                .hold
+                .map(|secs| seconds(&format!("{}.hold", key), secs))
+                .transpose()?;
+            items.push(ItemRule {
+                pattern,
+                hold,
+                fit: parse_opt(&key, "fit", &item.fit)?,
+                background: parse_opt(&key, "background", &item.background)?,
+                gravity: parse_opt(&key, "gravity", &item.gravity)?,
+            });
+        }
+        Ok(PlayOptions {
+            fps: self.display.fps,
+            filter: parse_filter(&self.display.filter).context("display.filter")?,
+            fit: Fit {
+                mode: self.display.fit.parse().context("display.fit")?,
+                background: self
+                    .display
+                    .background
+                    .parse()
+                    .context("display.background")?,
+                gravity: self.display.gravity.parse().context("display.gravity")?,
+            },
+            dither: self.display.dither.parse().context("display.dither")?,
+            matte: self.matte().context("display.matte")?,
+            shuffle: self.playlist.shuffle,
+            repeat: self.playlist.repeat,
+            hold: self
+                .playlist
+                .hold
+                .map(|secs| seconds("playlist.hold", secs))
+                .transpose()?,
+            items,
+            queue_depth: self.pipeline.queue_depth,
+            backpressure: self
+                .pipeline
+                .backpressure
+                .parse()
+                .context("pipeline.backpressure")?,
+        })
+    }
+
+    pub fn scan_options(&self) -> Result<ScanOptions> {
+        for (key, globs) in [
+            ("include", &self.playlist.include),
+            ("exclude", &self.playlist.exclude),
+        ] {
+            for (i, glob) in globs.iter().enumerate() {
+                glob::Pattern::new(glob)
+                    .with_context(|| format!("playlist.{}[{}]: 无效的 glob: {}", key, i, glob))?;
+            }
+        }
+        ScanOptions::with_globs(
+            self.playlist.recursive,
+            &self.playlist.include,
+            &self.playlist.exclude,
+        )
+    }
+
+    pub fn cache_config(&self) -> CacheConfig {
+        let disk_dir = if self.cache.persist {
+            self.cache
+                .dir
+                .clone()
+                .or_else(|| cache_dir().map(|dir| dir.join("usb-screen")))
+        } else {
+            None
+        };
+        CacheConfig {
+            memory_budget: (self.cache.memory_mb as usize).saturating_mul(1024 * 1024),
+            disk_dir,
+        }
+    }
+
+    /// 某块屏幕的图片来源：`[screens.别名]` 优先，否则使用 `[playlist]`。
+    pub fn sources_for(&self, alias: &str) -> &[PathBuf] {
+        match self.screens.get(alias) {
+            Some(screen) if !screen.sources.is_empty() => &screen.sources,
+            _ => &self.playlist.sources,
+        }
+    }
+
+    /// 配置了 `[wall]` 时返回拼接屏布局。
+    pub fn wall_layout(&self) -> Result<Option<WallLayout>> {
+        let Some(wall) = &self.wall else {
+            return Ok(None);
+        };
+        if wall.tile_width == 0 || wall.tile_height == 0 {
+            bail!(
+                "wall.tile_width/wall.tile_height: 屏幕尺寸无效: {}x{}",
+                wall.tile_width,
+                wall.tile_height
+            );
+        }
+        let mut layout = WallLayout::new(wall.cols, wall.rows, wall.tile_width, wall.tile_height);
+        layout.bezel_x = wall.bezel_x;
+        layout.bezel_y = wall.bezel_y;
+        for (i, tile) in wall.tiles.iter().enumerate() {
+            layout.tiles.push(WallTile {
+                alias: tile.alias.clone(),
+                col: tile.col,
+                row: tile.row,
+                rotation: Rotation::from_degrees(tile.rotation)
+                    .with_context(|| format!("wall.tiles[{}].rotation", i))?,
+            });
+        }
+        layout.validate().context("wall")?;
+        Ok(Some(layout))
+    }
+}
+
+/// 用户缓存目录。
+fn cache_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("LOCALAPPDATA").map(PathBuf::from);
+    }
+    match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")),
+    }
+}
+
+/// 用户配置目录。
+fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(PathBuf::from);
+    }
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在临时目录中写入配置和它引用的文件，返回配置文件路径。
+    fn write_config(name: &str, toml: &str) -> PathBuf {
+        let dir =
+            env::temp_dir().join(format!("usb-screen-config-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("panel.cube"),
+            "LUT_3D_SIZE 2\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n",
+        )
+        .unwrap();
+        fs::write(dir.join("palette.hex"), "#000000\n#ffffff\n").unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+        fs::write(&path, toml).unwrap();
+        path
+    }
+
+    #[test]
+    fn loads_files_relative_to_config_once() {
+        let path = write_config(
+            "cache",
+            "[device]\npalette = \"palette.hex\"\n\n[calibration]\nlut = \"panel.cube\"\n\n[screens.A.calibration]\ngamma = 1.2\nlut = \"panel.cube\"\n",
+        );
+        let config = Config::load(&path).unwrap();
+        let first = config.screen_options().unwrap();
+        let again = config.clone().screen_options_for("A").unwrap();
+        assert!(Arc::ptr_eq(&first.palette, &again.palette));
+        let (Some(a), Some(b)) = (&first.calibration.lut, &again.calibration.lut) else {
+            panic!("缺少查找表");
+        };
+        assert!(Arc::ptr_eq(a, b));
+        assert_eq!(again.calibration.gamma, [1.2; 3]);
+        assert_eq!(first.palette.colors().len(), 2);
+    }
+
+    #[test]
+    fn reports_key_of_bad_calibration() {
+        let path = write_config("bad", "[screens.X.calibration]\ncontrast = -1\n");
+        let err = format!("{:#}", Config::load(&path).unwrap_err());
+        assert!(err.contains("screens.X.calibration"), "{}", err);
+        let path = write_config("missing", "[calibration]\nlut = \"missing.cube\"\n");
+        let err = format!("{:#}", Config::load(&path).unwrap_err());
+        assert!(err.contains("calibration.lut"), "{}", err);
+    }
+}
@@ -0,0 +1,35 @@
+use image::RgbaImage;
+
+/// 把 RGBA 图像转换为大端 RGB565 字节流，alpha 通道被忽略。
+pub fn rgb888_to_rgb565(image: &RgbaImage) -> Vec<u8> {
+    let mut rgb565 = Vec::with_capacity((image.width() * image.height() * 2) as usize);
+    for pixel in image.pixels() {
+        let r = pixel[0] as u16;
+        let g = pixel[1] as u16;
+        let b = pixel[2] as u16;
+        let rgb565_pixel = ((r & 0b11111000) << 8) | ((g & 0b11111100) << 3) | (b >> 3);
+        rgb565.extend_from_slice(&rgb565_pixel.to_be_bytes());
+    }
+    rgb565
+}
+
+/// `rgb888_to_rgb565` 的逆变换，低位按高位补齐，用于模拟器和调试输出。
+pub fn rgb565_to_rgba(data: &[u8], width: u32, height: u32) -> Option<RgbaImage> {
+    if data.len() != (width * height * 2) as usize {
+        return None;
+    }
+    let mut image = RgbaImage::new(width, height);
+    for (pixel, bytes) in image.pixels_mut().zip(data.chunks_exact(2)) {
+        let value = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let r = ((value >> 11) & 0x1F) as u8;
+        let g = ((value >> 5) & 0x3F) as u8;
+        let b = (value & 0x1F) as u8;
+        pixel.0 = [
+            (r << 3) | (r >> 2),
+            (g << 2) | (g >> 4),
+            (b << 3) | (b >> 2),
+            255,
+        ];
+    }
+    Some(image)
+}
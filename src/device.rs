@@ -0,0 +1,366 @@
+//! 串口设备的查找与匹配。
+//!
+//! 匹配规则用字符串描述，命令行和配置文件共用同一种写法：
+//!
+//! - `2e8a:000a`：十六进制 VID:PID
+//! - `rp2040`、`esp32-s3`、`ch340`、`cp210x`：常见芯片的预设 VID:PID
+//! - `serial=E660583883`：USB 序列号，精确匹配
+//! - `product=USB-Screen`：USB 产品字符串，忽略大小写的子串匹配
+//! - `/dev/ttyACM0`、`COM3` 或 `path=...`：直接指定串口路径
+//!
+//! 多条 VID:PID 或路径之间是“或”的关系，序列号和产品字符串是附加条件。
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serialport::{SerialPort, SerialPortInfo, SerialPortType};
+
+use crate::transport::WRITE_TIMEOUT;
+
+pub const BAUD_RATE: u32 = 115_200;
+
+/// 常见芯片的 USB VID/PID 预设。
+pub const PRESETS: &[(&str, u16, u16)] = &[
+    ("rp2040", 0x2E8A, 0x000A),
+    ("esp32-s3", 0x303A, 0x1001),
+    ("ch340", 0x1A86, 0x7523),
+    ("cp210x", 0x10C4, 0xEA60),
+];
+
+/// 串口设备的匹配条件。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceMatcher {
+    /// 可接受的 `(VID, PID)`。
+    pub ids: Vec<(u16, u16)>,
+    /// 要求的 USB 序列号。
+    pub serial_number: Option<String>,
+    /// 要求 USB 产品字符串包含的内容。
+    pub product: Option<String>,
+    /// 直接指定的串口路径。
+    pub paths: Vec<String>,
+}
+
+impl DeviceMatcher {
+    /// 原有的默认行为：只匹配 RP2040。
+    pub fn rp2040() -> DeviceMatcher {
+        DeviceMatcher {
+            ids: vec![(0x2E8A, 0x000A)], // RP2040的USB VID/PID
+            ..DeviceMatcher::default()
+        }
+    }
+
+    /// 只匹配指定别名（见 [`DeviceEntry::alias`]）的设备：路径形式的别名按路径匹配，其余按序列号匹配。
+    pub fn for_alias(alias: &str) -> DeviceMatcher {
+        if alias.starts_with('/') || alias.to_ascii_uppercase().starts_with("COM") {
+            DeviceMatcher {
+                paths: vec![alias.to_string()],
+                ..DeviceMatcher::default()
+            }
+        } else {
+            DeviceMatcher {
+                serial_number: Some(alias.to_string()),
+                ..DeviceMatcher::default()
+            }
+        }
+    }
+
+    /// 由若干条规则构造匹配条件；没有任何规则时等同于 [`DeviceMatcher::rp2040`]。
+    pub fn from_specs<S: AsRef<str>>(specs: &[S]) -> Result<DeviceMatcher> {
+        if specs.is_empty() {
+            return Ok(DeviceMatcher::rp2040());
+        }
+        let mut matcher = DeviceMatcher::default();
+        for spec in specs {
+            matcher.add_spec(spec.as_ref())?;
+        }
+        Ok(matcher)
+    }
+
+    /// 追加一条规则，写法见模块文档。
+    pub fn add_spec(&mut self, spec: &str) -> Result<()> {
+        let spec = spec.trim();
+        if let Some(serial) = spec.strip_prefix("serial=") {
+            self.serial_number = Some(serial.to_string());
+        } else if let Some(product) = spec.strip_prefix("product=") {
+            self.product = Some(product.to_string());
+        } else if let Some(path) = spec.strip_prefix("path=") {
+            self.paths.push(path.to_string());
+        } else if spec.starts_with('/') || spec.to_ascii_uppercase().starts_with("COM") {
+            self.paths.push(spec.to_string());
+        } else if let Some(&(_, vid, pid)) = PRESETS
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(spec))
+        {
+            self.ids.push((vid, pid));
+        } else if let Some((vid, pid)) = spec.split_once(':') {
+            let parse = |s: &str| {
+                u16::from_str_radix(s.trim_start_matches("0x"), 16)
+                    .with_context(|| format!("无效的 VID/PID: {}", spec))
+            };
+            self.ids.push((parse(vid)?, parse(pid)?));
+        } else {
+            bail!("无法识别的设备规则: {}", spec);
+        }
+        Ok(())
+    }
+
+    /// 判断一个串口是否符合条件。
+    pub fn matches(&self, port: &SerialPortInfo) -> bool {
+        self.matches_entry(&DeviceEntry::from_port_info(port.clone()))
+    }
+
+    /// 同 [`DeviceMatcher::matches`]，作用于枚举结果。
+    pub fn matches_entry(&self, entry: &DeviceEntry) -> bool {
+        if self.paths.contains(&entry.port_name) {
+            return true;
+        }
+        let Some(usb_id) = entry.usb_id else {
+            return false;
+        };
+        if self.ids.is_empty() && self.serial_number.is_none() && self.product.is_none() {
+            return false;
+        }
+        if !self.ids.is_empty() && !self.ids.contains(&usb_id) {
+            return false;
+        }
+        if let Some(serial) = &self.serial_number {
+            if entry.serial_number.as_deref() != Some(serial.as_str()) {
+                return false;
+            }
+        }
+        if let Some(product) = &self.product {
+            let wanted = product.to_lowercase();
+            if !entry
+                .product
+                .as_deref()
+                .is_some_and(|p| p.to_lowercase().contains(&wanted))
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 列出当前所有符合条件的设备，按别名排序。
+    ///
+    /// 直接指定的路径只要存在就会列出，即使它不在系统枚举结果中（例如伪终端）。
+    pub fn enumerate(&self) -> Result<Vec<DeviceEntry>> {
+        let mut found: Vec<DeviceEntry> = available_devices()?
+            .into_iter()
+            .filter(|entry| self.matches_entry(entry))
+            .collect();
+        for path in &self.paths {
+            if Path::new(path).exists() && !found.iter().any(|e| e.port_name == *path) {
+                found.push(DeviceEntry {
+                    port_name: path.clone(),
+                    usb_id: None,
+                    serial_number: None,
+                    product: None,
+                });
+            }
+        }
+        found.sort_by_key(|e| e.alias());
+        Ok(found)
+    }
+
+    /// 列出当前所有符合条件的串口路径。
+    pub fn find_ports(&self) -> Result<Vec<String>> {
+        Ok(self.enumerate()?.into_iter().map(|e| e.port_name).collect())
+    }
+
+    /// 打开第一个符合条件的串口。
+    pub fn open(&self, baud_rate: u32) -> Result<Box<dyn SerialPort>> {
+        let port_name = self
+            .find_ports()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("未找到匹配的设备: {}", self))?;
+        serialport::new(port_name, baud_rate)
+            .timeout(WRITE_TIMEOUT)
+            .open()
+            .map_err(|e| e.into())
+    }
+}
+
+/// 枚举到的一个设备。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceEntry {
+    pub port_name: String,
+    /// USB 设备的 `(VID, PID)`，非 USB 串口为 `None`。
+    pub usb_id: Option<(u16, u16)>,
+    pub serial_number: Option<String>,
+    pub product: Option<String>,
+}
+
+impl DeviceEntry {
+    fn from_port_info(info: SerialPortInfo) -> DeviceEntry {
+        let (usb_id, serial_number, product) = match info.port_type {
+            SerialPortType::UsbPort(usb) => {
+                (Some((usb.vid, usb.pid)), usb.serial_number, usb.product)
+            }
+            _ => (None, None, None),
+        };
+        DeviceEntry {
+            port_name: info.port_name,
+            usb_id,
+            serial_number,
+            product,
+        }
+    }
+
+    /// 设备别名：有 USB 序列号时使用序列号，重新插拔或换口后保持不变；否则退回串口路径。
+    pub fn alias(&self) -> String {
+        self.serial_number
+            .clone()
+            .unwrap_or_else(|| self.port_name.clone())
+    }
+
+    /// 只匹配这一个设备的条件，用于掉线后重新找回同一块屏幕。
+    pub fn matcher(&self) -> DeviceMatcher {
+        DeviceMatcher::for_alias(&self.alias())
+    }
+}
+
+impl std::fmt::Display for DeviceMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts: Vec<String> = self
+            .ids
+            .iter()
+            .map(|(vid, pid)| format!("{:04x}:{:04x}", vid, pid))
+            .collect();
+        parts.extend(self.paths.iter().cloned());
+        if let Some(serial) = &self.serial_number {
+            parts.push(format!("serial={}", serial));
+        }
+        if let Some(product) = &self.product {
+            parts.push(format!("product={}", product));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// 列出系统中所有串口，不做筛选。
+pub fn available_devices() -> Result<Vec<DeviceEntry>> {
+    let mut entries: Vec<DeviceEntry> = serialport::available_ports()?
+        .into_iter()
+        .map(DeviceEntry::from_port_info)
+        .collect();
+    entries.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    Ok(entries)
+}
+
+/// 自动查找第一个 RP2040 串口设备并打开。
+pub fn find_and_open_rp2040() -> Result<Box<dyn SerialPort>> {
+    DeviceMatcher::rp2040().open(BAUD_RATE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usb(port: &str, id: (u16, u16), serial: &str, product: &str) -> DeviceEntry {
+        DeviceEntry {
+            port_name: port.to_string(),
+            usb_id: Some(id),
+            serial_number: Some(serial.to_string()),
+            product: Some(product.to_string()),
+        }
+    }
+
+    #[test]
+    fn parses_specs() {
+        let matcher = DeviceMatcher::from_specs(&[
+            "2e8a:000a",
+            "0x303A:0x1001",
+            "CH340",
+            "serial=E66058",
+            "product=USB-Screen",
+            "/dev/ttyACM3",
+            "path=/tmp/tty",
+            "com7",
+        ])
+        .unwrap();
+        assert_eq!(
+            matcher.ids,
+            [(0x2E8A, 0x000A), (0x303A, 0x1001), (0x1A86, 0x7523)]
+        );
+        assert_eq!(matcher.serial_number.as_deref(), Some("E66058"));
+        assert_eq!(matcher.product.as_deref(), Some("USB-Screen"));
+        assert_eq!(matcher.paths, ["/dev/ttyACM3", "/tmp/tty", "com7"]);
+    }
+
+    #[test]
+    fn rejects_bad_specs() {
+        for spec in ["2e8a", "2e8a:zzzz", "12345:0001", "stm32", ""] {
+            assert!(
+                DeviceMatcher::default().add_spec(spec).is_err(),
+                "{} 应报错",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn empty_specs_default_to_rp2040() {
+        let specs: [&str; 0] = [];
+        assert_eq!(
+            DeviceMatcher::from_specs(&specs).unwrap(),
+            DeviceMatcher::rp2040()
+        );
+    }
+
+    #[test]
+    fn matches_ids_with_extra_conditions() {
+        let pico = usb(
+            "/dev/ttyACM0",
+            (0x2E8A, 0x000A),
+            "E66058",
+            "Pico USB-Screen",
+        );
+        let other = usb("/dev/ttyUSB0", (0x1A86, 0x7523), "X1", "USB Serial");
+        assert!(DeviceMatcher::rp2040().matches_entry(&pico));
+        assert!(!DeviceMatcher::rp2040().matches_entry(&other));
+
+        let matcher = DeviceMatcher::from_specs(&["rp2040", "serial=E66058"]).unwrap();
+        assert!(matcher.matches_entry(&pico));
+        let matcher = DeviceMatcher::from_specs(&["rp2040", "serial=OTHER"]).unwrap();
+        assert!(!matcher.matches_entry(&pico));
+
+        let matcher = DeviceMatcher::from_specs(&["product=usb-screen"]).unwrap();
+        assert!(matcher.matches_entry(&pico));
+        assert!(!matcher.matches_entry(&other));
+    }
+
+    #[test]
+    fn matches_paths_without_usb_info() {
+        let pty = DeviceEntry {
+            port_name: "/dev/pts/3".to_string(),
+            usb_id: None,
+            serial_number: None,
+            product: None,
+        };
+        assert!(DeviceMatcher::from_specs(&["/dev/pts/3"])
+            .unwrap()
+            .matches_entry(&pty));
+        assert!(!DeviceMatcher::rp2040().matches_entry(&pty));
+        assert!(!DeviceMatcher::default().matches_entry(&pty));
+    }
+
+    #[test]
+    fn alias_matcher_finds_the_same_device() {
+        let pico = usb("/dev/ttyACM0", (0x2E8A, 0x000A), "E66058", "Pico");
+        assert_eq!(pico.alias(), "E66058");
+        let moved = DeviceEntry {
+            port_name: "/dev/ttyACM1".to_string(),
+            ..pico.clone()
+        };
+        assert!(pico.matcher().matches_entry(&moved));
+
+        let pty = DeviceEntry {
+            serial_number: None,
+            ..pico
+        };
+        assert_eq!(pty.alias(), "/dev/ttyACM0");
+        assert_eq!(pty.matcher().paths, ["/dev/ttyACM0"]);
+    }
+}
@@ -0,0 +1,279 @@
+//! 相邻两帧之间的脏矩形计算。
+//!
+//! 先按固定大小的块比较新旧帧，再把脏块拼成矩形，最后按代价模型合并：
+//! 每个矩形的代价是像素字节数加上一个固定开销（包头、CRC、矩形头），
+//! 两个矩形合并后的代价不高于分开发送时就合并。
+
+use crate::protocol::{CRC_LEN, HEADER_LEN, RECT_HEADER_LEN};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// 同时包含两个矩形的最小矩形。
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
+
+/// 脏矩形的代价模型参数。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirtyConfig {
+    /// 比较时使用的块边长（像素）。
+    pub tile_size: u32,
+    /// 每个矩形的固定开销（字节）。
+    pub rect_overhead: u64,
+    /// 单帧最多发送的矩形数，超出时继续合并代价增加最少的两个。
+    pub max_rects: usize,
+    /// 脏区代价超过整帧代价的该比例时直接发送整帧。
+    pub full_frame_ratio: f64,
+    /// 每隔多少帧强制发送一次整帧，用于纠正丢包造成的残影；0 表示从不。
+    pub keyframe_interval: u32,
+}
+
+impl Default for DirtyConfig {
+    fn default() -> DirtyConfig {
+        DirtyConfig {
+            tile_size: 16,
+            rect_overhead: (HEADER_LEN + RECT_HEADER_LEN + CRC_LEN) as u64,
+            max_rects: 16,
+            full_frame_ratio: 0.7,
+            keyframe_interval: 300,
+        }
+    }
+}
+
+impl DirtyConfig {
+    /// 以 `bytes_per_pixel` 计算矩形的发送代价。
+    pub fn cost(&self, rect: &Rect, bytes_per_pixel: u32) -> u64 {
+        rect.area() * bytes_per_pixel as u64 + self.rect_overhead
+    }
+}
+
+/// 比较两帧（相同尺寸、逐像素 `bytes_per_pixel` 字节）并返回合并后的脏矩形。
+///
+/// 两帧相同时返回空列表。
+pub fn diff(
+    prev: &[u8],
+    next: &[u8],
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u32,
+    config: &DirtyConfig,
+) -> Vec<Rect> {
+    let tile = config.tile_size.max(1);
+    let cols = width.div_ceil(tile);
+    let rows = height.div_ceil(tile);
+    let stride = (width * bytes_per_pixel) as usize;
+
+    // 按块行扫描，把连续的脏块拼成横向的条带
+    let mut rects: Vec<Rect> = Vec::new();
+    for row in 0..rows {
+        let y = row * tile;
+        let h = tile.min(height - y);
+        let mut run: Option<Rect> = None;
+        for col in 0..cols {
+            let x = col * tile;
+            let w = tile.min(width - x);
+            let dirty = (y..y + h).any(|line| {
+                let start = line as usize * stride + (x * bytes_per_pixel) as usize;
+                let end = start + (w * bytes_per_pixel) as usize;
+                prev[start..end] != next[start..end]
+            });
+            match (dirty, run.as_mut()) {
+                (true, Some(r)) => r.width += w,
+                (true, None) => run = Some(Rect::new(x, y, w, h)),
+                (false, Some(_)) => rects.extend(run.take()),
+                (false, None) => {}
+            }
+        }
+        rects.extend(run);
+    }
+
+    // 上下相邻且横向范围相同的条带直接拼接
+    let mut stacked: Vec<Rect> = Vec::with_capacity(rects.len());
+    for rect in rects {
+        match stacked
+            .iter_mut()
+            .find(|r| r.x == rect.x && r.width == rect.width && r.y + r.height == rect.y)
+        {
+            Some(r) => r.height += rect.height,
+            None => stacked.push(rect),
+        }
+    }
+
+    merge(stacked, bytes_per_pixel, config)
+}
+
+/// 按代价模型合并矩形。
+fn merge(mut rects: Vec<Rect>, bytes_per_pixel: u32, config: &DirtyConfig) -> Vec<Rect> {
+    loop {
+        // 找出合并收益最大（代价增加最少）的一对
+        let mut best: Option<(usize, usize, i64)> = None;
+        for i in 0..rects.len() {
+            for j in i + 1..rects.len() {
+                let separate = config.cost(&rects[i], bytes_per_pixel)
+                    + config.cost(&rects[j], bytes_per_pixel);
+                let merged = config.cost(&rects[i].union(&rects[j]), bytes_per_pixel);
+                let delta = merged as i64 - separate as i64;
+                if best.is_none_or(|(_, _, d)| delta < d) {
+                    best = Some((i, j, delta));
+                }
+            }
+        }
+        match best {
+            Some((i, j, delta)) if delta <= 0 || rects.len() > config.max_rects => {
+                let other = rects.swap_remove(j);
+                rects[i] = rects[i].union(&other);
+            }
+            _ => return rects,
+        }
+    }
+}
+
+/// 从整帧缓冲中拷贝出矩形区域的像素。
+pub fn extract(frame: &[u8], width: u32, bytes_per_pixel: u32, rect: &Rect) -> Vec<u8> {
+    let stride = (width * bytes_per_pixel) as usize;
+    let row_len = (rect.width * bytes_per_pixel) as usize;
+    let mut out = Vec::with_capacity(row_len * rect.height as usize);
+    for line in rect.y..rect.y + rect.height {
+        let start = line as usize * stride + (rect.x * bytes_per_pixel) as usize;
+        out.extend_from_slice(&frame[start..start + row_len]);
+    }
+    out
+}
+
+/// 把矩形区域的像素写回整帧缓冲，`extract` 的逆操作。
+pub fn blit(frame: &mut [u8], width: u32, bytes_per_pixel: u32, rect: &Rect, pixels: &[u8]) {
+    let stride = (width * bytes_per_pixel) as usize;
+    let row_len = (rect.width * bytes_per_pixel) as usize;
+    for (i, line) in (rect.y..rect.y + rect.height).enumerate() {
+        let start = line as usize * stride + (rect.x * bytes_per_pixel) as usize;
+        frame[start..start + row_len].copy_from_slice(&pixels[i * row_len..(i + 1) * row_len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+    const BPP: u32 = 2;
+
+    fn frame() -> Vec<u8> {
+        (0..WIDTH * HEIGHT * BPP).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// 修改 `(x, y)` 处的一个像素。
+    fn touch(frame: &mut [u8], x: u32, y: u32) {
+        let i = ((y * WIDTH + x) * BPP) as usize;
+        frame[i] ^= 0xFF;
+    }
+
+    fn covers(rects: &[Rect], x: u32, y: u32) -> bool {
+        rects
+            .iter()
+            .any(|r| (r.x..r.x + r.width).contains(&x) && (r.y..r.y + r.height).contains(&y))
+    }
+
+    #[test]
+    fn identical_frames_have_no_rects() {
+        let prev = frame();
+        let rects = diff(&prev, &prev, WIDTH, HEIGHT, BPP, &DirtyConfig::default());
+        assert!(rects.is_empty());
+    }
+
+    #[test]
+    fn one_changed_tile_gives_one_rect() {
+        let prev = frame();
+        let mut next = prev.clone();
+        touch(&mut next, 20, 35);
+        touch(&mut next, 31, 32);
+        let rects = diff(&prev, &next, WIDTH, HEIGHT, BPP, &DirtyConfig::default());
+        assert_eq!(rects, [Rect::new(16, 32, 16, 16)]);
+    }
+
+    #[test]
+    fn keeps_distant_rects_apart_within_max_rects() {
+        let prev = frame();
+        let mut next = prev.clone();
+        let points = [(0, 0), (63, 0), (0, 47), (63, 47)];
+        for (x, y) in points {
+            touch(&mut next, x, y);
+        }
+        let config = DirtyConfig::default();
+        let rects = diff(&prev, &next, WIDTH, HEIGHT, BPP, &config);
+        assert_eq!(rects.len(), 4);
+
+        let config = DirtyConfig {
+            max_rects: 2,
+            ..config
+        };
+        let rects = diff(&prev, &next, WIDTH, HEIGHT, BPP, &config);
+        assert!(rects.len() <= 2);
+        assert!(points.iter().all(|&(x, y)| covers(&rects, x, y)));
+    }
+
+    #[test]
+    fn merges_when_overhead_dominates() {
+        let prev = frame();
+        let mut next = prev.clone();
+        touch(&mut next, 0, 0);
+        touch(&mut next, 40, 20);
+        let config = DirtyConfig {
+            rect_overhead: 1 << 20,
+            ..DirtyConfig::default()
+        };
+        let rects = diff(&prev, &next, WIDTH, HEIGHT, BPP, &config);
+        assert_eq!(rects, [Rect::new(0, 0, 48, 32)]);
+    }
+
+    #[test]
+    fn extract_then_blit_reproduces_next_frame() {
+        let prev = frame();
+        let mut next = prev.clone();
+        for (x, y) in [(3, 3), (50, 10), (10, 40), (33, 33)] {
+            touch(&mut next, x, y);
+        }
+        let rects = diff(&prev, &next, WIDTH, HEIGHT, BPP, &DirtyConfig::default());
+        let mut rebuilt = prev.clone();
+        for rect in &rects {
+            let pixels = extract(&next, WIDTH, BPP, rect);
+            assert_eq!(pixels.len() as u64, rect.area() * BPP as u64);
+            blit(&mut rebuilt, WIDTH, BPP, rect, &pixels);
+        }
+        assert_eq!(rebuilt, next);
+    }
+
+    #[test]
+    fn union_and_cost() {
+        let a = Rect::new(0, 0, 4, 4);
+        let b = Rect::new(10, 2, 2, 8);
+        assert_eq!(a.union(&b), Rect::new(0, 0, 12, 10));
+        let config = DirtyConfig::default();
+        assert_eq!(config.cost(&a, 2), 32 + config.rect_overhead);
+    }
+}
@@ -0,0 +1,427 @@
+//! 降低色深时的抖动。
+//!
+//! RGB565 每个通道只有 32 或 64 级，直接截掉低位会在渐变上出现明显的色带，
+//! 灰度、单色和索引色更明显。
+//! 抖动把量化误差分散成细小的噪点，远看更接近原图：
+//!
+//! - `none`：截断低位（原有行为）；
+//! - `round`：四舍五入到最近的一级，不加噪点；
+//! - `bayer4`、`bayer8`：有序抖动，图案固定，动画不闪烁；
+//! - `floyd-steinberg`：误差扩散，静态图片效果最好，动画中噪点会跳动；
+//! - `blue-noise`：蓝噪声阈值，噪点均匀，没有 Bayer 的网格感。
+//!
+//! [`Dither::apply`] 只修改像素值，把每个像素换成目标色深下可以显示的颜色
+//! （见 [`Quantize`]），编码时按截断或查调色板的方式得到的就是这个颜色。
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Result};
+use image::RgbaImage;
+
+/// 蓝噪声阈值图的边长。
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// 抖动方式，见模块文档。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    #[default]
+    None,
+    Round,
+    Bayer4,
+    Bayer8,
+    FloydSteinberg,
+    BlueNoise,
+}
+
+impl Dither {
+    const NAMES: [(Dither, &'static str); 6] = [
+        (Dither::None, "none"),
+        (Dither::Round, "round"),
+        (Dither::Bayer4, "bayer4"),
+        (Dither::Bayer8, "bayer8"),
+        (Dither::FloydSteinberg, "floyd-steinberg"),
+        (Dither::BlueNoise, "blue-noise"),
+    ];
+
+    /// 把图片量化为 `levels` 中的颜色，结果写回图片。`None` 不做任何处理。
+    pub fn apply(&self, image: &mut RgbaImage, levels: &dyn Quantize) {
+        match self {
+            Dither::None => {}
+            Dither::Round => ordered(image, levels, |_, _| 0.5),
+            Dither::Bayer4 => ordered(image, levels, |x, y| bayer(4, x, y)),
+            Dither::Bayer8 => ordered(image, levels, |x, y| bayer(8, x, y)),
+            Dither::BlueNoise => {
+                let map = blue_noise();
+                ordered(image, levels, |x, y| {
+                    map[(y as usize % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE
+                        + x as usize % BLUE_NOISE_SIZE]
+                })
+            }
+            Dither::FloydSteinberg => floyd_steinberg(image, levels),
+        }
+    }
+}
+
+impl fmt::Display for Dither {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = Dither::NAMES.iter().find(|(d, _)| d == self).unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Dither {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Dither> {
+        let name = s.trim().to_ascii_lowercase().replace('_', "-");
+        let name = match name.as_str() {
+            "fs" | "floydsteinberg" => "floyd-steinberg",
+            "bluenoise" => "blue-noise",
+            other => other,
+        };
+        match Dither::NAMES.iter().find(|(_, n)| *n == name) {
+            Some((dither, _)) => Ok(*dither),
+            None => bail!(
+                "未知的抖动方式: {}（可选 none、round、bayer4、bayer8、floyd-steinberg、blue-noise）",
+                s.trim()
+            ),
+        }
+    }
+}
+
+/// 目标色深下可以显示的颜色。
+pub trait Quantize {
+    /// 离 `color` 最近的可显示颜色。
+    fn nearest(&self, color: [f32; 3]) -> [u8; 3];
+    /// 每个通道相邻两级之间的差值，决定有序抖动的幅度。
+    fn step(&self) -> [f32; 3];
+}
+
+/// 每个通道独立量化到给定位数，例如 RGB565 为 `[5, 6, 5]`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelBits(pub [u32; 3]);
+
+impl ChannelBits {
+    fn levels(bits: u32) -> f32 {
+        ((1u32 << bits.clamp(1, 8)) - 1) as f32
+    }
+}
+
+impl Quantize for ChannelBits {
+    fn nearest(&self, color: [f32; 3]) -> [u8; 3] {
+        let mut out = [0u8; 3];
+        for ((out, value), bits) in out.iter_mut().zip(color).zip(self.0) {
+            let bits = bits.clamp(1, 8);
+            let max = ChannelBits::levels(bits);
+            let level = (value * max / 255.0).round().clamp(0.0, max) as u32;
+            // 按位复制补齐低位，与 rgb565_to_rgba 的还原方式一致
+            let mut expanded = level << (8 - bits);
+            let mut filled = bits;
+            while filled < 8 {
+                expanded |= expanded >> filled;
+                filled *= 2;
+            }
+            *out = expanded as u8;
+        }
+        out
+    }
+
+    fn step(&self) -> [f32; 3] {
+        self.0.map(|bits| 255.0 / ChannelBits::levels(bits))
+    }
+}
+
+/// 有序抖动：按阈值 `t`（0 到 1）把颜色偏移至多半级后取最近的颜色。
+fn ordered(image: &mut RgbaImage, levels: &dyn Quantize, threshold: impl Fn(u32, u32) -> f32) {
+    let step = levels.step();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let t = threshold(x, y) - 0.5;
+        let color = [0, 1, 2].map(|c| pixel[c] as f32 + t * step[c]);
+        let [r, g, b] = levels.nearest(color);
+        pixel.0 = [r, g, b, pixel[3]];
+    }
+}
+
+/// Bayer 矩阵的阈值，`size` 为 2 的幂。
+fn bayer(size: u32, x: u32, y: u32) -> f32 {
+    let (mut x, mut y) = (x % size, y % size);
+    let mut value = 0;
+    let mut bit = size * size / 4;
+    while bit > 0 {
+        // 每一层按 0 2 / 3 1 的顺序排列
+        value += bit * [[0, 2], [3, 1]][(y & 1) as usize][(x & 1) as usize];
+        x >>= 1;
+        y >>= 1;
+        bit /= 4;
+    }
+    (value as f32 + 0.5) / (size * size) as f32
+}
+
+/// Floyd–Steinberg 误差扩散，奇数行从右往左扫描以减少方向性纹理。
+fn floyd_steinberg(image: &mut RgbaImage, levels: &dyn Quantize) {
+    let width = image.width() as usize;
+    let mut current = vec![[0.0f32; 3]; width + 2];
+    let mut next = vec![[0.0f32; 3]; width + 2];
+    for y in 0..image.height() {
+        let reverse = y % 2 == 1;
+        for i in 0..width {
+            let x = if reverse { width - 1 - i } else { i };
+            let pixel = image.get_pixel_mut(x as u32, y);
+            // 误差缓冲左右各留一格，下标整体加一
+            let k = x + 1;
+            let (ahead, behind) = if reverse {
+                (k - 1, k + 1)
+            } else {
+                (k + 1, k - 1)
+            };
+            let wanted = [0, 1, 2].map(|c| pixel[c] as f32 + current[k][c]);
+            let actual = levels.nearest(wanted);
+            for c in 0..3 {
+                pixel[c] = actual[c];
+                let error = wanted[c] - actual[c] as f32;
+                current[ahead][c] += error * 7.0 / 16.0;
+                next[behind][c] += error * 3.0 / 16.0;
+                next[k][c] += error * 5.0 / 16.0;
+                next[ahead][c] += error / 16.0;
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+        next.fill([0.0; 3]);
+    }
+}
+
+/// 用 void-and-cluster 算法生成的蓝噪声阈值图，首次使用时计算。
+fn blue_noise() -> &'static [f32] {
+    static MAP: OnceLock<Vec<f32>> = OnceLock::new();
+    MAP.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE))
+}
+
+/// 在 `size` x `size` 的环面上生成阈值图（Ulichney 1993）：
+/// 反复把最密集处的点挪到最空旷处得到均匀的初始图案，
+/// 再按“去掉最密集的点”“填上最空旷的位置”的顺序给每个像素排名。
+fn void_and_cluster(size: usize) -> Vec<f32> {
+    const SIGMA: f32 = 1.5;
+    let n = size * size;
+    // 环面上两点间距离对应的高斯权重
+    let kernel: Vec<f32> = (0..n)
+        .map(|i| {
+            let wrap = |d: usize| d.min(size - d) as f32;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+    let toggle = |energy: &mut [f32], p: usize, sign: f32| {
+        let (px, py) = (p % size, p / size);
+        for (q, e) in energy.iter_mut().enumerate() {
+            let dx = (q % size + size - px) % size;
+            let dy = (q / size + size - py) % size;
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+    let extreme = |energy: &[f32], set: &[bool], want: bool, max: bool| -> usize {
+        let candidates = (0..n).filter(|&i| set[i] == want);
+        if max {
+            candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        } else {
+            candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        }
+        .unwrap()
+    };
+
+    // 初始图案：约十分之一的点，用固定种子的 xorshift 撒点
+    let mut pattern = vec![false; n];
+    let mut energy = vec![0.0f32; n];
+    let mut state = 0x9e37_79b9u32;
+    let mut ones = 0;
+    while ones < n / 10 {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let p = state as usize % n;
+        if !pattern[p] {
+            pattern[p] = true;
+            toggle(&mut energy, p, 1.0);
+            ones += 1;
+        }
+    }
+    loop {
+        let cluster = extreme(&energy, &pattern, true, true);
+        pattern[cluster] = false;
+        toggle(&mut energy, cluster, -1.0);
+        let void = extreme(&energy, &pattern, false, false);
+        pattern[void] = true;
+        toggle(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; n];
+    // 第一阶段：从初始图案中逐个去掉最密集的点，排名从高到低
+    let mut set = pattern.clone();
+    let mut e = energy.clone();
+    for r in (0..ones).rev() {
+        let cluster = extreme(&e, &set, true, true);
+        set[cluster] = false;
+        toggle(&mut e, cluster, -1.0);
+        rank[cluster] = r;
+    }
+    // 第二阶段：从初始图案开始逐个填上最空旷的位置，直到填满
+    for r in ones..n {
+        let void = extreme(&energy, &pattern, false, false);
+        pattern[void] = true;
+        toggle(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+    rank.into_iter()
+        .map(|r| (r as f32 + 0.5) / n as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::path::PathBuf;
+
+    /// 每通道 3 位，色带足够明显。
+    const BITS: [u32; 3] = [3, 3, 3];
+    /// 衡量色带时取平均的方块边长，与 bayer8 的周期一致。
+    const BLOCK: u32 = 8;
+
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(256, 16, |x, y| {
+            Rgba([x as u8, 255 - x as u8, (y * 16) as u8, 255])
+        })
+    }
+
+    /// 屏幕上实际显示的颜色：抖动后再按编码方式截掉低位，未抖动的图片在这里出现色带。
+    fn shown(dither: Dither) -> RgbaImage {
+        let mut image = gradient();
+        dither.apply(&mut image, &ChannelBits(BITS));
+        let levels = ChannelBits(BITS);
+        let step = levels.step();
+        for pixel in image.pixels_mut() {
+            let truncated = [0, 1, 2].map(|c| (pixel[c] >> (8 - BITS[c])) as f32 * step[c]);
+            let [r, g, b] = levels.nearest(truncated);
+            pixel.0 = [r, g, b, pixel[3]];
+        }
+        image
+    }
+
+    /// 色带程度：按方块取平均后与原图的平均绝对误差。抖动保留了局部的平均亮度，这个值应该很小。
+    fn banding(image: &RgbaImage) -> f64 {
+        let original = gradient();
+        let (mut total, mut count) = (0.0, 0);
+        for by in (0..image.height()).step_by(BLOCK as usize) {
+            for bx in (0..image.width()).step_by(BLOCK as usize) {
+                for c in 0..3 {
+                    let sum = |img: &RgbaImage| -> f64 {
+                        (by..by + BLOCK)
+                            .flat_map(|y| (bx..bx + BLOCK).map(move |x| (x, y)))
+                            .map(|(x, y)| img.get_pixel(x, y)[c] as f64)
+                            .sum()
+                    };
+                    total += (sum(image) - sum(&original)).abs() / (BLOCK * BLOCK) as f64;
+                    count += 1;
+                }
+            }
+        }
+        total / count as f64
+    }
+
+    fn fixture(dither: Dither) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(format!("gradient-{}.png", dither))
+    }
+
+    /// 与仓库中的参考图逐像素比较；设置 `UPDATE_FIXTURES=1` 时重新生成参考图。
+    #[test]
+    fn matches_reference_images() {
+        for (dither, _) in Dither::NAMES {
+            let image = shown(dither);
+            let path = fixture(dither);
+            if std::env::var_os("UPDATE_FIXTURES").is_some() {
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                image.save(&path).unwrap();
+                continue;
+            }
+            let reference = image::open(&path)
+                .unwrap_or_else(|err| panic!("无法读取参考图 {}: {}", path.display(), err))
+                .to_rgba8();
+            assert!(
+                reference == image,
+                "{} 的输出与参考图 {} 不一致",
+                dither,
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn dithering_beats_rounding() {
+        let none = banding(&shown(Dither::None));
+        let round = banding(&shown(Dither::Round));
+        assert!(round < none, "round {:.2} 应优于截断 {:.2}", round, none);
+        for dither in [
+            Dither::Bayer4,
+            Dither::Bayer8,
+            Dither::FloydSteinberg,
+            Dither::BlueNoise,
+        ] {
+            let error = banding(&shown(dither));
+            assert!(
+                error < round / 2.0,
+                "{} 的色带误差 {:.2} 应明显小于 round 的 {:.2}",
+                dither,
+                error,
+                round
+            );
+        }
+    }
+
+    #[test]
+    fn outputs_only_displayable_colors() {
+        let levels = ChannelBits(BITS);
+        for (dither, _) in Dither::NAMES.into_iter().skip(1) {
+            let mut image = gradient();
+            dither.apply(&mut image, &levels);
+            for pixel in image.pixels() {
+                let color = [pixel[0], pixel[1], pixel[2]];
+                assert_eq!(levels.nearest(color.map(|v| v as f32)), color, "{}", dither);
+            }
+        }
+    }
+
+    #[test]
+    fn bayer_thresholds_cover_every_rank() {
+        for size in [4, 8] {
+            let mut ranks: Vec<u32> = (0..size * size)
+                .map(|i| (bayer(size, i % size, i / size) * (size * size) as f32) as u32)
+                .collect();
+            ranks.sort();
+            assert_eq!(ranks, (0..size * size).collect::<Vec<_>>());
+        }
+        let mut map = blue_noise().to_vec();
+        map.sort_by(f32::total_cmp);
+        let n = map.len() as f32;
+        assert!(map
+            .iter()
+            .enumerate()
+            .all(|(i, t)| *t == (i as f32 + 0.5) / n));
+    }
+
+    #[test]
+    fn parses_names() {
+        for (dither, name) in Dither::NAMES {
+            assert_eq!(name.parse::<Dither>().unwrap(), dither);
+            assert_eq!(dither.to_string(), name);
+        }
+        assert_eq!("FS".parse::<Dither>().unwrap(), Dither::FloydSteinberg);
+        assert_eq!("blue_noise".parse::<Dither>().unwrap(), Dither::BlueNoise);
+        assert!("random".parse::<Dither>().is_err());
+    }
+}
@@ -0,0 +1,507 @@
+//! 图片与屏幕宽高比不一致时的处理方式。
+//!
+//! - `stretch`：拉伸到屏幕尺寸，会变形；
+//! - `contain`：完整显示，空白处填充背景色或模糊的图片本身；
+//! - `cover`：铺满屏幕，按 [`Gravity`] 裁掉多余部分；
+//! - `center`：按原始尺寸显示，不缩放；
+//! - `tile`：按原始尺寸从左上角平铺。
+//!
+//! 带透明度的图片以预乘 alpha 的形式传入，见 [`crate::alpha`]。
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+
+use crate::alpha;
+
+/// 模糊背景先缩小到这个比例再放大，省时间也更柔和。
+const BLUR_DOWNSCALE: u32 = 8;
+const BLUR_SIGMA: f32 = 2.0;
+
+/// 缩放方式，见模块文档。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitMode {
+    #[default]
+    Stretch,
+    Contain,
+    Cover,
+    Center,
+    Tile,
+}
+
+impl fmt::Display for FitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FitMode::Stretch => "stretch",
+            FitMode::Contain => "contain",
+            FitMode::Cover => "cover",
+            FitMode::Center => "center",
+            FitMode::Tile => "tile",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for FitMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<FitMode> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "stretch" => FitMode::Stretch,
+            "contain" | "letterbox" => FitMode::Contain,
+            "cover" | "crop" => FitMode::Cover,
+            "center" | "centre" => FitMode::Center,
+            "tile" => FitMode::Tile,
+            other => bail!(
+                "未知的缩放方式: {}（可选 stretch、contain、cover、center、tile）",
+                other
+            ),
+        })
+    }
+}
+
+/// 图片在屏幕中的对齐位置：`cover` 保留哪一部分，`contain` 和 `center` 放在哪里。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Gravity {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Gravity {
+    const NAMES: [(Gravity, &'static str); 9] = [
+        (Gravity::Center, "center"),
+        (Gravity::Top, "top"),
+        (Gravity::Bottom, "bottom"),
+        (Gravity::Left, "left"),
+        (Gravity::Right, "right"),
+        (Gravity::TopLeft, "top-left"),
+        (Gravity::TopRight, "top-right"),
+        (Gravity::BottomLeft, "bottom-left"),
+        (Gravity::BottomRight, "bottom-right"),
+    ];
+
+    /// 横向、纵向的位置：0 靠左（上），1 居中，2 靠右（下）。
+    fn weights(&self) -> (i64, i64) {
+        match self {
+            Gravity::Center => (1, 1),
+            Gravity::Top => (1, 0),
+            Gravity::Bottom => (1, 2),
+            Gravity::Left => (0, 1),
+            Gravity::Right => (2, 1),
+            Gravity::TopLeft => (0, 0),
+            Gravity::TopRight => (2, 0),
+            Gravity::BottomLeft => (0, 2),
+            Gravity::BottomRight => (2, 2),
+        }
+    }
+
+    /// `inner` 放进 `outer` 时左上角的坐标；`inner` 更大时为负数。
+    fn offset(&self, outer: (u32, u32), inner: (u32, u32)) -> (i64, i64) {
+        let (wx, wy) = self.weights();
+        (
+            (outer.0 as i64 - inner.0 as i64) * wx / 2,
+            (outer.1 as i64 - inner.1 as i64) * wy / 2,
+        )
+    }
+}
+
+impl fmt::Display for Gravity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = Gravity::NAMES.iter().find(|(g, _)| g == self).unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Gravity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Gravity> {
+        let name = s.trim().to_ascii_lowercase().replace('_', "-");
+        match Gravity::NAMES.iter().find(|(_, n)| *n == name) {
+            Some((gravity, _)) => Ok(*gravity),
+            None => bail!(
+                "未知的对齐位置: {}（可选 center、top、bottom、left、right、top-left 等）",
+                s.trim()
+            ),
+        }
+    }
+}
+
+/// `contain` 和 `center` 留下的空白如何填充。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Color(Rgba<u8>),
+    /// 把图片本身铺满屏幕并模糊，作为背景。
+    Blur,
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::Color(Rgba([0, 0, 0, 255]))
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Background::Color(Rgba([r, g, b, _])) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            Background::Blur => write!(f, "blur"),
+        }
+    }
+}
+
+impl FromStr for Background {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Background> {
+        if s.trim().eq_ignore_ascii_case("blur") {
+            return Ok(Background::Blur);
+        }
+        Ok(Background::Color(parse_color(s)?))
+    }
+}
+
+/// 解析颜色：`#rrggbb`、`#rgb` 或 `black`、`white`、`gray`。
+pub fn parse_color(s: &str) -> Result<Rgba<u8>> {
+    let s = s.trim();
+    let [r, g, b] = match s.to_ascii_lowercase().as_str() {
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "gray" | "grey" => [128, 128, 128],
+        other => {
+            let hex = other.strip_prefix('#').unwrap_or(other);
+            let value = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 3 || hex.len() == 6)
+                .with_context(|| format!("无效的颜色: {}（应为 #rrggbb 或 #rgb）", s))?;
+            if hex.len() == 3 {
+                let expand = |v: u32| (v & 0xf) as u8 * 17;
+                [expand(value >> 8), expand(value >> 4), expand(value)]
+            } else {
+                [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+            }
+        }
+    };
+    Ok(Rgba([r, g, b, 255]))
+}
+
+/// 缩放方式及其参数。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Fit {
+    pub mode: FitMode,
+    pub background: Background,
+    pub gravity: Gravity,
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            FitMode::Stretch | FitMode::Tile => write!(f, "{}", self.mode),
+            FitMode::Cover => write!(f, "{}-{}", self.mode, self.gravity),
+            FitMode::Contain | FitMode::Center => {
+                write!(f, "{}-{}-{}", self.mode, self.gravity, self.background)
+            }
+        }
+    }
+}
+
+impl Fit {
+    /// 把图片放进 `width` x `height` 的画面。
+    pub fn apply(
+        &self,
+        image: &RgbaImage,
+        width: u32,
+        height: u32,
+        filter: FilterType,
+    ) -> RgbaImage {
+        let size = (width, height);
+        match self.mode {
+            FitMode::Stretch => imageops::resize(image, width, height, filter),
+            FitMode::Contain => {
+                let inner = contain_size(image.dimensions(), size);
+                let scaled = imageops::resize(image, inner.0, inner.1, filter);
+                let mut canvas = self.canvas(image, size);
+                let (x, y) = self.gravity.offset(size, inner);
+                alpha::over(&mut canvas, &scaled, x, y);
+                canvas
+            }
+            FitMode::Cover => cover(image, size, filter, self.gravity),
+            FitMode::Center => {
+                let mut canvas = self.canvas(image, size);
+                let (x, y) = self.gravity.offset(size, image.dimensions());
+                alpha::over(&mut canvas, image, x, y);
+                canvas
+            }
+            FitMode::Tile => {
+                let mut canvas = RgbaImage::new(width, height);
+                imageops::tile(&mut canvas, image);
+                canvas
+            }
+        }
+    }
+
+    fn canvas(&self, image: &RgbaImage, (width, height): (u32, u32)) -> RgbaImage {
+        match self.background {
+            Background::Color(color) => RgbaImage::from_pixel(width, height, color),
+            Background::Blur => {
+                let small = (
+                    (width / BLUR_DOWNSCALE).max(1),
+                    (height / BLUR_DOWNSCALE).max(1),
+                );
+                let small = cover(image, small, FilterType::Triangle, Gravity::Center);
+                let small = imageops::fast_blur(&small, BLUR_SIGMA);
+                imageops::resize(&small, width, height, FilterType::Triangle)
+            }
+        }
+    }
+}
+
+/// 等比缩放到铺满 `size`，再按 `gravity` 裁剪。
+pub(crate) fn cover(
+    image: &RgbaImage,
+    size: (u32, u32),
+    filter: FilterType,
+    gravity: Gravity,
+) -> RgbaImage {
+    let inner = cover_size(image.dimensions(), size);
+    let scaled = imageops::resize(image, inner.0, inner.1, filter);
+    let (x, y) = gravity.offset(size, inner);
+    imageops::crop_imm(&scaled, (-x) as u32, (-y) as u32, size.0, size.1).to_image()
+}
+
+/// 等比缩放到完整放入 `target` 的尺寸。
+fn contain_size((width, height): (u32, u32), target: (u32, u32)) -> (u32, u32) {
+    let scale = f64::min(
+        target.0 as f64 / width.max(1) as f64,
+        target.1 as f64 / height.max(1) as f64,
+    );
+    (
+        ((width as f64 * scale).round() as u32).clamp(1, target.0),
+        ((height as f64 * scale).round() as u32).clamp(1, target.1),
+    )
+}
+
+/// 等比缩放到铺满 `target` 的尺寸，至少与 `target` 一样大。
+fn cover_size((width, height): (u32, u32), target: (u32, u32)) -> (u32, u32) {
+    let scale = f64::max(
+        target.0 as f64 / width.max(1) as f64,
+        target.1 as f64 / height.max(1) as f64,
+    );
+    (
+        ((width as f64 * scale).round() as u32).max(target.0),
+        ((height as f64 * scale).round() as u32).max(target.1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    /// 背景色，与图片中的颜色都不同。
+    const GRAY: Rgba<u8> = Rgba([128, 128, 128, 255]);
+
+    fn fit(mode: FitMode, gravity: Gravity) -> Fit {
+        Fit {
+            mode,
+            background: Background::Color(GRAY),
+            gravity,
+        }
+    }
+
+    fn apply(fit: Fit, image: &RgbaImage) -> RgbaImage {
+        fit.apply(image, 20, 20, FilterType::Nearest)
+    }
+
+    /// 40x10，四条 10 像素宽的竖条：红、绿、蓝、白。
+    fn stripes() -> RgbaImage {
+        RgbaImage::from_fn(40, 10, |x, _| [RED, GREEN, BLUE, WHITE][x as usize / 10])
+    }
+
+    fn solid(width: u32, height: u32, color: Rgba<u8>) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, color)
+    }
+
+    #[test]
+    fn output_always_matches_target_size() {
+        let sources = [
+            stripes(),
+            solid(10, 40, RED),
+            solid(1, 1, RED),
+            solid(64, 64, RED),
+        ];
+        let modes = [
+            FitMode::Stretch,
+            FitMode::Contain,
+            FitMode::Cover,
+            FitMode::Center,
+            FitMode::Tile,
+        ];
+        for source in &sources {
+            for mode in modes {
+                for background in [Background::Color(GRAY), Background::Blur] {
+                    let fit = Fit {
+                        mode,
+                        background,
+                        gravity: Gravity::BottomRight,
+                    };
+                    let out = fit.apply(source, 20, 12, FilterType::Triangle);
+                    assert_eq!(
+                        out.dimensions(),
+                        (20, 12),
+                        "{} {:?}",
+                        fit,
+                        source.dimensions()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn contain_letterboxes_wide_and_pillarboxes_tall() {
+        assert_eq!(contain_size((40, 10), (20, 20)), (20, 5));
+        assert_eq!(contain_size((10, 40), (20, 20)), (5, 20));
+        // 宽图居中：第 7 到 11 行是图片，上下是背景
+        let out = apply(fit(FitMode::Contain, Gravity::Center), &solid(40, 10, RED));
+        for y in 0..20 {
+            let expected = if (7..12).contains(&y) { RED } else { GRAY };
+            assert_eq!(*out.get_pixel(10, y), expected, "y={}", y);
+        }
+        let out = apply(fit(FitMode::Contain, Gravity::Center), &solid(10, 40, RED));
+        for x in 0..20 {
+            let expected = if (7..12).contains(&x) { RED } else { GRAY };
+            assert_eq!(*out.get_pixel(x, 10), expected, "x={}", x);
+        }
+    }
+
+    #[test]
+    fn gravity_places_contained_image() {
+        let image = solid(40, 10, RED);
+        let top = apply(fit(FitMode::Contain, Gravity::TopLeft), &image);
+        assert_eq!(*top.get_pixel(0, 0), RED);
+        assert_eq!(*top.get_pixel(0, 5), GRAY);
+        let bottom = apply(fit(FitMode::Contain, Gravity::BottomRight), &image);
+        assert_eq!(*bottom.get_pixel(19, 19), RED);
+        assert_eq!(*bottom.get_pixel(19, 14), GRAY);
+        assert_eq!(*bottom.get_pixel(0, 15), RED);
+    }
+
+    #[test]
+    fn cover_crops_according_to_gravity() {
+        // 放大到 80x20，每条竖条 20 像素宽
+        assert_eq!(cover_size((40, 10), (20, 20)), (80, 20));
+        assert_eq!(cover_size((10, 40), (20, 20)), (20, 80));
+        let image = stripes();
+        let left = apply(fit(FitMode::Cover, Gravity::Left), &image);
+        assert!(left.pixels().all(|p| *p == RED));
+        let right = apply(fit(FitMode::Cover, Gravity::BottomRight), &image);
+        assert!(right.pixels().all(|p| *p == WHITE));
+        // 居中时保留 30 到 50 列：一半绿、一半蓝
+        let center = apply(fit(FitMode::Cover, Gravity::Center), &image);
+        assert_eq!(*center.get_pixel(0, 0), GREEN);
+        assert_eq!(*center.get_pixel(9, 19), GREEN);
+        assert_eq!(*center.get_pixel(10, 0), BLUE);
+        assert_eq!(*center.get_pixel(19, 19), BLUE);
+
+        // 竖图按上下裁剪
+        let tall = RgbaImage::from_fn(10, 40, |_, y| if y < 20 { RED } else { BLUE });
+        assert!(apply(fit(FitMode::Cover, Gravity::Top), &tall)
+            .pixels()
+            .all(|p| *p == RED));
+        assert!(apply(fit(FitMode::Cover, Gravity::Bottom), &tall)
+            .pixels()
+            .all(|p| *p == BLUE));
+    }
+
+    #[test]
+    fn center_does_not_scale() {
+        let out = apply(fit(FitMode::Center, Gravity::Center), &solid(4, 4, RED));
+        let red: Vec<(u32, u32)> = out
+            .enumerate_pixels()
+            .filter(|(_, _, p)| **p == RED)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(red.len(), 16);
+        assert_eq!(red.first(), Some(&(8, 8)));
+        assert_eq!(red.last(), Some(&(11, 11)));
+
+        let corner = apply(
+            fit(FitMode::Center, Gravity::BottomRight),
+            &solid(4, 4, RED),
+        );
+        assert_eq!(*corner.get_pixel(16, 16), RED);
+        assert_eq!(*corner.get_pixel(15, 15), GRAY);
+
+        // 比屏幕大时按原尺寸裁掉四周
+        let big = RgbaImage::from_fn(30, 30, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let out = apply(fit(FitMode::Center, Gravity::Center), &big);
+        assert_eq!(out.get_pixel(0, 0).0, [5, 5, 0, 255]);
+    }
+
+    #[test]
+    fn tile_repeats_from_top_left() {
+        let image = RgbaImage::from_fn(3, 3, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let out = apply(fit(FitMode::Tile, Gravity::Center), &image);
+        assert_eq!(out.get_pixel(0, 0), out.get_pixel(3, 6));
+        assert_eq!(out.get_pixel(19, 19).0, [1, 1, 0, 255]);
+    }
+
+    #[test]
+    fn blurred_background_uses_image_colors() {
+        let background = Fit {
+            mode: FitMode::Contain,
+            background: Background::Blur,
+            gravity: Gravity::Center,
+        };
+        let out = apply(background, &solid(40, 10, GREEN));
+        // 纯色图片模糊后仍是同一种颜色，留白处不是默认的黑色
+        for y in [0, 3, 16, 19] {
+            let [r, g, b, a] = out.get_pixel(10, y).0;
+            assert!(
+                r < 8 && g > 247 && b < 8 && a == 255,
+                "y={} {:?}",
+                y,
+                [r, g, b, a]
+            );
+        }
+    }
+
+    #[test]
+    fn parses_names() {
+        for (gravity, name) in Gravity::NAMES {
+            assert_eq!(name.parse::<Gravity>().unwrap(), gravity);
+            assert_eq!(gravity.to_string(), name);
+        }
+        assert_eq!(
+            "Bottom_Right".parse::<Gravity>().unwrap(),
+            Gravity::BottomRight
+        );
+        assert!("middle".parse::<Gravity>().is_err());
+        assert_eq!("letterbox".parse::<FitMode>().unwrap(), FitMode::Contain);
+        assert_eq!("crop".parse::<FitMode>().unwrap(), FitMode::Cover);
+        assert!("zoom".parse::<FitMode>().is_err());
+        assert_eq!("BLUR".parse::<Background>().unwrap(), Background::Blur);
+        assert_eq!(parse_color("#f80").unwrap(), Rgba([255, 136, 0, 255]));
+        assert_eq!(
+            Background::Color(parse_color("#12aBef").unwrap()).to_string(),
+            "#12abef"
+        );
+        assert!(parse_color("#12345").is_err());
+        assert_eq!(fit(FitMode::Cover, Gravity::Top).to_string(), "cover-top");
+    }
+}
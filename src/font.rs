@@ -0,0 +1,148 @@
+//! 5x7 点阵字体，用于在测试图案上标注坐标和帧号。
+//!
+//! 只包含数字、大写字母和少量符号，小写字母按大写显示（`x` 除外）；
+//! 其他字符显示为 `?`。每个字符占 6 列（含 1 列间距），缩放时按整数倍放大。
+
+use image::{Rgba, RgbaImage};
+
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+/// 字符之间的间距。
+const SPACING: u32 = 1;
+
+/// 每行 5 位，最高位在左。
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x0A, 0x04, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        'x' => [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11],
+        ' ' => [0x00; 7],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        c if c.is_ascii_lowercase() => glyph(c.to_ascii_uppercase()),
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+/// 文字放大 `scale` 倍后的宽度。
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let count = text.chars().count() as u32;
+    (count * (GLYPH_WIDTH + SPACING)).saturating_sub(SPACING) * scale
+}
+
+/// 以 `(x, y)` 为左上角绘制文字，超出画面的部分被裁掉。
+pub fn draw_text(image: &mut RgbaImage, x: i64, y: i64, scale: u32, text: &str, color: Rgba<u8>) {
+    let scale = scale.max(1) as i64;
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as i64 * (GLYPH_WIDTH + SPACING) as i64 * scale;
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for col in 0..GLYPH_WIDTH as i64 {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = left + col * scale + dx;
+                        let py = y + row as i64 * scale + dy;
+                        if (0..width).contains(&px) && (0..height).contains(&py) {
+                            image.put_pixel(px as u32, py as u32, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FALLBACK: [u8; 7] = [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04];
+
+    #[test]
+    fn covers_digits_and_letters() {
+        let digits: Vec<[u8; 7]> = ('0'..='9').map(glyph).collect();
+        for (i, bits) in digits.iter().enumerate() {
+            assert_ne!(*bits, FALLBACK, "{}", i);
+            assert!(bits.iter().all(|row| *row < 0x20), "{}", i);
+            assert!(!digits[..i].contains(bits), "{} 与前面的数字相同", i);
+        }
+        for c in 'A'..='Z' {
+            assert_ne!(glyph(c), FALLBACK, "{}", c);
+        }
+        assert_eq!(glyph('f'), glyph('F'));
+        assert_ne!(glyph('x'), glyph('X'));
+        assert_eq!(glyph('€'), FALLBACK);
+        assert_eq!(glyph(' '), [0; 7]);
+    }
+
+    #[test]
+    fn measures_text() {
+        assert_eq!(text_width("", 1), 0);
+        assert_eq!(text_width("1", 1), 5);
+        assert_eq!(text_width("12", 1), 11);
+        assert_eq!(text_width("12", 3), 33);
+    }
+
+    #[test]
+    fn draws_scaled_and_clipped() {
+        let white = Rgba([255, 255, 255, 255]);
+        let mut image = RgbaImage::new(12, 16);
+        draw_text(&mut image, 0, 0, 2, "1", white);
+        // "1" 第一行只有中间一点，放大 2 倍后占 (4..6, 0..2)
+        let lit: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, y, p)| *y < 2 && p[3] == 255)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(lit, [(4, 0), (5, 0), (4, 1), (5, 1)]);
+        let count = image.pixels().filter(|p| p[3] == 255).count();
+        let bits: u32 = glyph('1').iter().map(|row| row.count_ones()).sum();
+        assert_eq!(count as u32, bits * 4);
+
+        // 部分或完全在画面外时不越界
+        let mut small = RgbaImage::new(3, 3);
+        draw_text(&mut small, -4, -4, 1, "88", white);
+        draw_text(&mut small, 100, 100, 4, "8", white);
+        assert!(small.pixels().any(|p| p[3] == 255));
+    }
+}
@@ -0,0 +1,459 @@
+//! 设备显存的像素格式及其编码。
+//!
+//! 编号即协议包头中的像素格式字段，也是握手应答中格式位图的位序号：
+//!
+//! | 编号 | 名称 | 布局 |
+//! |------|------|------|
+//! | 0 | `rgb565` | 每像素 2 字节，大端，红色在高位 |
+//! | 1 | `rgb565le` | 同上，小端 |
+//! | 2 | `bgr565` | 每像素 2 字节，大端，蓝色在高位 |
+//! | 3 | `rgb666` | 每像素 3 字节 R、G、B，各取高 6 位，低 2 位为 0（ILI9488 等） |
+//! | 4 | `rgb888` | 每像素 3 字节 R、G、B |
+//! | 5 | `indexed8` | 每像素 1 字节调色板索引，见 [`crate::palette`] |
+//! | 6 | `gray8` | 每像素 1 字节亮度 |
+//! | 7 | `gray4` | 每字节 2 像素，左边的像素在高 4 位 |
+//! | 8 | `mono` | 每字节 8 像素，左边的像素在最高位，1 为亮（SSD1306 等） |
+//!
+//! 不足一字节的格式每行末尾补齐到整字节。这类格式不支持局部更新，
+//! 总是整帧发送。
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use image::{Rgba, RgbaImage};
+
+use crate::convert::rgb888_to_rgb565;
+use crate::dither::{ChannelBits, Dither};
+use crate::handshake::DeviceInfo;
+use crate::palette::Palette;
+
+/// 像素格式，见模块文档。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PixelFormat {
+    #[default]
+    Rgb565,
+    Rgb565Le,
+    Bgr565,
+    Rgb666,
+    Rgb888,
+    Indexed8,
+    Gray8,
+    Gray4,
+    Mono,
+}
+
+impl PixelFormat {
+    /// 按编号排列。
+    pub const ALL: [PixelFormat; 9] = [
+        PixelFormat::Rgb565,
+        PixelFormat::Rgb565Le,
+        PixelFormat::Bgr565,
+        PixelFormat::Rgb666,
+        PixelFormat::Rgb888,
+        PixelFormat::Indexed8,
+        PixelFormat::Gray8,
+        PixelFormat::Gray4,
+        PixelFormat::Mono,
+    ];
+
+    /// 协议中的格式编号。
+    pub fn id(&self) -> u8 {
+        PixelFormat::ALL.iter().position(|f| f == self).unwrap() as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<PixelFormat> {
+        PixelFormat::ALL.get(id as usize).copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PixelFormat::Rgb565 => "rgb565",
+            PixelFormat::Rgb565Le => "rgb565le",
+            PixelFormat::Bgr565 => "bgr565",
+            PixelFormat::Rgb666 => "rgb666",
+            PixelFormat::Rgb888 => "rgb888",
+            PixelFormat::Indexed8 => "indexed8",
+            PixelFormat::Gray8 => "gray8",
+            PixelFormat::Gray4 => "gray4",
+            PixelFormat::Mono => "mono",
+        }
+    }
+
+    /// 每像素的位数。
+    pub fn bits_per_pixel(&self) -> u32 {
+        match self {
+            PixelFormat::Rgb565 | PixelFormat::Rgb565Le | PixelFormat::Bgr565 => 16,
+            PixelFormat::Rgb666 | PixelFormat::Rgb888 => 24,
+            PixelFormat::Indexed8 | PixelFormat::Gray8 => 8,
+            PixelFormat::Gray4 => 4,
+            PixelFormat::Mono => 1,
+        }
+    }
+
+    /// 每像素的字节数；不足一字节的格式返回 `None`，不能按像素截取矩形。
+    pub fn bytes_per_pixel(&self) -> Option<u32> {
+        let bits = self.bits_per_pixel();
+        bits.is_multiple_of(8).then_some(bits / 8)
+    }
+
+    /// 一行的字节数，末尾补齐到整字节。
+    pub fn row_len(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel() as usize).div_ceil(8)
+    }
+
+    /// 一帧的字节数。
+    pub fn frame_len(&self, width: u32, height: u32) -> usize {
+        self.row_len(width) * height as usize
+    }
+
+    pub fn is_gray(&self) -> bool {
+        matches!(
+            self,
+            PixelFormat::Gray8 | PixelFormat::Gray4 | PixelFormat::Mono
+        )
+    }
+
+    /// 每个通道的有效位数，用于抖动；灰度格式三个通道相同。
+    fn channel_bits(&self) -> [u32; 3] {
+        match self {
+            PixelFormat::Rgb565 | PixelFormat::Rgb565Le | PixelFormat::Bgr565 => [5, 6, 5],
+            PixelFormat::Rgb666 => [6; 3],
+            PixelFormat::Rgb888 | PixelFormat::Indexed8 | PixelFormat::Gray8 => [8; 3],
+            PixelFormat::Gray4 => [4; 3],
+            PixelFormat::Mono => [1; 3],
+        }
+    }
+
+    /// 按设置或设备能力选择格式：`preferred` 为 `None` 时取设备支持的编号最小的格式。
+    pub fn select(info: &DeviceInfo, preferred: Option<PixelFormat>) -> Result<PixelFormat> {
+        match preferred {
+            Some(format) if info.supports_format(format.id()) => Ok(format),
+            Some(format) => bail!(
+                "设备不支持像素格式 {}（设备支持: {}）",
+                format,
+                PixelFormat::list(info.formats)
+            ),
+            None => PixelFormat::ALL
+                .into_iter()
+                .find(|format| info.supports_format(format.id()))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("设备没有报告任何已知的像素格式: {:#06x}", info.formats)
+                }),
+        }
+    }
+
+    /// 把格式位图列成名称，用于日志。
+    pub fn list(formats: u16) -> String {
+        let names: Vec<&str> = PixelFormat::ALL
+            .iter()
+            .filter(|f| formats & (1 << f.id()) != 0)
+            .map(|f| f.name())
+            .collect();
+        if names.is_empty() {
+            "无".to_string()
+        } else {
+            names.join("、")
+        }
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<PixelFormat> {
+        let name = s.trim().to_ascii_lowercase().replace(['-', '_'], "");
+        let name = match name.as_str() {
+            "rgb565be" => "rgb565",
+            "rgb666be" | "rgb18" => "rgb666",
+            "rgb24" => "rgb888",
+            "indexed" | "palette" => "indexed8",
+            "gray" | "grey" | "grey8" => "gray8",
+            "grey4" => "gray4",
+            "mono1" | "monochrome" => "mono",
+            other => other,
+        };
+        match PixelFormat::ALL.iter().find(|f| f.name() == name) {
+            Some(format) => Ok(*format),
+            None => bail!(
+                "未知的像素格式: {}（可选 rgb565、rgb565le、bgr565、rgb666、rgb888、indexed8、gray8、gray4、mono）",
+                s.trim()
+            ),
+        }
+    }
+}
+
+/// 把 RGBA 图像编码为某种像素格式的显存数据，索引色格式附带调色板。
+#[derive(Debug, Clone, Default)]
+pub struct Encoder {
+    format: PixelFormat,
+    palette: Arc<Palette>,
+}
+
+impl PartialEq for Encoder {
+    fn eq(&self, other: &Encoder) -> bool {
+        self.format == other.format
+            && (self.format != PixelFormat::Indexed8 || self.palette == other.palette)
+    }
+}
+
+impl Encoder {
+    pub fn new(format: PixelFormat, palette: Arc<Palette>) -> Encoder {
+        Encoder { format, palette }
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn palette(&self) -> &Arc<Palette> {
+        &self.palette
+    }
+
+    /// 一帧的字节数。
+    pub fn frame_len(&self, width: u32, height: u32) -> usize {
+        self.format.frame_len(width, height)
+    }
+
+    /// 区分编码结果的简短描述，用于缓存键。
+    pub fn id(&self) -> String {
+        match self.format {
+            PixelFormat::Indexed8 => format!("{}-{:08x}", self.format, self.palette.checksum()),
+            format => format.to_string(),
+        }
+    }
+
+    /// 按本格式的色深抖动；灰度格式先转为灰度。
+    pub fn dither(&self, image: &mut RgbaImage, dither: Dither) {
+        if self.format.is_gray() {
+            for pixel in image.pixels_mut() {
+                let l = luma(pixel);
+                pixel.0 = [l, l, l, pixel[3]];
+            }
+        }
+        match self.format {
+            PixelFormat::Indexed8 => dither.apply(image, self.palette.as_ref()),
+            format => dither.apply(image, &ChannelBits(format.channel_bits())),
+        }
+    }
+
+    /// 编码整幅图像，alpha 通道被忽略，多余的低位直接截掉。
+    pub fn encode(&self, image: &RgbaImage) -> Vec<u8> {
+        let (width, height) = image.dimensions();
+        match self.format {
+            PixelFormat::Rgb565 => rgb888_to_rgb565(image),
+            PixelFormat::Rgb565Le => {
+                let mut data = rgb888_to_rgb565(image);
+                for pair in data.chunks_exact_mut(2) {
+                    pair.swap(0, 1);
+                }
+                data
+            }
+            PixelFormat::Bgr565 => image
+                .pixels()
+                .flat_map(|p| {
+                    let [r, g, b] = [p[0] as u16, p[1] as u16, p[2] as u16];
+                    (((b & 0xF8) << 8) | ((g & 0xFC) << 3) | (r >> 3)).to_be_bytes()
+                })
+                .collect(),
+            PixelFormat::Rgb666 => image
+                .pixels()
+                .flat_map(|p| [p[0] & 0xFC, p[1] & 0xFC, p[2] & 0xFC])
+                .collect(),
+            PixelFormat::Rgb888 => image.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect(),
+            PixelFormat::Indexed8 => image
+                .pixels()
+                .map(|p| self.palette.index([p[0], p[1], p[2]]))
+                .collect(),
+            PixelFormat::Gray8 => image.pixels().map(luma).collect(),
+            PixelFormat::Gray4 | PixelFormat::Mono => {
+                let bits = self.format.bits_per_pixel();
+                let per_byte = 8 / bits;
+                let mut data = Vec::with_capacity(self.frame_len(width, height));
+                for y in 0..height {
+                    for chunk in 0..width.div_ceil(per_byte) {
+                        let mut byte = 0u8;
+                        for i in 0..per_byte {
+                            let x = chunk * per_byte + i;
+                            let level = if x < width {
+                                luma(image.get_pixel(x, y)) >> (8 - bits)
+                            } else {
+                                0
+                            };
+                            byte |= level << (8 - bits * (i + 1));
+                        }
+                        data.push(byte);
+                    }
+                }
+                data
+            }
+        }
+    }
+
+    /// [`Encoder::encode`] 的逆变换，低位按高位补齐，用于模拟器和预览。长度不符时返回 `None`。
+    pub fn decode(&self, data: &[u8], width: u32, height: u32) -> Option<RgbaImage> {
+        if data.len() != self.frame_len(width, height) {
+            return None;
+        }
+        let row_len = self.format.row_len(width);
+        Some(RgbaImage::from_fn(width, height, |x, y| {
+            let row = &data[y as usize * row_len..][..row_len];
+            let [r, g, b] = self.sample(row, x as usize);
+            Rgba([r, g, b, 255])
+        }))
+    }
+
+    /// 取出一行中第 `x` 个像素的颜色。
+    fn sample(&self, row: &[u8], x: usize) -> [u8; 3] {
+        let rgb565 = |value: u16| {
+            let [r, g, b] = [value >> 11, (value >> 5) & 0x3F, value & 0x1F].map(|v| v as u8);
+            [
+                (r << 3) | (r >> 2),
+                (g << 2) | (g >> 4),
+                (b << 3) | (b >> 2),
+            ]
+        };
+        let pair = || [row[x * 2], row[x * 2 + 1]];
+        let triple = || [row[x * 3], row[x * 3 + 1], row[x * 3 + 2]];
+        match self.format {
+            PixelFormat::Rgb565 => rgb565(u16::from_be_bytes(pair())),
+            PixelFormat::Rgb565Le => rgb565(u16::from_le_bytes(pair())),
+            PixelFormat::Bgr565 => {
+                let [b, g, r] = rgb565(u16::from_be_bytes(pair()));
+                [r, g, b]
+            }
+            PixelFormat::Rgb666 => triple().map(|v| (v & 0xFC) | (v >> 6)),
+            PixelFormat::Rgb888 => triple(),
+            PixelFormat::Indexed8 => self
+                .palette
+                .colors()
+                .get(row[x] as usize)
+                .copied()
+                .unwrap_or_default(),
+            PixelFormat::Gray8 => [row[x]; 3],
+            PixelFormat::Gray4 => [((row[x / 2] >> (4 * (1 - x % 2))) & 0x0F) * 17; 3],
+            PixelFormat::Mono => [((row[x / 8] >> (7 - x % 8)) & 1) * 255; 3],
+        }
+    }
+}
+
+/// ITU-R BT.601 亮度。
+fn luma(pixel: &Rgba<u8>) -> u8 {
+    ((pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114 + 500) / 1000) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoder(format: PixelFormat) -> Encoder {
+        Encoder::new(format, Arc::default())
+    }
+
+    fn row(colors: &[[u8; 3]]) -> RgbaImage {
+        RgbaImage::from_fn(colors.len() as u32, 1, |x, _| {
+            let [r, g, b] = colors[x as usize];
+            Rgba([r, g, b, 255])
+        })
+    }
+
+    fn gray_row(levels: &[u8]) -> RgbaImage {
+        row(&levels.iter().map(|&l| [l; 3]).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn encodes_rgb_byte_layouts() {
+        let image = row(&[[255, 0, 0], [0, 255, 0], [0, 0, 255]]);
+        let cases: [(PixelFormat, &[u8]); 5] = [
+            (PixelFormat::Rgb565, &[0xF8, 0x00, 0x07, 0xE0, 0x00, 0x1F]),
+            (PixelFormat::Rgb565Le, &[0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00]),
+            (PixelFormat::Bgr565, &[0x00, 0x1F, 0x07, 0xE0, 0xF8, 0x00]),
+            (PixelFormat::Rgb666, &[0xFC, 0, 0, 0, 0xFC, 0, 0, 0, 0xFC]),
+            (PixelFormat::Rgb888, &[0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0xFF]),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(encoder(format).encode(&image), expected, "{}", format);
+        }
+        // rgb666 只保留高 6 位
+        let odd = row(&[[0x13, 0x57, 0xFF]]);
+        assert_eq!(
+            encoder(PixelFormat::Rgb666).encode(&odd),
+            [0x10, 0x54, 0xFC]
+        );
+    }
+
+    #[test]
+    fn packs_sub_byte_formats_per_row() {
+        // 3 个像素占 2 字节，第二字节低 4 位补零
+        let gray4 = encoder(PixelFormat::Gray4);
+        let image = gray_row(&[0x00, 0xFF, 0x80]);
+        assert_eq!(gray4.encode(&image), [0x0F, 0x80]);
+        let two_rows = RgbaImage::from_fn(3, 2, |x, _| *image.get_pixel(x, 0));
+        assert_eq!(gray4.encode(&two_rows), [0x0F, 0x80, 0x0F, 0x80]);
+        assert_eq!(gray4.frame_len(3, 2), 4);
+
+        let mono = encoder(PixelFormat::Mono);
+        let mut levels = [0u8; 10];
+        levels[0] = 255;
+        levels[9] = 255;
+        assert_eq!(mono.encode(&gray_row(&levels)), [0x80, 0x40]);
+        assert_eq!(PixelFormat::Mono.row_len(10), 2);
+    }
+
+    #[test]
+    fn encodes_palette_indices() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255], [255, 0, 0]]).unwrap();
+        let indexed = Encoder::new(PixelFormat::Indexed8, Arc::new(palette));
+        let image = row(&[[255, 0, 0], [255, 255, 255], [0, 0, 0], [250, 10, 0]]);
+        let data = indexed.encode(&image);
+        assert_eq!(data, [2, 1, 0, 2]);
+        let decoded = indexed.decode(&data, 4, 1).unwrap();
+        assert_eq!(decoded.get_pixel(3, 0), &Rgba([255, 0, 0, 255]));
+    }
+
+    /// 抖动后的颜色都可以精确显示，编码再解码应得到同样的图像。
+    #[test]
+    fn round_trips_every_format() {
+        let source = RgbaImage::from_fn(13, 5, |x, y| {
+            Rgba([(x * 19) as u8, (y * 50) as u8, (x * y * 7) as u8, 255])
+        });
+        for format in PixelFormat::ALL {
+            let palette = match format {
+                PixelFormat::Indexed8 => Arc::new(Palette::rgb332()),
+                _ => Arc::default(),
+            };
+            let encoder = Encoder::new(format, palette);
+            let mut image = source.clone();
+            encoder.dither(&mut image, Dither::Round);
+            let data = encoder.encode(&image);
+            assert_eq!(data.len(), encoder.frame_len(13, 5), "{}", format);
+            assert_eq!(encoder.decode(&data, 13, 5).unwrap(), image, "{}", format);
+            assert!(encoder.decode(&data[1..], 13, 5).is_none());
+        }
+    }
+
+    #[test]
+    fn parses_names_and_ids() {
+        for format in PixelFormat::ALL {
+            assert_eq!(PixelFormat::from_id(format.id()), Some(format));
+            assert_eq!(format.name().parse::<PixelFormat>().unwrap(), format);
+        }
+        assert_eq!(PixelFormat::from_id(9), None);
+        assert_eq!(
+            "RGB-565_LE".parse::<PixelFormat>().unwrap(),
+            PixelFormat::Rgb565Le
+        );
+        assert_eq!("grey".parse::<PixelFormat>().unwrap(), PixelFormat::Gray8);
+        assert!("rgb555".parse::<PixelFormat>().is_err());
+        assert_eq!(PixelFormat::Rgb666.bytes_per_pixel(), Some(3));
+        assert_eq!(PixelFormat::Gray4.bytes_per_pixel(), None);
+        assert_eq!(PixelFormat::list(0b1_0001), "rgb565、rgb888");
+    }
+}
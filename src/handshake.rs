@@ -0,0 +1,235 @@
+//! 连接时的握手：询问设备的面板尺寸、像素格式、压缩支持和固件版本。
+//!
+//! 主机发送一个空负载的 [`Command::Hello`] 包，设备回复 [`Command::HelloReply`]，
+//! 负载布局（大端）：
+//!
+//! | 偏移 | 长度 | 字段 |
+//! |------|------|------|
+//! | 0    | 2    | 面板宽 |
+//! | 2    | 2    | 面板高 |
+//! | 4    | 2    | 支持的像素格式位图，第 n 位对应格式编号 n |
+//! | 6    | 1    | 能力位：bit0 LZ4，bit1 局部更新 |
+//! | 7    | 3    | 固件版本 主.次.修订 |
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+
+use crate::protocol::{Command, Packet, PacketDecoder, FORMAT_RGB565};
+use crate::transport::Transport;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const HELLO_REPLY_LEN: usize = 10;
+/// 默认的握手等待时间。
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+
+const CAP_LZ4: u8 = 0x01;
+const CAP_PARTIAL_UPDATE: u8 = 0x02;
+
+/// 设备在握手中报告的参数。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub width: u32,
+    pub height: u32,
+    /// 支持的像素格式位图。
+    pub formats: u16,
+    pub lz4: bool,
+    pub partial_update: bool,
+    pub firmware: (u8, u8, u8),
+}
+
+impl DeviceInfo {
+    /// 不应答握手的旧固件：320x240，仅 RGB565，不支持压缩和局部更新。
+    pub fn legacy() -> DeviceInfo {
+        DeviceInfo {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            formats: 1 << FORMAT_RGB565,
+            lz4: false,
+            partial_update: false,
+            firmware: (0, 0, 0),
+        }
+    }
+
+    pub fn supports_format(&self, format: u8) -> bool {
+        format < 16 && self.formats & (1 << format) != 0
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HELLO_REPLY_LEN);
+        out.extend_from_slice(&(self.width as u16).to_be_bytes());
+        out.extend_from_slice(&(self.height as u16).to_be_bytes());
+        out.extend_from_slice(&self.formats.to_be_bytes());
+        let mut caps = 0;
+        if self.lz4 {
+            caps |= CAP_LZ4;
+        }
+        if self.partial_update {
+            caps |= CAP_PARTIAL_UPDATE;
+        }
+        out.push(caps);
+        out.extend_from_slice(&[self.firmware.0, self.firmware.1, self.firmware.2]);
+        out
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<DeviceInfo> {
+        if payload.len() < HELLO_REPLY_LEN {
+            bail!("握手应答长度不足: {}", payload.len());
+        }
+        let info = DeviceInfo {
+            width: u16::from_be_bytes([payload[0], payload[1]]) as u32,
+            height: u16::from_be_bytes([payload[2], payload[3]]) as u32,
+            formats: u16::from_be_bytes([payload[4], payload[5]]),
+            lz4: payload[6] & CAP_LZ4 != 0,
+            partial_update: payload[6] & CAP_PARTIAL_UPDATE != 0,
+            firmware: (payload[7], payload[8], payload[9]),
+        };
+        if info.width == 0 || info.height == 0 {
+            bail!("设备报告的分辨率无效: {}x{}", info.width, info.height);
+        }
+        Ok(info)
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}，像素格式 {:#06x}，LZ4 {}，局部更新 {}，固件 {}.{}.{}",
+            self.width,
+            self.height,
+            self.formats,
+            supported(self.lz4),
+            supported(self.partial_update),
+            self.firmware.0,
+            self.firmware.1,
+            self.firmware.2
+        )
+    }
+}
+
+fn supported(flag: bool) -> &'static str {
+    if flag {
+        "支持"
+    } else {
+        "不支持"
+    }
+}
+
+/// 发送握手请求并等待应答，超时未应答时返回 `None`。
+pub fn handshake(transport: &mut dyn Transport, timeout: Duration) -> Result<Option<DeviceInfo>> {
+    let hello = Packet::new(Command::Hello, 0, 0, 0, Vec::new());
+    transport.send(&hello.encode())?;
+    transport.flush()?;
+
+    let deadline = Instant::now() + timeout;
+    let mut decoder = PacketDecoder::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        let n = transport.recv(&mut buf, remaining)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        decoder.push(&buf[..n]);
+        while let Some(packet) = decoder.next_packet() {
+            if packet.header.command == Command::HelloReply {
+                return DeviceInfo::decode(&packet.payload).map(Some);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::PacketDecoder;
+    use crate::screen::{Screen, ScreenOptions};
+    use crate::transport::MemoryTransport;
+
+    fn sample() -> DeviceInfo {
+        DeviceInfo {
+            width: 240,
+            height: 135,
+            formats: 0b101,
+            lz4: true,
+            partial_update: false,
+            firmware: (1, 2, 3),
+        }
+    }
+
+    fn reply(info: &DeviceInfo) -> Vec<u8> {
+        Packet::new(Command::HelloReply, 0, 0, 0, info.encode()).encode()
+    }
+
+    #[test]
+    fn reply_round_trip() {
+        let info = sample();
+        let bytes = info.encode();
+        assert_eq!(bytes.len(), HELLO_REPLY_LEN);
+        assert_eq!(bytes, [0, 240, 0, 135, 0, 5, 0x01, 1, 2, 3]);
+        assert_eq!(DeviceInfo::decode(&bytes).unwrap(), info);
+        assert!(info.supports_format(2));
+        assert!(!info.supports_format(1));
+        assert!(!info.supports_format(16));
+    }
+
+    #[test]
+    fn rejects_bad_replies() {
+        assert!(DeviceInfo::decode(&[0; HELLO_REPLY_LEN - 1]).is_err());
+        let zero = DeviceInfo {
+            width: 0,
+            ..sample()
+        };
+        assert!(DeviceInfo::decode(&zero.encode()).is_err());
+    }
+
+    #[test]
+    fn handshake_reads_reply_after_noise() {
+        let mut transport = MemoryTransport::new();
+        transport.push_reply(b"boot log\r\n");
+        transport.push_reply(&reply(&sample()));
+        let info = handshake(&mut transport, HANDSHAKE_TIMEOUT).unwrap();
+        assert_eq!(info, Some(sample()));
+
+        let mut decoder = PacketDecoder::new();
+        decoder.push(&transport.contents());
+        let hello = decoder.next_packet().unwrap();
+        assert_eq!(hello.header.command, Command::Hello);
+        assert!(hello.payload.is_empty());
+    }
+
+    #[test]
+    fn silent_device_falls_back_to_legacy() {
+        let mut transport = MemoryTransport::new();
+        assert_eq!(handshake(&mut transport, HANDSHAKE_TIMEOUT).unwrap(), None);
+
+        let options = ScreenOptions {
+            fallback_size: (160, 128),
+            ..ScreenOptions::default()
+        };
+        let screen = Screen::connect_with(Box::new(MemoryTransport::new()), &options).unwrap();
+        assert_eq!(screen.size(), (160, 128));
+        assert_eq!(
+            *screen.info(),
+            DeviceInfo {
+                width: 160,
+                height: 128,
+                ..DeviceInfo::legacy()
+            }
+        );
+    }
+
+    #[test]
+    fn connect_uses_reported_size() {
+        let transport = MemoryTransport::new();
+        transport.push_reply(&reply(&sample()));
+        let screen = Screen::connect(Box::new(transport)).unwrap();
+        assert_eq!(screen.size(), (240, 135));
+        assert_eq!(*screen.info(), sample());
+    }
+}
@@ -0,0 +1,66 @@
+//! USB-Screen 主机端库：查找设备、加载图片并把画面推送到 RP2040 屏幕。
+
+pub mod alpha;
+pub mod animation;
+pub mod cache;
+pub mod calibration;
+pub mod compress;
+pub mod config;
+pub mod convert;
+#[cfg(feature = "usb-serial")]
+pub mod device;
+pub mod dirty;
+pub mod dither;
+pub mod fit;
+pub mod font;
+pub mod format;
+pub mod handshake;
+pub mod loader;
+#[cfg(feature = "usb-serial")]
+pub mod multi;
+pub mod pacing;
+pub mod palette;
+pub mod pattern;
+pub mod pipeline;
+pub mod player;
+pub mod protocol;
+pub mod render;
+pub mod screen;
+pub mod supervisor;
+pub mod transform;
+pub mod transport;
+pub mod wall;
+
+pub use alpha::Matte;
+pub use animation::{load_media, Animation, Media};
+pub use cache::{CacheConfig, FrameCache};
+pub use calibration::{Calibration, CubeLut};
+pub use compress::{Compression, CompressionStats};
+pub use config::Config;
+pub use convert::{rgb565_to_rgba, rgb888_to_rgb565};
+#[cfg(feature = "usb-serial")]
+pub use device::{available_devices, find_and_open_rp2040, DeviceEntry, DeviceMatcher};
+pub use dirty::{DirtyConfig, Rect};
+pub use dither::Dither;
+pub use fit::{Background, Fit, FitMode, Gravity};
+pub use format::{Encoder, PixelFormat};
+pub use handshake::DeviceInfo;
+pub use loader::{
+    collect_images, load_image, load_image_with, scan_images, select_images, ScanOptions,
+};
+pub use pacing::{FrameStats, Pacer};
+pub use palette::Palette;
+pub use pattern::Pattern;
+pub use pipeline::{Backpressure, BoundedQueue};
+pub use player::PlayOptions;
+pub use render::{RenderOptions, Target};
+pub use screen::{Screen, ScreenOptions};
+pub use supervisor::Supervisor;
+pub use transform::{Flip, Orientation, Rotation};
+#[cfg(feature = "usb-serial")]
+pub use transport::SerialTransport;
+pub use transport::{FileTransport, MemoryTransport, TcpTransport, Transport};
+pub use wall::{WallLayout, WallTile};
+
+pub const SCREEN_WIDTH: u32 = 320;
+pub const SCREEN_HEIGHT: u32 = 240;
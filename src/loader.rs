@@ -0,0 +1,329 @@
+//! 图片的查找与加载。
+//!
+//! 目录按内容识别图片格式（文件头无法识别时再看扩展名，忽略大小写），
+//! 默认递归进入子目录，跳过以 `.` 开头的隐藏文件和目录。结果按相对路径的
+//! 自然顺序排序（`img2` 排在 `img10` 前面），每次运行顺序都相同。
+
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use glob::{MatchOptions, Pattern};
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageReader, RgbaImage};
+
+use crate::format::Encoder;
+use crate::render::{RenderOptions, Target};
+
+/// 识别格式时读取的文件头长度。
+const SNIFF_LEN: usize = 64;
+
+/// glob 匹配忽略大小写，`*` 可以跨越目录层级。
+pub(crate) const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// 扫描目录的选项。
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub recursive: bool,
+    /// 非空时只保留相对路径匹配其中任一模式的文件。
+    pub include: Vec<Pattern>,
+    /// 相对路径匹配其中任一模式的文件被排除。
+    pub exclude: Vec<Pattern>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> ScanOptions {
+        ScanOptions {
+            recursive: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl ScanOptions {
+    /// 由字符串解析 glob 模式。
+    pub fn with_globs<S: AsRef<str>>(
+        recursive: bool,
+        include: &[S],
+        exclude: &[S],
+    ) -> Result<ScanOptions> {
+        let parse = |globs: &[S]| -> Result<Vec<Pattern>> {
+            globs
+                .iter()
+                .map(|g| {
+                    Pattern::new(g.as_ref()).with_context(|| format!("无效的 glob: {}", g.as_ref()))
+                })
+                .collect()
+        };
+        Ok(ScanOptions {
+            recursive,
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
+    }
+
+    fn accepts(&self, relative: &str) -> bool {
+        (self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|p| p.matches_with(relative, GLOB_OPTIONS)))
+            && !self
+                .exclude
+                .iter()
+                .any(|p| p.matches_with(relative, GLOB_OPTIONS))
+    }
+}
+
+/// 按默认选项列出目录中的所有图片。
+pub fn select_images(dir: impl AsRef<Path>) -> Result<Vec<String>> {
+    scan_images(dir, &ScanOptions::default())
+}
+
+/// 列出目录中的图片，见模块文档。
+pub fn scan_images(dir: impl AsRef<Path>, options: &ScanOptions) -> Result<Vec<String>> {
+    let dir = dir.as_ref();
+    let mut found = Vec::new();
+    walk(dir, dir, options, &mut found)?;
+    found.sort_by(|(a, _), (b, _)| natural_cmp(a, b));
+    Ok(found.into_iter().map(|(_, path)| path).collect())
+}
+
+/// 递归收集 `(相对路径, 完整路径)`。
+fn walk(
+    root: &Path,
+    dir: &Path,
+    options: &ScanOptions,
+    found: &mut Vec<(String, String)>,
+) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("无法读取目录 {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        // 不跟随指向目录的符号链接，避免循环
+        if entry.file_type()?.is_dir() {
+            if options.recursive {
+                walk(root, &path, options, found)?;
+            }
+            continue;
+        }
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        if path.is_file() && options.accepts(&relative) && detect_format(&path).is_some() {
+            found.push((relative, path.to_string_lossy().to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// 识别可以解码的图片格式：先看文件头，无法识别时再看扩展名。
+pub fn detect_format(path: impl AsRef<Path>) -> Option<ImageFormat> {
+    let path = path.as_ref();
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+    image::guess_format(&header)
+        .ok()
+        .or_else(|| ImageFormat::from_path(path).ok())
+        .filter(|format| format.reading_enabled())
+}
+
+/// 自然排序：连续的数字按数值比较，其余字符忽略大小写比较，完全相同时再按原始字节区分。
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (x.peek().copied(), y.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c), Some(d)) if c.is_ascii_digit() && d.is_ascii_digit() => {
+                let m = take_number(&mut x);
+                let n = take_number(&mut y);
+                // 先比较去掉前导零后的长度，避免数字过长溢出
+                let ordering = m.len().cmp(&n.len()).then_with(|| m.cmp(&n));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(c), Some(d)) => {
+                let ordering = c.to_lowercase().cmp(d.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                x.next();
+                y.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits.trim_start_matches('0').to_string()
+}
+
+/// 按顺序展开若干图片来源：目录按 `options` 扫描，文件直接加入。
+pub fn collect_images<P: AsRef<Path>>(sources: &[P], options: &ScanOptions) -> Result<Vec<String>> {
+    let mut images = Vec::new();
+    for source in sources {
+        let source = source.as_ref();
+        if source.is_dir() {
+            images.extend(scan_images(source, options)?);
+        } else if source.is_file() {
+            images.push(source.to_string_lossy().to_string());
+        } else {
+            bail!("图片来源不存在: {}", source.display());
+        }
+    }
+    Ok(images)
+}
+
+/// 按名称解析缩放算法：`nearest`、`triangle`、`catmullrom`、`gaussian`、`lanczos3`。
+pub fn parse_filter(name: &str) -> Result<FilterType> {
+    Ok(match name.trim().to_ascii_lowercase().as_str() {
+        "nearest" => FilterType::Nearest,
+        "triangle" | "bilinear" => FilterType::Triangle,
+        "catmullrom" | "bicubic" => FilterType::CatmullRom,
+        "gaussian" => FilterType::Gaussian,
+        "lanczos3" => FilterType::Lanczos3,
+        other => bail!(
+            "未知的缩放算法: {}（可选 nearest、triangle、catmullrom、gaussian、lanczos3）",
+            other
+        ),
+    })
+}
+
+/// 解码图片并拉伸到 `width` x `height`。
+pub fn load_image(path: impl AsRef<Path>, width: u32, height: u32) -> Result<RgbaImage> {
+    let target = Target::new(width, height, Encoder::default());
+    load_image_with(path, &target, &RenderOptions::default())
+}
+
+/// 同 [`load_image`]，按 `render` 渲染为 `target` 的显存画面：已变换到安装方向，并按像素格式抖动。
+pub fn load_image_with(
+    path: impl AsRef<Path>,
+    target: &Target,
+    render: &RenderOptions,
+) -> Result<RgbaImage> {
+    let img = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+    Ok(render.render(&img.to_rgba8(), target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("usb-screen-loader-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_png(path: &Path) {
+        RgbaImage::new(2, 2)
+            .save_with_format(path, ImageFormat::Png)
+            .unwrap();
+    }
+
+    #[test]
+    fn sorts_numbers_by_value() {
+        assert_eq!(natural_cmp("img2", "img10"), Ordering::Less);
+        assert_eq!(natural_cmp("img10", "img2"), Ordering::Greater);
+        assert_eq!(natural_cmp("a9b", "a10a"), Ordering::Less);
+        // 前导零不影响数值，超长数字也不会溢出
+        assert_eq!(natural_cmp("img007", "img8"), Ordering::Less);
+        assert_eq!(
+            natural_cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+        // 忽略大小写，只在完全相同时才按原始字节区分
+        assert_eq!(natural_cmp("Beta", "alpha"), Ordering::Greater);
+        assert_eq!(natural_cmp("IMG1", "img1"), Ordering::Less);
+        assert_eq!(natural_cmp("img", "img1"), Ordering::Less);
+
+        let mut names = vec!["img10.png", "img1.png", "IMG3.png", "img2.png", "img02.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            ["img1.png", "img02.png", "img2.png", "IMG3.png", "img10.png"]
+        );
+    }
+
+    #[test]
+    fn detects_format_from_header_before_extension() {
+        let dir = temp_dir("detect");
+        let disguised = dir.join("photo.jpg");
+        write_png(&disguised);
+        assert_eq!(detect_format(&disguised), Some(ImageFormat::Png));
+
+        let bare = dir.join("noext");
+        write_png(&bare);
+        assert_eq!(detect_format(&bare), Some(ImageFormat::Png));
+
+        let text = dir.join("notes.txt");
+        fs::write(&text, "not an image").unwrap();
+        assert_eq!(detect_format(&text), None);
+        assert_eq!(detect_format(dir.join("missing.png")), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scans_in_natural_order_with_filters() {
+        let dir = temp_dir("scan");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in [
+            "img10.png",
+            "img2.png",
+            "skip.png",
+            ".hidden.png",
+            "sub/img1.png",
+        ] {
+            write_png(&dir.join(name));
+        }
+        fs::write(dir.join("readme.txt"), "text").unwrap();
+
+        let relative = |options: &ScanOptions| -> Vec<String> {
+            scan_images(&dir, options)
+                .unwrap()
+                .iter()
+                .map(|path| {
+                    Path::new(path)
+                        .strip_prefix(&dir)
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect()
+        };
+        assert_eq!(
+            relative(&ScanOptions::default()),
+            ["img2.png", "img10.png", "skip.png", "sub/img1.png"]
+        );
+        let options = ScanOptions::with_globs(false, &["*.PNG"], &["skip*"]).unwrap();
+        assert_eq!(relative(&options), ["img2.png", "img10.png"]);
+        assert!(ScanOptions::with_globs(true, &["[bad"], &[]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
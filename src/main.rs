@@ -1,104 +1,619 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, Instant};
-use std::{fs, thread};
 
-use anyhow::Result;
-use image::{DynamicImage, RgbaImage};
-use log::{error, info};
-use serialport::SerialPort;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use log::{info, warn};
+use usb_screen::cache::FrameCache;
+use usb_screen::config::Config;
+use usb_screen::device::available_devices;
+use usb_screen::loader::{collect_images, load_image_with};
+use usb_screen::multi::{self, DeviceTask};
+use usb_screen::pattern::Pattern;
+use usb_screen::supervisor::Supervisor;
+use usb_screen::{
+    wall, Calibration, DeviceMatcher, Encoder, Pacer, Rotation, Screen, Target, WallLayout,
+    WallTile,
+};
 
-const SCREEN_WIDTH: u32 = 320;
-const SCREEN_HEIGHT: u32 = 240;
-const FRAME_DURATION: u128 = 1000 / 24; // 24 FPS
-
-fn main() -> Result<()> {
-    env_logger::init();
-    info!("启动USB-Screen");
+/// USB-Screen 主机端：把图片推送到 USB 串口屏幕。
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(flatten)]
+    device: DeviceArgs,
+    /// 不给子命令时等同于 `play`。
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    // 自动查找并连接RP2040设备
-    let mut port = find_and_open_rp2040()?;
-    info!("RP2040设备已连接");
+/// 所有子命令共用的设备选择参数，优先于配置文件。
+#[derive(Args)]
+struct DeviceArgs {
+    /// 配置文件路径，默认在当前目录和用户配置目录中查找 usb-screen.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// 设备匹配规则，可重复：VID:PID、预设名、serial=、product=、path= 或串口路径
+    #[arg(short, long = "device", global = true, env = "USB_SCREEN_PORT")]
+    devices: Vec<String>,
+    /// 串口波特率
+    #[arg(long, global = true)]
+    baud: Option<u32>,
+    /// 像素格式：auto、rgb565、rgb565le、bgr565、rgb666、rgb888、indexed8、gray8、gray4、mono
+    #[arg(long, global = true)]
+    pixel_format: Option<String>,
+    /// indexed8 使用的调色板文件（每行一个 #rrggbb）
+    #[arg(long, global = true)]
+    palette: Option<PathBuf>,
+    /// 屏幕安装方向，顺时针旋转角度：0、90、180、270
+    #[arg(long, global = true, value_name = "DEGREES")]
+    rotate: Option<u32>,
+    /// 镜像翻转：none、horizontal、vertical、both，先于旋转
+    #[arg(long, global = true)]
+    flip: Option<String>,
+}
 
-    // 选择要发送的图片
-    let images = select_images()?;
-    info!("已选择图片数量: {}", images.len());
+#[derive(Subcommand)]
+enum Command {
+    /// 列出系统中的串口及其 VID/PID、序列号，标出符合匹配规则的设备
+    List,
+    /// 循环播放目录或图片；每块匹配的屏幕各自播放，运行中插入的屏幕也会自动加入
+    Play(PlayArgs),
+    /// 发送一张图片后退出
+    Send {
+        image: PathBuf,
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// 与设备握手并显示面板参数
+    Info,
+    /// 显示测试图案；moving-bar 和 counter 按帧率持续刷新，Ctrl+C 退出
+    TestPattern {
+        /// 图案：bars、smpte、gradient、checkerboard、grid、white、black、red、green、blue、
+        /// solid:#rrggbb、moving-bar、counter、calibration
+        #[arg(default_value = "bars")]
+        pattern: Pattern,
+        /// 依次显示所有图案，每个停留的秒数
+        #[arg(long)]
+        cycle: Option<u64>,
+        /// 动态图案的帧率
+        #[arg(long)]
+        fps: Option<u32>,
+    },
+    /// 显示校准图案，从标准输入逐行调整颜色校准，例如 `gamma 1.1`、`gain 1 0.95 0.9`
+    Calibrate {
+        /// 图案名称
+        #[arg(default_value = "calibration")]
+        pattern: Pattern,
+        /// 校准这一别名（USB 序列号）的屏幕，从 [screens.别名.calibration] 开始调整
+        #[arg(long)]
+        screen: Option<String>,
+    },
+    /// 离线把图片转换为设备使用的显存数据；输出为 .png 时保存转换后的效果预览
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// 目标尺寸，默认使用配置中的分辨率
+        #[arg(long, value_parser = parse_size)]
+        size: Option<(u32, u32)>,
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+}
 
-    // 循环发送图片
-    loop {
-        for image_path in &images {
-            let image = load_image(image_path)?;
-            let start_time = Instant::now();
-
-            // 发送图片到RP2040
-            if let Err(err) = send_image_to_rp2040(&mut port, &image) {
-                error!("发送图片失败: {:?}", err);
-                return Err(err);
-            }
+/// 图片渲染参数，优先于配置文件。
+#[derive(Args, Default)]
+struct RenderArgs {
+    /// 缩放算法：nearest、triangle、catmullrom、gaussian、lanczos3
+    #[arg(long)]
+    filter: Option<String>,
+    /// 宽高比不一致时的处理方式：stretch、contain、cover、center、tile
+    #[arg(long)]
+    fit: Option<String>,
+    /// contain 和 center 的留白：#rrggbb 或 blur
+    #[arg(long)]
+    background: Option<String>,
+    /// 对齐位置：center、top、bottom、left、right、top-left 等
+    #[arg(long)]
+    gravity: Option<String>,
+    /// 抖动方式：none、round、bayer4、bayer8、floyd-steinberg、blue-noise
+    #[arg(long)]
+    dither: Option<String>,
+    /// 透明像素的底色：#rrggbb、gradient:#起始色,#结束色[,horizontal] 或 image:路径
+    #[arg(long)]
+    matte: Option<String>,
+}
 
-            // 计算并等待帧时间
-            let elapsed = start_time.elapsed().as_millis();
-            if elapsed < FRAME_DURATION {
-                thread::sleep(Duration::from_millis((FRAME_DURATION - elapsed) as u64));
+impl RenderArgs {
+    fn apply(self, config: &mut Config) {
+        let display = &mut config.display;
+        for (value, field) in [
+            (self.filter, &mut display.filter),
+            (self.fit, &mut display.fit),
+            (self.background, &mut display.background),
+            (self.gravity, &mut display.gravity),
+            (self.dither, &mut display.dither),
+            (self.matte, &mut display.matte),
+        ] {
+            if let Some(value) = value {
+                *field = value;
             }
         }
     }
 }
 
-fn find_and_open_rp2040() -> Result<Box<dyn SerialPort>> {
-    for port_info in serialport::available_ports()? {
-        if port_info.port_type
-            == serialport::SerialPortType::UsbPort(serialport::UsbPortInfo {
-                vid: 0x2E8A, // RP2040的USB VID
-                pid: 0x000A, // RP2040的USB PID
-                ..
-            })
-        {
-            return serialport::new(port_info.port_name, 115_200)
-                .open()
-                .map_err(|e| e.into());
+#[derive(Args, Default)]
+struct PlayArgs {
+    /// 图片目录或文件，默认使用配置中的 playlist.sources
+    sources: Vec<PathBuf>,
+    /// 为指定别名（USB 序列号）的屏幕单独指定内容：别名=目录
+    #[arg(long = "screen", value_parser = parse_screen)]
+    screens: Vec<(String, PathBuf)>,
+    /// 帧率
+    #[arg(long)]
+    fps: Option<u32>,
+    /// 每张静态图片显示的秒数
+    #[arg(long, value_name = "SECS")]
+    hold: Option<f64>,
+    #[command(flatten)]
+    render: RenderArgs,
+    /// 打乱播放顺序
+    #[arg(long)]
+    shuffle: bool,
+    /// 只播放一轮
+    #[arg(long)]
+    once: bool,
+    /// 解码、转换、发送之间的队列深度
+    #[arg(long)]
+    queue_depth: Option<usize>,
+    /// 队列满时的处理方式：block 或 drop-oldest
+    #[arg(long)]
+    backpressure: Option<String>,
+    /// 不进入子目录
+    #[arg(long)]
+    no_recursive: bool,
+    /// 只播放相对路径匹配的文件，可重复，例如 "*.gif"
+    #[arg(long)]
+    include: Vec<String>,
+    /// 跳过相对路径匹配的文件，可重复
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// 拼接屏网格：列x行
+    #[arg(long, value_parser = parse_size)]
+    wall: Option<(u32, u32)>,
+    /// 拼接屏中的一块屏幕：别名=列,行[,角度]，可重复
+    #[arg(long = "tile", value_parser = parse_tile, requires = "wall")]
+    tiles: Vec<WallTile>,
+    /// 拼接屏中每块屏幕的尺寸：宽x高
+    #[arg(long, value_parser = parse_size, requires = "wall")]
+    tile_size: Option<(u32, u32)>,
+    /// 屏幕之间的边框宽度：横[,纵]
+    #[arg(long, value_parser = parse_bezel, requires = "wall")]
+    bezel: Option<(u32, u32)>,
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32)> {
+    let (w, h) = value
+        .split_once('x')
+        .ok_or_else(|| anyhow!("尺寸格式应为 宽x高: {}", value))?;
+    Ok((w.parse()?, h.parse()?))
+}
+
+fn parse_bezel(value: &str) -> Result<(u32, u32)> {
+    Ok(match value.split_once(',') {
+        Some((x, y)) => (x.parse()?, y.parse()?),
+        None => (value.parse()?, value.parse()?),
+    })
+}
+
+fn parse_screen(value: &str) -> Result<(String, PathBuf)> {
+    let (alias, dir) = value
+        .split_once('=')
+        .ok_or_else(|| anyhow!("格式应为 别名=目录: {}", value))?;
+    Ok((alias.to_string(), dir.into()))
+}
+
+fn parse_tile(value: &str) -> Result<WallTile> {
+    let (alias, position) = value
+        .split_once('=')
+        .ok_or_else(|| anyhow!("格式应为 别名=列,行[,角度]: {}", value))?;
+    let fields: Vec<&str> = position.split(',').collect();
+    if fields.len() < 2 || fields.len() > 3 {
+        bail!("格式应为 别名=列,行[,角度]: {}", value);
+    }
+    Ok(WallTile {
+        alias: alias.to_string(),
+        col: fields[0].parse()?,
+        row: fields[1].parse()?,
+        rotation: match fields.get(2) {
+            Some(degrees) => degrees.parse()?,
+            None => Rotation::Deg0,
+        },
+    })
+}
+
+/// 读取配置文件并用命令行参数覆盖。
+fn load_config(args: &DeviceArgs) -> Result<Config> {
+    let path = args.config.clone().or_else(Config::find);
+    let mut config = match &path {
+        Some(path) => {
+            info!("使用配置文件 {}", path.display());
+            Config::load(path)?
         }
+        None => Config::default(),
+    };
+    if !args.devices.is_empty() {
+        config.device.specs = args.devices.clone();
+    }
+    if let Some(baud_rate) = args.baud {
+        config.device.baud = baud_rate;
+    }
+    if let Some(format) = &args.pixel_format {
+        config.device.pixel_format = format.clone();
     }
-    Err(anyhow::anyhow!("未找到RP2040设备"))
+    if let Some(palette) = &args.palette {
+        config.device.palette = Some(palette.clone());
+    }
+    if let Some(degrees) = args.rotate {
+        config.device.rotation = degrees;
+    }
+    if let Some(flip) = &args.flip {
+        config.device.flip = flip.clone();
+    }
+    Ok(config)
 }
 
-fn select_images() -> Result<Vec<String>> {
-    let paths = fs::read_dir("./images")?
-        .filter_map(|entry| {
-            let entry = entry.ok()?;
-            let path = entry.path();
-            if path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("png") {
-                Some(path.to_string_lossy().to_string())
+/// 打开第一块匹配的屏幕。
+fn open_screen(config: &Config) -> Result<Screen> {
+    Screen::open_with(
+        &config.matcher()?,
+        config.device.baud,
+        &config.screen_options()?,
+    )
+}
+
+fn list(config: &Config) -> Result<()> {
+    let matcher = config.matcher()?;
+    let mut entries = available_devices()?;
+    // 直接指定的路径（例如伪终端）不在系统枚举结果中
+    for entry in matcher.enumerate()? {
+        if !entries.iter().any(|e| e.port_name == entry.port_name) {
+            entries.push(entry);
+        }
+    }
+    if entries.is_empty() {
+        println!("没有发现串口");
+    }
+    for entry in &entries {
+        let usb_id = match entry.usb_id {
+            Some((vid, pid)) => format!("{:04x}:{:04x}", vid, pid),
+            None => "-".to_string(),
+        };
+        println!(
+            "{} {:<20} {}  序列号 {}  产品 {}",
+            if matcher.matches_entry(entry) {
+                "*"
             } else {
-                None
+                " "
+            },
+            entry.port_name,
+            usb_id,
+            entry.serial_number.as_deref().unwrap_or("-"),
+            entry.product.as_deref().unwrap_or("-"),
+        );
+    }
+    println!("匹配规则: {}（* 为匹配的设备）", matcher);
+    Ok(())
+}
+
+fn info(config: &Config) -> Result<()> {
+    let matcher = config.matcher()?;
+    let entries = matcher.enumerate()?;
+    if entries.is_empty() {
+        bail!("未找到匹配的设备: {}", matcher);
+    }
+    let options = config.screen_options()?;
+    for entry in &entries {
+        match Screen::open_with(&entry.matcher(), config.device.baud, &options) {
+            Ok(screen) => println!("{} ({}): {}", entry.alias(), entry.port_name, screen.info()),
+            Err(err) => println!(
+                "{} ({}): 打开失败: {:#}",
+                entry.alias(),
+                entry.port_name,
+                err
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn send(config: &Config, image: &Path) -> Result<()> {
+    let render = config.play_options()?.render_for(&image.to_string_lossy());
+    let mut screen = open_screen(config)?;
+    let target = screen.target();
+    let frame = load_image_with(image, &target, &render)
+        .with_context(|| format!("无法加载图片 {}", image.display()))?;
+    screen.draw_pixels(&target.encode(&frame))?;
+    screen.close()
+}
+
+fn test_pattern(config: &Config, pattern: Pattern, cycle: Option<u64>) -> Result<()> {
+    let mut screen = open_screen(config)?;
+    let frame_duration = Duration::from_secs(1) / config.display.fps;
+    let Some(seconds) = cycle else {
+        show_pattern(&mut screen, pattern, None, frame_duration)?;
+        return screen.close();
+    };
+    loop {
+        for pattern in Pattern::ALL {
+            info!("显示测试图案 {}", pattern);
+            let duration = Duration::from_secs(seconds);
+            show_pattern(&mut screen, pattern, Some(duration), frame_duration)?;
+        }
+    }
+}
+
+/// 显示 `duration` 时长的图案，`None` 表示静态图案显示一次即返回、动态图案一直播放。
+fn show_pattern(
+    screen: &mut Screen,
+    pattern: Pattern,
+    duration: Option<Duration>,
+    frame_duration: Duration,
+) -> Result<()> {
+    let (width, height) = screen.logical_size();
+    if !pattern.is_animated() {
+        screen.draw(&pattern.render(width, height))?;
+        if let Some(duration) = duration {
+            thread::sleep(duration);
+        }
+        return Ok(());
+    }
+    let start = Instant::now();
+    let mut pacer = Pacer::new();
+    for frame in 0.. {
+        if duration.is_some_and(|d| start.elapsed() >= d) {
+            break;
+        }
+        screen.draw(&pattern.render_frame(width, height, frame))?;
+        pacer.wait(frame_duration);
+    }
+    Ok(())
+}
+
+fn calibrate(config: &Config, mut pattern: Pattern, alias: Option<&str>) -> Result<()> {
+    let (mut screen, section) = match alias {
+        Some(alias) => {
+            let screen = Screen::open_with(
+                &DeviceMatcher::for_alias(alias),
+                config.device.baud,
+                &config.screen_options_for(alias)?,
+            )?;
+            (screen, format!("screens.{}.calibration", alias))
+        }
+        None => (open_screen(config)?, "calibration".to_string()),
+    };
+    let initial = screen.target().calibration;
+    let mut calibration = (*initial).clone();
+    println!("输入 参数 数值 调整，例如 gamma 1.1、gain 1 0.95 0.9、lut ./panel.cube、lut none");
+    println!("可调参数: gamma、gain、offset、brightness、contrast、saturation、lut");
+    println!("其他命令: pattern 名称、reset、show（输出配置）、quit");
+    let (width, height) = screen.logical_size();
+    screen.draw(&pattern.render(width, height))?;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => continue,
+            ["quit" | "q" | "exit"] => break,
+            ["show"] => {
+                print!("{}", calibration_toml(&section, &calibration));
+                continue;
             }
-        })
-        .collect();
-    Ok(paths)
+            ["reset"] => {
+                calibration = (*initial).clone();
+                Ok(())
+            }
+            ["pattern", name] => name.parse().map(|p| pattern = p),
+            [key, args @ ..] => calibration.set(key, args),
+        };
+        if let Err(err) = result {
+            println!("{:#}", err);
+            continue;
+        }
+        screen.set_calibration(Arc::new(calibration.clone()));
+        screen.draw(&pattern.render(width, height))?;
+    }
+    println!("{}", calibration_toml(&section, &calibration));
+    screen.close()
 }
 
-fn load_image(path: &str) -> Result<RgbaImage> {
-    let img = image::open(path)?;
-    let img = img.resize_exact(
-        SCREEN_WIDTH,
-        SCREEN_HEIGHT,
-        image::imageops::FilterType::Lanczos3,
+/// 校准参数写成配置文件中的一节。
+fn calibration_toml(section: &str, calibration: &Calibration) -> String {
+    let mut out = format!(
+        "[{}]\ngamma = {:?}\ngain = {:?}\noffset = {:?}\nbrightness = {:?}\ncontrast = {:?}\nsaturation = {:?}\n",
+        section,
+        calibration.gamma,
+        calibration.gain,
+        calibration.offset,
+        calibration.brightness,
+        calibration.contrast,
+        calibration.saturation
     );
-    Ok(img.to_rgba8())
+    if let Some(lut) = &calibration.lut {
+        out += &format!("lut = {:?}\n", lut.path.display().to_string());
+    }
+    out
 }
 
-fn send_image_to_rp2040(port: &mut Box<dyn SerialPort>, image: &RgbaImage) -> Result<()> {
-    let rgb565 = rgb888_to_rgb565(&image);
-    port.write_all(&rgb565)?;
+fn convert(config: &Config, input: &Path, output: &Path, size: Option<(u32, u32)>) -> Result<()> {
+    let (width, height) = size.unwrap_or((config.display.width, config.display.height));
+    // 离线转换没有设备可协商，auto 按 RGB565 处理
+    let format = config.pixel_format()?.unwrap_or_default();
+    let encoder = Encoder::new(format, config.screen_options()?.palette);
+    let target = Target::new(width, height, encoder)
+        .with_orientation(config.orientation()?)
+        .with_calibration(config.screen_options()?.calibration);
+    let render = config.play_options()?.render_for(&input.to_string_lossy());
+    let image = load_image_with(input, &target, &render)
+        .with_context(|| format!("无法加载图片 {}", input.display()))?;
+    let pixels = target.encode(&image);
+    let is_png = output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if is_png {
+        let preview = target
+            .encoder
+            .decode(&pixels, width, height)
+            .with_context(|| format!("{} 数据长度不符", format))?;
+        preview.save(output)?;
+    } else {
+        fs::write(output, &pixels)?;
+    }
+    info!(
+        "已转换 {} -> {}（{}x{}，{}，{} 字节）",
+        input.display(),
+        output.display(),
+        width,
+        height,
+        format,
+        pixels.len()
+    );
     Ok(())
 }
 
-fn rgb888_to_rgb565(image: &RgbaImage) -> Vec<u8> {
-    let mut rgb565 = Vec::with_capacity((SCREEN_WIDTH * SCREEN_HEIGHT * 2) as usize);
-    for pixel in image.pixels() {
-        let r = pixel[0] as u16;
-        let g = pixel[1] as u16;
-        let b = pixel[2] as u16;
-        let rgb565_pixel = ((r & 0b11111000) << 8) | ((g & 0b11111100) << 3) | (b >> 3);
-        rgb565.extend_from_slice(&rgb565_pixel.to_be_bytes());
+fn play(mut config: Config, args: PlayArgs) -> Result<()> {
+    if !args.sources.is_empty() {
+        config.playlist.sources = args.sources;
+    }
+    for (alias, dir) in args.screens {
+        config.screens.entry(alias).or_default().sources = vec![dir];
+    }
+    if let Some(fps) = args.fps {
+        config.display.fps = fps;
+    }
+    if let Some(hold) = args.hold {
+        config.playlist.hold = Some(hold);
+    }
+    if let Some(depth) = args.queue_depth {
+        config.pipeline.queue_depth = depth;
+    }
+    if let Some(backpressure) = args.backpressure {
+        config.pipeline.backpressure = backpressure;
+    }
+    config.playlist.shuffle |= args.shuffle;
+    config.playlist.repeat &= !args.once;
+    config.playlist.recursive &= !args.no_recursive;
+    config.playlist.include.extend(args.include);
+    config.playlist.exclude.extend(args.exclude);
+    config.validate()?;
+
+    let matcher = config.matcher()?;
+    let baud_rate = config.device.baud;
+    let play_options = config.play_options()?;
+    let scan_options = config.scan_options()?;
+    let cache = Arc::new(FrameCache::new(config.cache_config()));
+
+    let layout = match args.wall {
+        Some((cols, rows)) => {
+            let (tile_width, tile_height) = args
+                .tile_size
+                .unwrap_or((config.display.width, config.display.height));
+            let mut layout = WallLayout::new(cols, rows, tile_width, tile_height);
+            (layout.bezel_x, layout.bezel_y) = args.bezel.unwrap_or_default();
+            layout.tiles = args.tiles;
+            layout.validate()?;
+            Some(layout)
+        }
+        None => config.wall_layout()?,
+    };
+    if let Some(layout) = layout {
+        let images = collect_images(&config.playlist.sources, &scan_options)?;
+        let mut supervisors: Vec<Supervisor> = layout
+            .tiles
+            .iter()
+            .map(|tile| {
+                let matcher = DeviceMatcher::for_alias(&tile.alias);
+                let screen_options = config.screen_options_for(&tile.alias)?;
+                Ok(Supervisor::new(move || {
+                    Screen::open_with(&matcher, baud_rate, &screen_options)
+                }))
+            })
+            .collect::<Result<_>>()?;
+        return wall::play(&layout, &mut supervisors, &images, &play_options, &cache);
+    }
+
+    info!("设备匹配规则: {}", matcher);
+    let entries = matcher.enumerate()?;
+    if entries.is_empty() {
+        info!("暂时没有匹配的设备，插入后自动开始播放");
+    }
+    for alias in config.screens.keys() {
+        if !entries.iter().any(|e| e.alias() == *alias) {
+            warn!("未找到别名为 {} 的设备，插入后自动开始播放", alias);
+        }
+    }
+    multi::run(&matcher, baud_rate, play_options, cache, |entry| {
+        let alias = entry.alias();
+        let sources = config.sources_for(&alias);
+        info!(
+            "发现设备 {} ({})，内容来源 {:?}",
+            alias, entry.port_name, sources
+        );
+        Ok(DeviceTask {
+            screen_options: config.screen_options_for(&alias)?,
+            alias,
+            matcher: entry.matcher(),
+            images: collect_images(sources, &scan_options)?,
+        })
+    })
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let mut cli = Cli::parse();
+    let mut config = load_config(&cli.device)?;
+    match &mut cli.command {
+        Some(Command::Send { render, .. } | Command::Convert { render, .. }) => {
+            mem::take(render).apply(&mut config)
+        }
+        Some(Command::Play(args)) => mem::take(&mut args.render).apply(&mut config),
+        Some(Command::TestPattern { fps: Some(fps), .. }) => config.display.fps = *fps,
+        _ => {}
+    }
+    config.validate()?;
+
+    match cli.command {
+        Some(Command::List) => list(&config),
+        Some(Command::Info) => info(&config),
+        Some(Command::Send { image, .. }) => send(&config, &image),
+        Some(Command::TestPattern { pattern, cycle, .. }) => test_pattern(&config, pattern, cycle),
+        Some(Command::Calibrate { pattern, screen }) => {
+            calibrate(&config, pattern, screen.as_deref())
+        }
+        Some(Command::Convert {
+            input,
+            output,
+            size,
+            ..
+        }) => convert(&config, &input, &output, size),
+        Some(Command::Play(args)) => {
+            info!("启动USB-Screen");
+            play(config, args)
+        }
+        None => {
+            info!("启动USB-Screen");
+            play(config, PlayArgs::default())
+        }
     }
-    rgb565
 }
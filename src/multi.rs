@@ -0,0 +1,257 @@
+//! 多屏幕：每块屏幕在独立线程中播放各自的内容，互不影响。
+//!
+//! 启动后定期重新枚举设备，运行中插入的屏幕也会分到自己的播放线程。
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{error, info, warn};
+
+use crate::cache::FrameCache;
+use crate::device::{DeviceEntry, DeviceMatcher};
+use crate::player::{self, PlayOptions};
+use crate::screen::{Screen, ScreenOptions};
+use crate::supervisor::Supervisor;
+
+/// 重新枚举设备的间隔。
+pub const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 一块屏幕及分配给它的内容。
+#[derive(Debug, Clone)]
+pub struct DeviceTask {
+    pub alias: String,
+    /// 只匹配这一块屏幕的条件，见 [`crate::device::DeviceEntry::matcher`]。
+    pub matcher: DeviceMatcher,
+    /// 这块屏幕的设备选项，见 [`crate::config::Config::screen_options_for`]。
+    pub screen_options: ScreenOptions,
+    pub images: Vec<String>,
+}
+
+/// 为每块符合 `matcher` 的屏幕启动一个播放线程，`make_task` 为新发现的设备分配内容。
+///
+/// 每隔 [`RESCAN_INTERVAL`] 重新枚举一次，新别名的设备随时加入；掉线的屏幕由各自的
+/// [`Supervisor`] 重连，不会重复启动线程。单个线程出错或崩溃只记录日志，不影响其他屏幕。
+/// 所有已启动的线程都结束（例如只播放一轮）后返回。
+pub fn run(
+    matcher: &DeviceMatcher,
+    baud_rate: u32,
+    play_options: PlayOptions,
+    cache: Arc<FrameCache>,
+    mut make_task: impl FnMut(&DeviceEntry) -> Result<DeviceTask>,
+) -> Result<()> {
+    let mut roster = Roster::default();
+    let mut handles: BTreeMap<String, JoinHandle<()>> = BTreeMap::new();
+    loop {
+        match matcher.enumerate() {
+            Ok(entries) => {
+                let changes = roster.rescan(entries);
+                for alias in &changes.gone {
+                    warn!("[{}] 设备已拔出，重新插入后继续播放", alias);
+                }
+                for alias in &changes.returned {
+                    info!("[{}] 设备已重新插入", alias);
+                }
+                for entry in changes.added {
+                    let alias = entry.alias();
+                    let handle = make_task(&entry).and_then(|task| {
+                        info!("[{}] 分配图片 {} 张", alias, task.images.len());
+                        spawn(task, baud_rate, play_options.clone(), cache.clone())
+                    });
+                    match handle {
+                        Ok(handle) => {
+                            handles.insert(alias, handle);
+                        }
+                        Err(err) => {
+                            error!("[{}] 无法启动播放: {:?}", alias, err);
+                            roster.finish(&alias);
+                        }
+                    }
+                }
+            }
+            Err(err) => warn!("枚举设备失败: {:?}", err),
+        }
+
+        let done: Vec<String> = handles
+            .iter()
+            .filter(|(_, handle)| handle.is_finished())
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        for alias in done {
+            let handle = handles.remove(&alias).expect("线程句柄");
+            match handle.join() {
+                Ok(()) => info!("[{}] 播放线程已退出", alias),
+                Err(_) => error!("[{}] 播放线程崩溃", alias),
+            }
+            roster.finish(&alias);
+        }
+        if roster.is_done() {
+            return Ok(());
+        }
+        thread::sleep(RESCAN_INTERVAL);
+    }
+}
+
+/// 一次枚举相对上一次的变化。
+#[derive(Debug, Default)]
+struct Changes {
+    /// 需要启动播放线程的新设备。
+    added: Vec<DeviceEntry>,
+    /// 播放线程仍在运行、但这次没有枚举到的设备。
+    gone: Vec<String>,
+    /// 拔出后又出现的设备，由原来的线程继续播放。
+    returned: Vec<String>,
+}
+
+/// 按别名记录各设备的播放线程，决定每次枚举后启动哪些线程。
+#[derive(Debug, Default)]
+struct Roster {
+    running: BTreeSet<String>,
+    /// 播放线程在运行但设备已拔出。
+    missing: BTreeSet<String>,
+    /// 已结束或无法分配内容的别名，不再重新启动。
+    finished: BTreeSet<String>,
+}
+
+impl Roster {
+    /// 比较新的枚举结果，新设备记为运行中。同一别名只会出现在一次 `added` 中。
+    fn rescan(&mut self, entries: Vec<DeviceEntry>) -> Changes {
+        let mut changes = Changes::default();
+        let mut seen = BTreeSet::new();
+        for entry in entries {
+            let alias = entry.alias();
+            if !seen.insert(alias.clone()) || self.finished.contains(&alias) {
+                continue;
+            }
+            if self.missing.remove(&alias) {
+                changes.returned.push(alias);
+            } else if self.running.insert(alias) {
+                changes.added.push(entry);
+            }
+        }
+        for alias in &self.running {
+            if !seen.contains(alias) && self.missing.insert(alias.clone()) {
+                changes.gone.push(alias.clone());
+            }
+        }
+        changes
+    }
+
+    /// 播放线程已结束或未能启动。
+    fn finish(&mut self, alias: &str) {
+        self.running.remove(alias);
+        self.missing.remove(alias);
+        self.finished.insert(alias.to_string());
+    }
+
+    /// 启动过的线程都已结束。
+    fn is_done(&self) -> bool {
+        self.running.is_empty() && !self.finished.is_empty()
+    }
+}
+
+fn spawn(
+    task: DeviceTask,
+    baud_rate: u32,
+    play_options: PlayOptions,
+    cache: Arc<FrameCache>,
+) -> Result<JoinHandle<()>> {
+    let handle = thread::Builder::new()
+        .name(format!("screen-{}", task.alias))
+        .spawn(move || {
+            let DeviceTask {
+                alias,
+                matcher,
+                screen_options,
+                images,
+            } = task;
+            let mut supervisor =
+                Supervisor::new(move || Screen::open_with(&matcher, baud_rate, &screen_options));
+            if let Err(err) = player::play(&alias, &mut supervisor, &images, &play_options, &cache)
+            {
+                error!("[{}] 播放停止: {:?}", alias, err);
+            }
+        })?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(port: &str, serial: Option<&str>) -> DeviceEntry {
+        DeviceEntry {
+            port_name: port.to_string(),
+            usb_id: Some((0x2E8A, 0x000A)),
+            serial_number: serial.map(str::to_string),
+            product: None,
+        }
+    }
+
+    fn added(changes: &Changes) -> Vec<String> {
+        changes.added.iter().map(|e| e.alias()).collect()
+    }
+
+    #[test]
+    fn starts_one_thread_per_new_alias() {
+        let mut roster = Roster::default();
+        let a = entry("/dev/ttyACM0", Some("A"));
+        let b = entry("/dev/ttyACM1", Some("B"));
+
+        let changes = roster.rescan(vec![a.clone()]);
+        assert_eq!(added(&changes), ["A"]);
+        assert!(!roster.is_done());
+
+        let changes = roster.rescan(vec![a.clone(), b.clone(), b.clone()]);
+        assert_eq!(added(&changes), ["B"]);
+        assert!(roster.rescan(vec![a, b]).added.is_empty());
+    }
+
+    #[test]
+    fn replugged_device_keeps_its_thread() {
+        let mut roster = Roster::default();
+        let a = entry("/dev/ttyACM0", Some("A"));
+        assert_eq!(added(&roster.rescan(vec![a.clone()])), ["A"]);
+
+        let changes = roster.rescan(Vec::new());
+        assert_eq!(changes.gone, ["A"]);
+        assert!(changes.added.is_empty());
+        // 拔出期间只报告一次
+        assert!(roster.rescan(Vec::new()).gone.is_empty());
+
+        // 换了串口重新插入，别名（序列号）不变，由原来的线程继续播放
+        let moved = entry("/dev/ttyACM3", Some("A"));
+        let changes = roster.rescan(vec![moved.clone()]);
+        assert!(changes.added.is_empty());
+        assert_eq!(changes.returned, ["A"]);
+        let changes = roster.rescan(vec![moved]);
+        assert!(changes.added.is_empty() && changes.returned.is_empty() && changes.gone.is_empty());
+    }
+
+    #[test]
+    fn finished_aliases_are_not_restarted() {
+        let mut roster = Roster::default();
+        let a = entry("/dev/ttyACM0", Some("A"));
+        let b = entry("/dev/ttyACM1", None);
+        roster.rescan(vec![a.clone(), b.clone()]);
+
+        roster.finish("A");
+        assert!(!roster.is_done());
+        assert!(roster.rescan(vec![a.clone(), b.clone()]).added.is_empty());
+
+        // 已结束的设备拔出也不再报告
+        assert!(roster.rescan(vec![b.clone()]).gone.is_empty());
+        roster.finish("/dev/ttyACM1");
+        assert!(roster.is_done());
+        assert!(roster.rescan(vec![a, b]).added.is_empty());
+    }
+
+    #[test]
+    fn waits_for_the_first_device() {
+        let mut roster = Roster::default();
+        assert!(roster.rescan(Vec::new()).added.is_empty());
+        assert!(!roster.is_done());
+    }
+}
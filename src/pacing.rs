@@ -0,0 +1,277 @@
+//! 帧节奏控制和实际帧率统计。
+//!
+//! [`Pacer`] 按单调时钟上的截止时间排帧：每帧的截止时间是上一帧截止时间加上帧长，
+//! 解码、发送的耗时都计算在内，误差不会逐帧累积。落后太多（例如重连之后）时
+//! 从当前时间重新开始，不会为了追赶而连续快速发送。
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 落后超过这个时间就放弃追赶。
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+/// 基于截止时间的帧节奏。
+#[derive(Debug, Default)]
+pub struct Pacer {
+    deadline: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new() -> Pacer {
+        Pacer::default()
+    }
+
+    /// 当前帧已发出，等到它显示满 `duration` 为止。返回这一帧是否迟到：
+    /// 发出时已经过了它应结束显示的时间，或落后太多而重新计时。
+    pub fn wait(&mut self, duration: Duration) -> bool {
+        let now = Instant::now();
+        let (sleep, late) = self.schedule(now, duration);
+        if !sleep.is_zero() {
+            thread::sleep(sleep);
+        }
+        late
+    }
+
+    /// 丢弃节奏，下一帧从当前时间开始计时。
+    pub fn reset(&mut self) {
+        self.deadline = None;
+    }
+
+    /// 在 `now` 发出一帧后应等待的时间，以及这一帧是否迟到。
+    fn schedule(&mut self, now: Instant, duration: Duration) -> (Duration, bool) {
+        let (start, restarted) = match self.deadline {
+            Some(deadline) if now <= deadline + MAX_LAG => (deadline, false),
+            Some(_) => (now, true),
+            None => (now, false),
+        };
+        let deadline = start + duration;
+        self.deadline = Some(deadline);
+        let sleep = deadline.saturating_duration_since(now);
+        (sleep, restarted || (sleep.is_zero() && !duration.is_zero()))
+    }
+}
+
+/// 一段时间内的帧率、帧间隔分布和吞吐量。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameReport {
+    pub frames: u64,
+    /// 发出时已过截止时间的帧数。
+    pub late: u64,
+    pub fps: f64,
+    pub avg: Duration,
+    pub min: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    pub bytes_per_sec: f64,
+}
+
+impl fmt::Display for FrameReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} fps（{} 帧，迟到 {} 帧），帧间隔 平均 {:.1}ms / 最小 {:.1}ms / p50 {:.1}ms / p95 {:.1}ms / p99 {:.1}ms / 最大 {:.1}ms，{:.1} KiB/s",
+            self.fps,
+            self.frames,
+            self.late,
+            millis(self.avg),
+            millis(self.min),
+            millis(self.p50),
+            millis(self.p95),
+            millis(self.p99),
+            millis(self.max),
+            self.bytes_per_sec / 1024.0
+        )
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// 记录每帧的发送时间，按统计窗口汇总。
+#[derive(Debug)]
+pub struct FrameStats {
+    window_start: Instant,
+    last_frame: Option<Instant>,
+    intervals: Vec<Duration>,
+    frames: u64,
+    late: u64,
+    bytes: u64,
+}
+
+impl Default for FrameStats {
+    fn default() -> FrameStats {
+        FrameStats::new()
+    }
+}
+
+impl FrameStats {
+    pub fn new() -> FrameStats {
+        FrameStats {
+            window_start: Instant::now(),
+            last_frame: None,
+            intervals: Vec::new(),
+            frames: 0,
+            late: 0,
+            bytes: 0,
+        }
+    }
+
+    /// 记录一帧发送完成，`bytes` 为这一帧实际写入端口的字节数。
+    pub fn record(&mut self, bytes: u64) {
+        self.record_at(Instant::now(), bytes);
+    }
+
+    /// 记录一帧迟到，见 [`Pacer::wait`]。
+    pub fn record_late(&mut self) {
+        self.late += 1;
+    }
+
+    /// 当前窗口的时长。
+    pub fn elapsed(&self) -> Duration {
+        self.window_start.elapsed()
+    }
+
+    /// 汇总当前窗口并开始新窗口。
+    pub fn report(&mut self) -> FrameReport {
+        self.report_at(Instant::now())
+    }
+
+    fn record_at(&mut self, now: Instant, bytes: u64) {
+        if let Some(last) = self.last_frame {
+            self.intervals.push(now - last);
+        }
+        self.last_frame = Some(now);
+        self.frames += 1;
+        self.bytes += bytes;
+    }
+
+    fn report_at(&mut self, now: Instant) -> FrameReport {
+        let seconds = (now - self.window_start).as_secs_f64().max(f64::EPSILON);
+        self.intervals.sort_unstable();
+        let percentile = |p: usize| {
+            if self.intervals.is_empty() {
+                Duration::ZERO
+            } else {
+                self.intervals[(self.intervals.len() - 1) * p / 100]
+            }
+        };
+        let avg = match self.intervals.len() {
+            0 => Duration::ZERO,
+            n => self.intervals.iter().sum::<Duration>() / n as u32,
+        };
+        let report = FrameReport {
+            frames: self.frames,
+            late: self.late,
+            fps: self.frames as f64 / seconds,
+            avg,
+            min: self.intervals.first().copied().unwrap_or_default(),
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: self.intervals.last().copied().unwrap_or_default(),
+            bytes_per_sec: self.bytes as f64 / seconds,
+        };
+        self.window_start = now;
+        self.intervals.clear();
+        self.frames = 0;
+        self.late = 0;
+        self.bytes = 0;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    fn ms(value: u64) -> Duration {
+        Duration::from_millis(value)
+    }
+
+    #[test]
+    fn deadlines_advance_from_previous_deadline() {
+        let base = Instant::now();
+        let mut pacer = Pacer::new();
+        assert_eq!(pacer.schedule(base, FRAME), (FRAME, false));
+        // 每帧都花 5ms 发送，等待时间相应缩短，截止时间仍是 100ms 的整数倍
+        for i in 1..10 {
+            let now = base + FRAME * i + ms(5);
+            assert_eq!(pacer.schedule(now, FRAME), (ms(95), false));
+        }
+        assert_eq!(pacer.deadline, Some(base + FRAME * 10));
+    }
+
+    #[test]
+    fn catches_up_small_lags_and_flags_late_frames() {
+        let base = Instant::now();
+        let mut pacer = Pacer::new();
+        pacer.schedule(base, FRAME);
+        // 晚了 30ms：这一帧少显示 30ms，节奏不变，不算迟到
+        assert_eq!(pacer.schedule(base + ms(130), FRAME), (ms(70), false));
+        // 晚到连自己的显示时间都用完时不等待，记为迟到，下一帧追回来
+        assert_eq!(pacer.schedule(base + ms(320), FRAME), (ms(0), true));
+        assert_eq!(pacer.deadline, Some(base + ms(300)));
+        assert_eq!(pacer.schedule(base + ms(330), FRAME), (ms(70), false));
+    }
+
+    #[test]
+    fn restarts_after_falling_too_far_behind() {
+        let base = Instant::now();
+        let mut pacer = Pacer::new();
+        pacer.schedule(base, FRAME);
+        // 落后超过 MAX_LAG（例如重连）：从当前时间重新计时，不连续快速补发
+        let now = base + FRAME + MAX_LAG + ms(1);
+        assert_eq!(pacer.schedule(now, FRAME), (FRAME, true));
+        assert_eq!(pacer.schedule(now + FRAME, FRAME), (FRAME, false));
+
+        pacer.reset();
+        let later = now + ms(5000);
+        assert_eq!(pacer.schedule(later, FRAME), (FRAME, false));
+    }
+
+    #[test]
+    fn reports_interval_distribution() {
+        let base = Instant::now();
+        let mut stats = FrameStats::new();
+        stats.window_start = base;
+        // 间隔 10、20、…、100ms
+        let mut now = base;
+        stats.record_at(now, 1024);
+        for i in 1..=10 {
+            now += ms(10 * i);
+            stats.record_at(now, 1024);
+        }
+        stats.record_late();
+        stats.record_late();
+        let report = stats.report_at(base + ms(2000));
+        assert_eq!(report.frames, 11);
+        assert_eq!(report.late, 2);
+        assert_eq!(report.avg, ms(55));
+        assert_eq!(report.min, ms(10));
+        assert_eq!(report.max, ms(100));
+        assert_eq!(report.p50, ms(50));
+        assert_eq!(report.p95, ms(90));
+        assert_eq!(report.p99, ms(90));
+        assert!((report.fps - 5.5).abs() < 1e-9);
+        assert!((report.bytes_per_sec - 11.0 * 1024.0 / 2.0).abs() < 1e-9);
+        assert_eq!(
+            report.to_string(),
+            "5.5 fps（11 帧，迟到 2 帧），帧间隔 平均 55.0ms / 最小 10.0ms / p50 50.0ms / p95 90.0ms / p99 90.0ms / 最大 100.0ms，5.5 KiB/s"
+        );
+
+        // 汇总后开始新窗口
+        let empty = stats.report_at(base + ms(3000));
+        assert_eq!((empty.frames, empty.late), (0, 0));
+        assert_eq!(
+            (empty.avg, empty.min, empty.max),
+            (Duration::ZERO, Duration::ZERO, Duration::ZERO)
+        );
+        assert_eq!(empty.fps, 0.0);
+    }
+}
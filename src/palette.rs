@@ -0,0 +1,295 @@
+//! 8 位索引色使用的调色板。
+//!
+//! 默认调色板为 RGB332（红绿各 8 级、蓝 4 级）。也可以从文本文件加载，
+//! 每行一个 `#rrggbb` 或 `rrggbb` 颜色，空行和以 `;`、`//` 开头的行被忽略，
+//! 与 Lospec 等网站导出的 `.hex` 调色板兼容。连接屏幕后调色板通过
+//! [`crate::protocol::Command::Palette`] 包发送给设备。
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+
+use crate::dither::Quantize;
+
+/// 调色板最多容纳的颜色数。
+pub const MAX_COLORS: usize = 256;
+
+/// 查找表按每通道 5 位索引。
+const LOOKUP_BITS: u32 = 5;
+
+/// 最多 256 种颜色的调色板。
+#[derive(Debug)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+    /// 调色板中的颜色到索引，编码时优先精确匹配。
+    exact: HashMap<[u8; 3], u8>,
+    /// 按颜色高位索引的最近颜色表，首次使用时计算。
+    lookup: OnceLock<Vec<u8>>,
+}
+
+impl PartialEq for Palette {
+    fn eq(&self, other: &Palette) -> bool {
+        self.colors == other.colors
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::rgb332()
+    }
+}
+
+impl Palette {
+    pub fn new(colors: Vec<[u8; 3]>) -> Result<Palette> {
+        if colors.is_empty() || colors.len() > MAX_COLORS {
+            bail!(
+                "调色板应有 1 到 {} 种颜色，当前为 {}",
+                MAX_COLORS,
+                colors.len()
+            );
+        }
+        let mut exact = HashMap::with_capacity(colors.len());
+        for (i, color) in colors.iter().enumerate() {
+            exact.entry(*color).or_insert(i as u8);
+        }
+        Ok(Palette {
+            colors,
+            exact,
+            lookup: OnceLock::new(),
+        })
+    }
+
+    /// 红、绿各 3 位，蓝 2 位。
+    pub fn rgb332() -> Palette {
+        let expand = |value: u32, bits: u32| (value * 255 / ((1 << bits) - 1)) as u8;
+        let colors = (0..256u32)
+            .map(|i| [expand(i >> 5, 3), expand((i >> 2) & 7, 3), expand(i & 3, 2)])
+            .collect();
+        Palette::new(colors).unwrap()
+    }
+
+    /// 从文本文件加载，格式见模块文档。
+    pub fn load(path: impl AsRef<Path>) -> Result<Palette> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("无法读取调色板 {}", path.display()))?;
+        let mut colors = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with("//") {
+                continue;
+            }
+            let hex = line.strip_prefix('#').unwrap_or(line);
+            let value = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .with_context(|| {
+                    format!("{} 第 {} 行: 无效的颜色: {}", path.display(), i + 1, line)
+                })?;
+            colors.push([(value >> 16) as u8, (value >> 8) as u8, value as u8]);
+        }
+        Palette::new(colors).with_context(|| format!("调色板 {}", path.display()))
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// 发送给设备的负载：每种颜色 3 字节 RGB。
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.colors.concat()
+    }
+
+    /// 由 [`Palette::to_bytes`] 的结果还原。
+    pub fn from_bytes(data: &[u8]) -> Result<Palette> {
+        if !data.len().is_multiple_of(3) {
+            bail!("调色板数据长度 {} 不是 3 的倍数", data.len());
+        }
+        Palette::new(data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect())
+    }
+
+    /// 用于缓存键，区分不同的调色板。
+    pub fn checksum(&self) -> u32 {
+        crc32fast::hash(&self.to_bytes())
+    }
+
+    /// 离 `color` 最近的颜色的索引。
+    pub fn index(&self, color: [u8; 3]) -> u8 {
+        if let Some(&index) = self.exact.get(&color) {
+            return index;
+        }
+        let shift = 8 - LOOKUP_BITS;
+        let key = ((color[0] as usize >> shift) << (2 * LOOKUP_BITS))
+            | ((color[1] as usize >> shift) << LOOKUP_BITS)
+            | (color[2] as usize >> shift);
+        self.lookup.get_or_init(|| self.build_lookup())[key]
+    }
+
+    /// 对每个查找表格子取中心点，找出最近的颜色。
+    fn build_lookup(&self) -> Vec<u8> {
+        let levels = 1usize << LOOKUP_BITS;
+        let center = |v: usize| ((v << (8 - LOOKUP_BITS)) + (1 << (7 - LOOKUP_BITS))) as i32;
+        let mut table = Vec::with_capacity(levels * levels * levels);
+        for r in 0..levels {
+            for g in 0..levels {
+                for b in 0..levels {
+                    table.push(self.search([center(r), center(g), center(b)]));
+                }
+            }
+        }
+        table
+    }
+
+    /// 逐个比较，按加权欧氏距离找最近的颜色。
+    fn search(&self, color: [i32; 3]) -> u8 {
+        let distance = |c: &[u8; 3]| {
+            let d = [
+                c[0] as i32 - color[0],
+                c[1] as i32 - color[1],
+                c[2] as i32 - color[2],
+            ];
+            // 人眼对绿色最敏感，蓝色最不敏感
+            2 * d[0] * d[0] + 4 * d[1] * d[1] + 3 * d[2] * d[2]
+        };
+        (0..self.colors.len())
+            .min_by_key(|&i| distance(&self.colors[i]))
+            .unwrap() as u8
+    }
+}
+
+impl Quantize for Palette {
+    fn nearest(&self, color: [f32; 3]) -> [u8; 3] {
+        let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+        self.colors[self.index([clamp(color[0]), clamp(color[1]), clamp(color[2])]) as usize]
+    }
+
+    /// 按颜色数估计：调色板越小，相邻颜色间隔越大。
+    fn step(&self) -> [f32; 3] {
+        let levels = (self.colors.len() as f32).cbrt().max(2.0);
+        [255.0 / (levels - 1.0); 3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use image::{Rgba, RgbaImage};
+
+    use crate::format::{Encoder, PixelFormat};
+
+    const PRIMARIES: [[u8; 3]; 5] = [
+        [0, 0, 0],
+        [255, 255, 255],
+        [255, 0, 0],
+        [0, 255, 0],
+        [0, 0, 255],
+    ];
+
+    fn write_palette(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "usb-screen-palette-{}-{}.hex",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let palette = Palette::rgb332();
+        let bytes = palette.to_bytes();
+        assert_eq!(bytes.len(), 768);
+        assert_eq!(&bytes[..6], [0, 0, 0, 0, 0, 85]);
+        let restored = Palette::from_bytes(&bytes).unwrap();
+        assert_eq!(restored, palette);
+        assert_eq!(restored.checksum(), palette.checksum());
+
+        assert!(Palette::from_bytes(&bytes[..767]).is_err());
+        assert!(Palette::from_bytes(&[]).is_err());
+        assert!(Palette::from_bytes(&[0; 257 * 3]).is_err());
+    }
+
+    #[test]
+    fn loads_hex_files() {
+        let path = write_palette(
+            "ok",
+            "; Lospec 导出\n// 注释\n\n#000000\nffffff\n  #FF0000  \n00ff00\n#0000ff\n",
+        );
+        let palette = Palette::load(&path).unwrap();
+        assert_eq!(palette.colors(), PRIMARIES);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let cases = [
+            ("short", "#000000\n#12345\n", "第 2 行"),
+            ("letters", "zzzzzz\n", "第 1 行"),
+            ("alpha", "#000000ff\n", "第 1 行"),
+            ("empty", "; 只有注释\n", "1 到 256"),
+        ];
+        for (name, text, message) in cases {
+            let path = write_palette(name, text);
+            let err = format!("{:#}", Palette::load(&path).unwrap_err());
+            assert!(err.contains(message), "{}: {}", name, err);
+            fs::remove_file(&path).unwrap();
+        }
+
+        let oversized: String = (0..257).map(|i| format!("#{:06x}\n", i)).collect();
+        let path = write_palette("oversized", &oversized);
+        let err = format!("{:#}", Palette::load(&path).unwrap_err());
+        assert!(err.contains("当前为 257"), "{}", err);
+        fs::remove_file(&path).unwrap();
+
+        assert!(Palette::load("/nonexistent/palette.hex").is_err());
+    }
+
+    #[test]
+    fn finds_exact_and_nearest_colors() {
+        let palette = Palette::new(PRIMARIES.to_vec()).unwrap();
+        for (i, color) in PRIMARIES.iter().enumerate() {
+            assert_eq!(palette.index(*color), i as u8);
+        }
+        assert_eq!(palette.index([250, 10, 5]), 2);
+        assert_eq!(palette.index([30, 20, 25]), 0);
+        assert_eq!(palette.index([220, 230, 240]), 1);
+        assert_eq!(palette.index([10, 200, 40]), 3);
+        assert_eq!(palette.index([20, 30, 180]), 4);
+        assert_eq!(palette.nearest([240.4, 3.0, -7.0]), [255, 0, 0]);
+
+        // 重复的颜色取第一个索引
+        let duplicated = Palette::new(vec![[9, 9, 9], [1, 2, 3], [9, 9, 9]]).unwrap();
+        assert_eq!(duplicated.index([9, 9, 9]), 0);
+
+        // 默认调色板中的每种颜色都精确命中
+        let rgb332 = Palette::rgb332();
+        for (i, color) in rgb332.colors().iter().enumerate() {
+            assert_eq!(rgb332.index(*color), i as u8);
+        }
+    }
+
+    #[test]
+    fn indexed_frames_round_trip() {
+        let path = write_palette("frame", "#000000\n#ffffff\n#ff0000\n#00ff00\n#0000ff\n");
+        let encoder = Encoder::new(
+            PixelFormat::Indexed8,
+            Arc::new(Palette::load(&path).unwrap()),
+        );
+        fs::remove_file(&path).unwrap();
+        let image = RgbaImage::from_fn(5, 3, |x, y| {
+            let [r, g, b] = PRIMARIES[((x + y) % 5) as usize];
+            Rgba([r, g, b, 255])
+        });
+        let data = encoder.encode(&image);
+        assert_eq!(&data[..5], [0, 1, 2, 3, 4]);
+        assert_eq!(&data[5..10], [1, 2, 3, 4, 0]);
+        assert_eq!(encoder.decode(&data, 5, 3).unwrap(), image);
+    }
+}
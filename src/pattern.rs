@@ -0,0 +1,456 @@
+//! 测试图案，用于新面板接线调试：检查接线、颜色顺序、字节序、分辨率和撕裂。
+//!
+//! `moving-bar` 和 `counter` 是动态图案，逐帧生成，见 [`Pattern::render_frame`]。
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use image::{Rgba, RgbaImage};
+
+use crate::fit::parse_color;
+use crate::font::{self, GLYPH_HEIGHT};
+
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// 有名字的纯色，其他颜色写作 `solid:#rrggbb`。
+const NAMED_COLORS: [(&str, [u8; 3]); 5] = [
+    ("white", [255, 255, 255]),
+    ("black", [0, 0, 0]),
+    ("red", [255, 0, 0]),
+    ("green", [0, 255, 0]),
+    ("blue", [0, 0, 255]),
+];
+
+/// 内置测试图案。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pattern {
+    /// 八条竖直彩条：白、黄、青、绿、品红、红、蓝、黑。
+    #[default]
+    Bars,
+    /// SMPTE 彩条：75% 彩条、反向色块、-I/白/+Q 和 PLUGE 暗场。
+    Smpte,
+    /// 红、绿、蓝、白四段由暗到亮的渐变。
+    ///
+    /// RGB565 下红、蓝应为 32 级、绿为 64 级的平滑台阶；通道接错时颜色错位，
+    /// 字节序反了则出现明显跳变的杂色条纹。
+    Gradient,
+    /// 16 像素黑白棋盘格。
+    Checkerboard,
+    /// 每 16 像素一条细线、每 64 像素一条粗线并标注坐标，红色边框和绿色中心十字。
+    Grid,
+    /// 纯色填充。
+    Solid(Rgba<u8>),
+    /// 黑底上水平移动的白色竖条，用于检查撕裂和延迟，左上角为帧号。
+    MovingBar,
+    /// 大号帧号和逐帧点亮的十个方块，拍照即可对比主机与屏幕的帧。
+    Counter,
+    /// 颜色校准用：gamma 对照块、16 级灰阶、暗部和亮部细节、纯色和肤色。
+    Calibration,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 13] = [
+        Pattern::Bars,
+        Pattern::Smpte,
+        Pattern::Gradient,
+        Pattern::Checkerboard,
+        Pattern::Grid,
+        Pattern::Solid(WHITE),
+        Pattern::Solid(BLACK),
+        Pattern::Solid(Rgba([255, 0, 0, 255])),
+        Pattern::Solid(Rgba([0, 255, 0, 255])),
+        Pattern::Solid(Rgba([0, 0, 255, 255])),
+        Pattern::MovingBar,
+        Pattern::Counter,
+        Pattern::Calibration,
+    ];
+
+    /// 是否需要逐帧刷新。
+    pub fn is_animated(&self) -> bool {
+        matches!(self, Pattern::MovingBar | Pattern::Counter)
+    }
+
+    /// 生成 `width` x `height` 的图案，动态图案为第 0 帧。
+    pub fn render(&self, width: u32, height: u32) -> RgbaImage {
+        self.render_frame(width, height, 0)
+    }
+
+    /// 生成第 `frame` 帧，静态图案与帧号无关。
+    pub fn render_frame(&self, width: u32, height: u32, frame: u64) -> RgbaImage {
+        match self {
+            Pattern::Bars => {
+                const COLORS: [[u8; 3]; 8] = [
+                    [255, 255, 255],
+                    [255, 255, 0],
+                    [0, 255, 255],
+                    [0, 255, 0],
+                    [255, 0, 255],
+                    [255, 0, 0],
+                    [0, 0, 255],
+                    [0, 0, 0],
+                ];
+                RgbaImage::from_fn(width, height, |x, _| {
+                    let [r, g, b] = COLORS[(x * 8 / width) as usize];
+                    Rgba([r, g, b, 255])
+                })
+            }
+            Pattern::Smpte => smpte(width, height),
+            Pattern::Gradient => RgbaImage::from_fn(width, height, |x, y| {
+                let level = (x * 255 / (width - 1).max(1)) as u8;
+                match y * 4 / height {
+                    3 => Rgba([level, level, level, 255]),
+                    band => {
+                        let mut pixel = [0, 0, 0, 255];
+                        pixel[band as usize] = level;
+                        Rgba(pixel)
+                    }
+                }
+            }),
+            Pattern::Checkerboard => RgbaImage::from_fn(width, height, |x, y| {
+                if (x / 16 + y / 16) % 2 == 0 {
+                    WHITE
+                } else {
+                    BLACK
+                }
+            }),
+            Pattern::Grid => grid(width, height),
+            Pattern::Solid(color) => RgbaImage::from_pixel(width, height, *color),
+            Pattern::MovingBar => moving_bar(width, height, frame),
+            Pattern::Counter => counter(width, height, frame),
+            Pattern::Calibration => calibration(width, height),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Bars => f.write_str("bars"),
+            Pattern::Smpte => f.write_str("smpte"),
+            Pattern::Gradient => f.write_str("gradient"),
+            Pattern::Checkerboard => f.write_str("checkerboard"),
+            Pattern::Grid => f.write_str("grid"),
+            Pattern::Solid(Rgba([r, g, b, _])) => {
+                match NAMED_COLORS.iter().find(|(_, rgb)| *rgb == [*r, *g, *b]) {
+                    Some((name, _)) => f.write_str(name),
+                    None => write!(f, "solid:#{:02x}{:02x}{:02x}", r, g, b),
+                }
+            }
+            Pattern::MovingBar => f.write_str("moving-bar"),
+            Pattern::Counter => f.write_str("counter"),
+            Pattern::Calibration => f.write_str("calibration"),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Pattern> {
+        let name = s.trim().to_ascii_lowercase();
+        if let Some(color) = name.strip_prefix("solid:") {
+            return Ok(Pattern::Solid(parse_color(color)?));
+        }
+        match Pattern::ALL.iter().find(|p| p.to_string() == name) {
+            Some(pattern) => Ok(*pattern),
+            None => {
+                let names: Vec<String> = Pattern::ALL.iter().map(|p| p.to_string()).collect();
+                bail!(
+                    "未知的测试图案: {}（可选 {}、solid:#rrggbb）",
+                    s,
+                    names.join("、")
+                )
+            }
+        }
+    }
+}
+
+/// SMPTE ECR 1-1978 彩条，亮度带 7.5% 黑电平。
+fn smpte(width: u32, height: u32) -> RgbaImage {
+    const BARS: [[u8; 3]; 7] = [
+        [192, 192, 192],
+        [192, 192, 0],
+        [0, 192, 192],
+        [0, 192, 0],
+        [192, 0, 192],
+        [192, 0, 0],
+        [0, 0, 192],
+    ];
+    const BLACK: [u8; 3] = [19, 19, 19];
+    const CASTELLATIONS: [[u8; 3]; 7] = [BARS[6], BLACK, BARS[4], BLACK, BARS[2], BLACK, BARS[0]];
+    RgbaImage::from_fn(width, height, |x, y| {
+        let bar = (x * 7 / width) as usize;
+        let [r, g, b] = match y * 12 / height {
+            0..=7 => BARS[bar],
+            8 => CASTELLATIONS[bar],
+            // 底部以 1/12 条宽为单位：-I、白、+Q、黑各 15，PLUGE 各 4，其余为黑
+            _ => match x * 84 / width {
+                0..=14 => [0, 33, 76],
+                15..=29 => [255, 255, 255],
+                30..=44 => [50, 0, 106],
+                60..=63 => [9, 9, 9],
+                68..=71 => [29, 29, 29],
+                _ => BLACK,
+            },
+        };
+        Rgba([r, g, b, 255])
+    })
+}
+
+fn grid(width: u32, height: u32) -> RgbaImage {
+    let mut image = RgbaImage::from_fn(width, height, |x, y| {
+        if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+            Rgba([255, 0, 0, 255])
+        } else if x % 64 == 0 || y % 64 == 0 {
+            Rgba([160, 160, 160, 255])
+        } else if x % 16 == 0 || y % 16 == 0 {
+            Rgba([64, 64, 64, 255])
+        } else {
+            BLACK
+        }
+    });
+    let label = Rgba([255, 255, 0, 255]);
+    for y in (0..height).step_by(64) {
+        for x in (0..width).step_by(64) {
+            let text = format!("{},{}", x, y);
+            font::draw_text(&mut image, x as i64 + 3, y as i64 + 3, 1, &text, label);
+        }
+    }
+    let (cx, cy) = (width / 2, height / 2);
+    let green = Rgba([0, 255, 0, 255]);
+    for x in cx.saturating_sub(8)..(cx + 9).min(width) {
+        image.put_pixel(x, cy, green);
+    }
+    for y in cy.saturating_sub(8)..(cy + 9).min(height) {
+        image.put_pixel(cx, y, green);
+    }
+    let text = format!("{}x{}", width, height);
+    let text_width = font::text_width(&text, 2);
+    let left = cx.saturating_sub(text_width / 2);
+    let top = cy + 12;
+    // 黑底盖住下面的网格和坐标，保证可读
+    for y in top.saturating_sub(2)..(top + GLYPH_HEIGHT * 2 + 2).min(height) {
+        for x in left.saturating_sub(2)..(left + text_width + 2).min(width) {
+            image.put_pixel(x, y, BLACK);
+        }
+    }
+    font::draw_text(&mut image, left as i64, top as i64, 2, &text, WHITE);
+    image
+}
+
+/// 竖条每帧移动的像素数。
+const BAR_STEP: u32 = 4;
+const BAR_WIDTH: u32 = 16;
+
+fn moving_bar(width: u32, height: u32, frame: u64) -> RgbaImage {
+    let span = width as u64 + BAR_WIDTH as u64;
+    let left = (frame * BAR_STEP as u64 % span) as i64 - BAR_WIDTH as i64;
+    let mut image = RgbaImage::from_fn(width, height, |x, _| {
+        if (left..left + BAR_WIDTH as i64).contains(&(x as i64)) {
+            WHITE
+        } else {
+            BLACK
+        }
+    });
+    font::draw_text(
+        &mut image,
+        4,
+        4,
+        2,
+        &frame.to_string(),
+        Rgba([255, 255, 0, 255]),
+    );
+    image
+}
+
+fn counter(width: u32, height: u32, frame: u64) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(width, height, BLACK);
+    font::draw_text(&mut image, 4, 4, 2, "FRAME", Rgba([160, 160, 160, 255]));
+    // 帧号尽量大，但不超过画面宽度和一半高度
+    let text = frame.to_string();
+    let scale = (width * 9 / 10 / font::text_width(&text, 1).max(1))
+        .min(height / 2 / GLYPH_HEIGHT)
+        .max(1);
+    let left = (width as i64 - font::text_width(&text, scale) as i64) / 2;
+    let top = (height as i64 - (GLYPH_HEIGHT * scale) as i64) / 2;
+    font::draw_text(&mut image, left, top, scale, &text, WHITE);
+    // 底部十个方块，第 frame % 10 个点亮
+    let size = width / 10;
+    let lit = (frame % 10) as u32;
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if x < size * 10 && y + size / 2 < height && y + size >= height && x % size < size - 2 {
+            *pixel = if x / size == lit {
+                Rgba([0, 255, 0, 255])
+            } else {
+                Rgba([48, 48, 48, 255])
+            };
+        }
+    }
+    image
+}
+
+/// gamma 2.2 下亮度为 50% 的灰度值。
+const HALF_LIGHT: u8 = 186;
+
+/// 从上到下四段：
+///
+/// 1. 灰、红、绿、蓝各一组 gamma 对照块：左半是 0 和 255 交替的横线，
+///    眯眼看时亮度为 50%；右半是纯色 [`HALF_LIGHT`]。两半亮度一致时 gamma 正确；
+/// 2. 16 级灰阶，每一级都应能分辨；
+/// 3. 左半 0 到 28 的暗部，右半 227 到 255 的亮部，各 8 级；
+/// 4. 红、绿、蓝、青、品红、黄和两种肤色。
+fn calibration(width: u32, height: u32) -> RgbaImage {
+    const SWATCHES: [[u8; 3]; 8] = [
+        [255, 0, 0],
+        [0, 255, 0],
+        [0, 0, 255],
+        [0, 255, 255],
+        [255, 0, 255],
+        [255, 255, 0],
+        [234, 192, 134],
+        [141, 85, 36],
+    ];
+    let bands = [height * 2 / 5, height * 3 / 5, height * 4 / 5];
+    // 每一段内 x 所在的格子，`n` 为格子数
+    let cell = |x: u32, n: u32| (x * n / width.max(1)) as usize;
+    RgbaImage::from_fn(width, height, |x, y| {
+        let [r, g, b] = if y < bands[0] {
+            let column = cell(x, 4);
+            let half = x * 8 / width.max(1) % 2 == 1;
+            let level = if half {
+                HALF_LIGHT
+            } else if y % 2 == 0 {
+                255
+            } else {
+                0
+            };
+            match column {
+                0 => [level; 3],
+                c => {
+                    let mut rgb = [0; 3];
+                    rgb[c - 1] = level;
+                    rgb
+                }
+            }
+        } else if y < bands[1] {
+            [(cell(x, 16) * 17) as u8; 3]
+        } else if y < bands[2] {
+            let step = cell(x, 16) as u8;
+            if step < 8 {
+                [step * 4; 3]
+            } else {
+                [227 + (step - 8) * 4; 3]
+            }
+        } else {
+            SWATCHES[cell(x, 8)]
+        };
+        Rgba([r, g, b, 255])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [(u32, u32); 6] = [(1, 1), (2, 3), (8, 8), (9, 1), (64, 48), (320, 240)];
+
+    #[test]
+    fn names_round_trip() {
+        for pattern in Pattern::ALL {
+            let name = pattern.to_string();
+            assert_eq!(name.parse::<Pattern>().unwrap(), pattern, "{}", name);
+        }
+        let custom = Pattern::Solid(Rgba([0x12, 0x34, 0x56, 255]));
+        assert_eq!(custom.to_string(), "solid:#123456");
+        assert_eq!("solid:#123456".parse::<Pattern>().unwrap(), custom);
+        assert_eq!("solid:#f00".parse::<Pattern>().unwrap().to_string(), "red");
+        assert_eq!(" SMPTE ".parse::<Pattern>().unwrap(), Pattern::Smpte);
+
+        let err = "rainbow".parse::<Pattern>().unwrap_err().to_string();
+        assert!(err.contains("moving-bar"), "{}", err);
+        assert!("solid:#12".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn renders_requested_size() {
+        for pattern in Pattern::ALL {
+            for (width, height) in SIZES {
+                for frame in [0, 1, 12_345_678] {
+                    let image = pattern.render_frame(width, height, frame);
+                    assert_eq!(image.dimensions(), (width, height), "{}", pattern);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn static_patterns_have_expected_colors() {
+        let bars = Pattern::Bars.render(80, 4);
+        let expected = [
+            [255, 255, 255],
+            [255, 255, 0],
+            [0, 255, 255],
+            [0, 255, 0],
+            [255, 0, 255],
+            [255, 0, 0],
+            [0, 0, 255],
+            [0, 0, 0],
+        ];
+        for (i, [r, g, b]) in expected.into_iter().enumerate() {
+            let x = i as u32 * 10;
+            assert_eq!(*bars.get_pixel(x, 0), Rgba([r, g, b, 255]));
+            assert_eq!(*bars.get_pixel(x + 9, 3), Rgba([r, g, b, 255]));
+        }
+
+        let board = Pattern::Checkerboard.render(48, 32);
+        assert_eq!(*board.get_pixel(0, 0), WHITE);
+        assert_eq!(*board.get_pixel(15, 15), WHITE);
+        assert_eq!(*board.get_pixel(16, 0), BLACK);
+        assert_eq!(*board.get_pixel(0, 16), BLACK);
+        assert_eq!(*board.get_pixel(16, 16), WHITE);
+        assert_eq!(*board.get_pixel(47, 31), BLACK);
+
+        let color = Rgba([1, 2, 3, 255]);
+        assert!(Pattern::Solid(color)
+            .render(5, 7)
+            .pixels()
+            .all(|p| *p == color));
+
+        let gradient = Pattern::Gradient.render(256, 8);
+        assert_eq!(*gradient.get_pixel(255, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*gradient.get_pixel(255, 2), Rgba([0, 255, 0, 255]));
+        assert_eq!(*gradient.get_pixel(128, 4), Rgba([0, 0, 128, 255]));
+        assert_eq!(*gradient.get_pixel(0, 7), BLACK);
+    }
+
+    #[test]
+    fn animated_patterns_change_every_frame() {
+        for pattern in [Pattern::MovingBar, Pattern::Counter] {
+            assert!(pattern.is_animated());
+            for frame in [0, 1, 9, 99] {
+                assert_ne!(
+                    pattern.render_frame(160, 120, frame),
+                    pattern.render_frame(160, 120, frame + 1),
+                    "{} 第 {} 帧",
+                    pattern,
+                    frame
+                );
+            }
+        }
+        // 竖条每帧右移 BAR_STEP 像素
+        let bar = |frame| Pattern::MovingBar.render_frame(160, 120, frame);
+        assert_eq!(*bar(4).get_pixel(0, 100), WHITE);
+        assert_eq!(*bar(4).get_pixel(16, 100), BLACK);
+        assert_eq!(*bar(5).get_pixel(19, 100), WHITE);
+
+        for pattern in [Pattern::Bars, Pattern::Grid, Pattern::Calibration] {
+            assert!(!pattern.is_animated());
+            assert_eq!(
+                pattern.render_frame(64, 48, 0),
+                pattern.render_frame(64, 48, 7)
+            );
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! 播放流水线各阶段之间的有界队列。
+//!
+//! 解码、转换和发送分别在独立线程中运行，相邻阶段之间用 [`BoundedQueue`] 连接。
+//! 队列满时按 [`Backpressure`] 处理：阻塞上游，或丢弃最旧的一项。
+//! 任何一端调用 [`BoundedQueue::close`] 后另一端都会得知，用于结束整条流水线。
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex};
+
+use anyhow::{bail, Result};
+
+/// 默认队列深度。
+pub const DEFAULT_QUEUE_DEPTH: usize = 4;
+
+/// 队列满时的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// 阻塞上游，直到下游取走一项。不丢帧，慢的一端决定整体速度。
+    #[default]
+    Block,
+    /// 丢弃队列中最旧的一项，上游从不等待。适合只关心最新画面的场景。
+    DropOldest,
+}
+
+impl fmt::Display for Backpressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backpressure::Block => write!(f, "block"),
+            Backpressure::DropOldest => write!(f, "drop-oldest"),
+        }
+    }
+}
+
+impl FromStr for Backpressure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Backpressure> {
+        match s.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "block" => Ok(Backpressure::Block),
+            "drop-oldest" => Ok(Backpressure::DropOldest),
+            other => bail!("未知的队列策略: {}（可选 block、drop-oldest）", other),
+        }
+    }
+}
+
+/// 队列已关闭，推入的数据被退回。
+#[derive(Debug)]
+pub struct Closed<T>(pub T);
+
+struct State<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    dropped: u64,
+}
+
+/// 多线程共享的有界队列。
+pub struct BoundedQueue<T> {
+    capacity: usize,
+    policy: Backpressure,
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> BoundedQueue<T> {
+    pub fn new(capacity: usize, policy: Backpressure) -> BoundedQueue<T> {
+        BoundedQueue {
+            capacity: capacity.max(1),
+            policy,
+            state: Mutex::new(State {
+                items: VecDeque::new(),
+                closed: false,
+                dropped: 0,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    /// 推入一项，队列满时按策略阻塞或丢弃最旧的一项。
+    pub fn push(&self, item: T) -> Result<(), Closed<T>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return Err(Closed(item));
+            }
+            if state.items.len() < self.capacity {
+                break;
+            }
+            match self.policy {
+                Backpressure::Block => state = self.not_full.wait(state).unwrap(),
+                Backpressure::DropOldest => {
+                    state.items.pop_front();
+                    state.dropped += 1;
+                }
+            }
+        }
+        state.items.push_back(item);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// 取出一项；队列为空时等待，关闭且取空后返回 `None`。
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.not_full.notify_one();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// 关闭队列：上游不能再推入，下游取完剩余数据后结束。
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    /// 下游主动退出时调用：关闭队列并丢弃尚未取走的数据。
+    pub fn abort(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.items.clear();
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    /// 因队列满而被丢弃的项数。
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    /// 给另一个线程足够时间进入等待。
+    const SETTLE: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn drop_oldest_keeps_newest_items() {
+        let queue = BoundedQueue::new(2, Backpressure::DropOldest);
+        for i in 1..=5 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.dropped(), 3);
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.pop(), Some(5));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn block_waits_for_consumer() {
+        let queue = BoundedQueue::new(1, Backpressure::Block);
+        queue.push(1).unwrap();
+        thread::scope(|scope| {
+            let producer = scope.spawn(|| queue.push(2).is_ok());
+            thread::sleep(SETTLE);
+            assert!(!producer.is_finished());
+            assert_eq!(queue.len(), 1);
+            assert_eq!(queue.pop(), Some(1));
+            assert!(producer.join().unwrap());
+        });
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.dropped(), 0);
+    }
+
+    #[test]
+    fn close_drains_remaining_items() {
+        let queue = BoundedQueue::new(4, Backpressure::Block);
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        queue.close();
+        assert!(matches!(queue.push(3), Err(Closed(3))));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn close_wakes_blocked_producer() {
+        let queue = BoundedQueue::new(1, Backpressure::Block);
+        queue.push(1).unwrap();
+        thread::scope(|scope| {
+            let producer = scope.spawn(|| queue.push(2));
+            thread::sleep(SETTLE);
+            queue.close();
+            assert!(matches!(producer.join().unwrap(), Err(Closed(2))));
+        });
+        assert_eq!(queue.pop(), Some(1));
+    }
+
+    #[test]
+    fn abort_discards_items_and_wakes_consumer() {
+        let queue = BoundedQueue::new(4, Backpressure::Block);
+        queue.push(1).unwrap();
+        queue.abort();
+        assert_eq!(queue.pop(), None);
+        assert!(queue.push(2).is_err());
+
+        let queue: BoundedQueue<u32> = BoundedQueue::new(4, Backpressure::Block);
+        thread::scope(|scope| {
+            let consumer = scope.spawn(|| queue.pop());
+            thread::sleep(SETTLE);
+            queue.abort();
+            assert_eq!(consumer.join().unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn parses_policies() {
+        assert_eq!(
+            "block".parse::<Backpressure>().unwrap(),
+            Backpressure::Block
+        );
+        assert_eq!(
+            " Drop_Oldest ".parse::<Backpressure>().unwrap(),
+            Backpressure::DropOldest
+        );
+        assert_eq!(Backpressure::DropOldest.to_string(), "drop-oldest");
+        assert!("newest".parse::<Backpressure>().is_err());
+    }
+}
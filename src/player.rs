@@ -0,0 +1,494 @@
+//! 图片轮播。静态图片每张显示一帧，动画按文件中的帧延时播放。
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use glob::Pattern;
+use image::imageops::FilterType;
+use log::{debug, error, info};
+
+use crate::alpha::Matte;
+use crate::animation::{decode_media, Animation, Decoded, Media};
+use crate::cache::FrameCache;
+use crate::dither::Dither;
+use crate::fit::{Background, Fit, FitMode, Gravity};
+use crate::loader::GLOB_OPTIONS;
+use crate::pacing::{FrameStats, Pacer};
+use crate::pipeline::{Backpressure, BoundedQueue, DEFAULT_QUEUE_DEPTH};
+use crate::render::{RenderOptions, Target};
+use crate::supervisor::Supervisor;
+
+/// 默认帧率。
+pub const DEFAULT_FPS: u32 = 24;
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 按路径为部分图片单独指定的设置。
+#[derive(Debug, Clone)]
+pub struct ItemRule {
+    /// 与完整路径或文件名匹配的 glob，忽略大小写。
+    pub pattern: Pattern,
+    /// 静态图片的显示时长。
+    pub hold: Option<Duration>,
+    pub fit: Option<FitMode>,
+    pub background: Option<Background>,
+    pub gravity: Option<Gravity>,
+}
+
+impl ItemRule {
+    pub fn matches(&self, path: &str) -> bool {
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        self.pattern.matches_with(path, GLOB_OPTIONS)
+            || self.pattern.matches_with(&name, GLOB_OPTIONS)
+    }
+}
+
+/// 播放参数。
+#[derive(Debug, Clone)]
+pub struct PlayOptions {
+    pub fps: u32,
+    /// 图片缩放到屏幕尺寸时使用的算法。
+    pub filter: FilterType,
+    /// 宽高比与屏幕不一致时的处理方式。
+    pub fit: Fit,
+    pub dither: Dither,
+    /// 透明像素下方的底色。
+    pub matte: Matte,
+    /// 每轮开始前打乱播放顺序。
+    pub shuffle: bool,
+    /// 播完一轮后从头再来；为 `false` 时播完即返回。
+    pub repeat: bool,
+    /// 每张静态图片的显示时长，`None` 表示只显示一帧。动画始终按自身的帧延时播放。
+    pub hold: Option<Duration>,
+    /// 单项设置，先匹配到的规则优先。
+    pub items: Vec<ItemRule>,
+    /// 流水线各阶段之间的队列深度。
+    pub queue_depth: usize,
+    pub backpressure: Backpressure,
+}
+
+impl Default for PlayOptions {
+    fn default() -> PlayOptions {
+        PlayOptions {
+            fps: DEFAULT_FPS,
+            filter: FilterType::Lanczos3,
+            fit: Fit::default(),
+            dither: Dither::default(),
+            matte: Matte::default(),
+            shuffle: false,
+            repeat: true,
+            hold: None,
+            items: Vec::new(),
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            backpressure: Backpressure::default(),
+        }
+    }
+}
+
+impl PlayOptions {
+    /// 每帧的时长。
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.fps.max(1)
+    }
+
+    /// 某张静态图片的显示时长。
+    pub fn hold_for(&self, path: &str) -> Duration {
+        self.items
+            .iter()
+            .filter(|rule| rule.matches(path))
+            .find_map(|rule| rule.hold)
+            .or(self.hold)
+            .unwrap_or_else(|| self.frame_duration())
+    }
+
+    /// 某张图片的渲染参数，单项设置中未指定的部分使用全局设置。
+    pub fn render_for(&self, path: &str) -> RenderOptions {
+        let rules: Vec<&ItemRule> = self.items.iter().filter(|r| r.matches(path)).collect();
+        let fit = Fit {
+            mode: rules.iter().find_map(|r| r.fit).unwrap_or(self.fit.mode),
+            background: rules
+                .iter()
+                .find_map(|r| r.background)
+                .unwrap_or(self.fit.background),
+            gravity: rules
+                .iter()
+                .find_map(|r| r.gravity)
+                .unwrap_or(self.fit.gravity),
+        };
+        RenderOptions {
+            filter: self.filter,
+            fit,
+            dither: self.dither,
+            matte: self.matte.clone(),
+        }
+    }
+}
+
+/// 在一块屏幕上循环播放图片，设备掉线后从当前位置继续。
+///
+/// 解码、转换和发送分别在三个线程中进行，见 [`crate::pipeline`]。
+/// 只有图片解码失败才会返回错误；`alias` 用于区分多块屏幕的日志。
+pub fn play(
+    alias: &str,
+    supervisor: &mut Supervisor,
+    images: &[String],
+    options: &PlayOptions,
+    cache: &FrameCache,
+) -> Result<()> {
+    if images.is_empty() {
+        info!("[{}] 没有可播放的图片", alias);
+        return Ok(());
+    }
+
+    // 屏幕尺寸和像素格式由发送线程在每次（重新）连接后更新，上游按它转换
+    let target = Mutex::new(supervisor.screen().target());
+    let decoded = BoundedQueue::new(options.queue_depth, options.backpressure);
+    let converted = BoundedQueue::new(options.queue_depth, options.backpressure);
+
+    thread::scope(|scope| {
+        let spawned = thread::Builder::new()
+            .name(format!("load-{}", alias))
+            .spawn_scoped(scope, || {
+                load_stage(images, options, cache, &target, &decoded)
+            })
+            .and_then(|_| {
+                thread::Builder::new()
+                    .name(format!("convert-{}", alias))
+                    .spawn_scoped(scope, || convert_stage(cache, &decoded, &converted))
+            });
+        if let Err(err) = spawned {
+            decoded.abort();
+            converted.abort();
+            return Err(err.into());
+        }
+
+        let mut sender = Sender {
+            alias,
+            supervisor,
+            pacer: Pacer::new(),
+            frames: FrameStats::new(),
+            dropped: &|| decoded.dropped() + converted.dropped(),
+        };
+        let result = sender.run(images.len(), options, &target, &converted);
+        // 发送端提前结束（出错）时让上游线程退出
+        converted.abort();
+        decoded.abort();
+        result
+    })
+}
+
+/// 解码阶段的产物。
+enum Loaded {
+    /// 缓存命中，已是可发送的数据。
+    Ready(Arc<Media>),
+    /// 需要缩放和转换。
+    Decoded(Decoded),
+}
+
+/// 流水线中传递的一项。
+struct Job<T> {
+    path: String,
+    /// 解码时的目标屏幕。
+    target: Target,
+    /// 静态图片的显示时长。
+    hold: Duration,
+    render: RenderOptions,
+    content: T,
+}
+
+/// 动画的播放遍数，`None` 表示无限循环。
+pub(crate) fn rounds(loop_count: Option<u32>, playlist_len: usize, repeat: bool) -> Option<u32> {
+    match loop_count {
+        Some(n) => Some(n),
+        // 无限循环的动画在列表中还有其他内容时只播一遍，否则一直播放
+        None if playlist_len > 1 || !repeat => Some(1),
+        None => None,
+    }
+}
+
+/// 解码线程：按播放顺序读取图片，缓存命中时直接交给下游。
+fn load_stage(
+    images: &[String],
+    options: &PlayOptions,
+    cache: &FrameCache,
+    target: &Mutex<Target>,
+    output: &BoundedQueue<Result<Job<Loaded>>>,
+) {
+    let mut playlist = images.to_vec();
+    let mut rng = seed();
+    loop {
+        if options.shuffle {
+            shuffle(&mut playlist, &mut rng);
+        }
+        for path in &playlist {
+            let target = target.lock().unwrap().clone();
+            let hold = options.hold_for(path);
+            let render = options.render_for(path);
+            let loaded = load_one(cache, path, &target, &render);
+            // 丢帧模式下上游从不等待，按节目时长自行控制节奏，否则会空转
+            let pace = match &loaded {
+                Ok(content) if options.backpressure == Backpressure::DropOldest => {
+                    let (duration, loop_count) = match content {
+                        Loaded::Ready(media) => match &**media {
+                            Media::Still { .. } => (hold, None),
+                            Media::Animated(a) => (a.duration(), a.loop_count),
+                        },
+                        Loaded::Decoded(d) if d.animated => {
+                            (d.frames.iter().map(|(_, delay)| *delay).sum(), d.loop_count)
+                        }
+                        Loaded::Decoded(_) => (hold, None),
+                    };
+                    duration * rounds(loop_count, playlist.len(), options.repeat).unwrap_or(1)
+                }
+                _ => Duration::ZERO,
+            };
+            let failed = loaded.is_err();
+            let job = loaded.map(|content| Job {
+                path: path.clone(),
+                target,
+                hold,
+                render,
+                content,
+            });
+            if output.push(job).is_err() || failed {
+                output.close();
+                return;
+            }
+            thread::sleep(pace);
+        }
+        debug!("缓存统计: {}", cache.stats());
+        if !options.repeat {
+            break;
+        }
+    }
+    output.close();
+}
+
+fn load_one(
+    cache: &FrameCache,
+    path: &str,
+    target: &Target,
+    render: &RenderOptions,
+) -> Result<Loaded> {
+    if let Some(media) = cache.get(path, target, render)? {
+        return Ok(Loaded::Ready(media));
+    }
+    let decoded = decode_media(path).with_context(|| format!("无法解码 {}", path))?;
+    Ok(Loaded::Decoded(decoded))
+}
+
+/// 转换线程：缩放、编码为屏幕的像素格式并放入缓存。
+fn convert_stage(
+    cache: &FrameCache,
+    input: &BoundedQueue<Result<Job<Loaded>>>,
+    output: &BoundedQueue<Result<Job<Arc<Media>>>>,
+) {
+    while let Some(job) = input.pop() {
+        let job = job.and_then(|job| {
+            let media = match job.content {
+                Loaded::Ready(media) => media,
+                Loaded::Decoded(decoded) => {
+                    let media = decoded.convert(&job.target, &job.render);
+                    cache.put(&job.path, &job.target, &job.render, media)?
+                }
+            };
+            Ok(Job {
+                path: job.path,
+                target: job.target,
+                hold: job.hold,
+                render: job.render,
+                content: media,
+            })
+        });
+        let failed = job.is_err();
+        if output.push(job).is_err() {
+            input.abort();
+            return;
+        }
+        if failed {
+            input.abort();
+            break;
+        }
+    }
+    output.close();
+}
+
+/// 发送线程：按帧时间把画面发给屏幕，掉线后重连并重发当前画面。
+struct Sender<'a> {
+    alias: &'a str,
+    supervisor: &'a mut Supervisor,
+    pacer: Pacer,
+    frames: FrameStats,
+    dropped: &'a dyn Fn() -> u64,
+}
+
+impl Sender<'_> {
+    fn run(
+        &mut self,
+        playlist_len: usize,
+        options: &PlayOptions,
+        target: &Mutex<Target>,
+        input: &BoundedQueue<Result<Job<Arc<Media>>>>,
+    ) -> Result<()> {
+        while let Some(job) = input.pop() {
+            let job = job?;
+            loop {
+                let current = self.supervisor.screen().target();
+                // 重连到不同尺寸或格式的屏幕后，已排队的旧画面直接跳过
+                let stale = job.target != current;
+                *target.lock().unwrap() = current;
+                if stale {
+                    debug!("[{}] 跳过尺寸或格式不符的 {}", self.alias, job.path);
+                    break;
+                }
+                let result = match &*job.content {
+                    Media::Still { pixels, .. } => self.show(pixels, job.hold),
+                    Media::Animated(animation) => {
+                        let rounds = rounds(animation.loop_count, playlist_len, options.repeat);
+                        self.play_animation(animation, rounds)
+                    }
+                };
+                match result {
+                    Ok(()) => break,
+                    Err(err) => {
+                        error!("[{}] 发送图片失败: {:?}", self.alias, err);
+                        self.supervisor.report_error(&err);
+                        self.pacer.reset();
+                    }
+                }
+            }
+        }
+        info!("[{}] 播放列表已播完", self.alias);
+        Ok(())
+    }
+
+    /// 发送一帧并让它显示 `duration`。
+    fn show(&mut self, pixels: &[u8], duration: Duration) -> Result<()> {
+        let screen = self.supervisor.screen();
+        let sent_before = screen.bytes_sent();
+        screen.draw_pixels(pixels)?;
+        self.frames.record(screen.bytes_sent() - sent_before);
+        self.report_stats();
+        if self.pacer.wait(duration) {
+            self.frames.record_late();
+        }
+        Ok(())
+    }
+
+    /// 按文件中的帧延时播放动画 `rounds` 遍，`None` 表示无限循环。
+    fn play_animation(&mut self, animation: &Animation, rounds: Option<u32>) -> Result<()> {
+        let mut round = 0;
+        while rounds.is_none_or(|n| round < n) {
+            for frame in &animation.frames {
+                self.show(&frame.pixels, frame.delay)?;
+            }
+            round += 1;
+        }
+        Ok(())
+    }
+
+    fn report_stats(&mut self) {
+        if self.frames.elapsed() >= STATS_INTERVAL {
+            info!("[{}] 实际播放: {}", self.alias, self.frames.report());
+            info!(
+                "[{}] 传输统计: {}，队列丢弃 {} 项",
+                self.alias,
+                self.supervisor.screen().stats(),
+                (self.dropped)()
+            );
+        }
+    }
+}
+
+fn seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    nanos | 1
+}
+
+/// xorshift64 + Fisher–Yates，打乱播放顺序用，不需要密码学强度。
+fn shuffle<T>(items: &mut [T], state: &mut u64) {
+    for i in (1..items.len()).rev() {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        let j = (*state % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(glob: &str) -> ItemRule {
+        ItemRule {
+            pattern: Pattern::new(glob).unwrap(),
+            hold: None,
+            fit: None,
+            background: None,
+            gravity: None,
+        }
+    }
+
+    #[test]
+    fn item_rules_override_fit_per_field() {
+        let options = PlayOptions {
+            fit: Fit {
+                mode: FitMode::Contain,
+                background: Background::Blur,
+                gravity: Gravity::Center,
+            },
+            hold: Some(Duration::from_secs(5)),
+            items: vec![
+                ItemRule {
+                    fit: Some(FitMode::Cover),
+                    hold: Some(Duration::from_secs(2)),
+                    ..rule("*.gif")
+                },
+                ItemRule {
+                    fit: Some(FitMode::Center),
+                    gravity: Some(Gravity::Top),
+                    ..rule("banners/*")
+                },
+            ],
+            ..PlayOptions::default()
+        };
+
+        // 未匹配的图片使用全局设置
+        assert_eq!(options.render_for("photos/a.png").fit, options.fit);
+        assert_eq!(options.hold_for("photos/a.png"), Duration::from_secs(5));
+
+        // 先匹配到的规则优先，未指定的字段逐项向后查找，再使用全局设置
+        let fit = options.render_for("banners/LOGO.GIF").fit;
+        assert_eq!(fit.mode, FitMode::Cover);
+        assert_eq!(fit.gravity, Gravity::Top);
+        assert_eq!(fit.background, Background::Blur);
+        assert_eq!(options.hold_for("banners/logo.gif"), Duration::from_secs(2));
+
+        // 文件名也参与匹配
+        assert_eq!(
+            options.render_for("/srv/x/anim.gif").fit.mode,
+            FitMode::Cover
+        );
+        assert_eq!(
+            options.render_for("banners/wide.png").fit.mode,
+            FitMode::Center
+        );
+    }
+
+    #[test]
+    fn hold_defaults_to_one_frame() {
+        let options = PlayOptions {
+            fps: 20,
+            ..PlayOptions::default()
+        };
+        assert_eq!(options.hold_for("a.png"), Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,406 @@
+//! 主机与屏幕之间的分包协议。
+//!
+//! 每个包由 20 字节包头、负载和 4 字节 CRC32 组成，多字节字段均为大端：
+//!
+//! | 偏移 | 长度 | 字段 |
+//! |------|------|------|
+//! | 0    | 4    | 同步字 `USCR` |
+//! | 4    | 1    | 协议版本 |
+//! | 5    | 1    | 命令 |
+//! | 6    | 1    | 标志位 |
+//! | 7    | 1    | 像素格式 |
+//! | 8    | 2    | 宽 |
+//! | 10   | 2    | 高 |
+//! | 12   | 4    | 序号 |
+//! | 16   | 4    | 负载长度 |
+//!
+//! CRC32 覆盖包头和负载。接收端遇到校验失败或包头不合理时只丢弃一个字节，
+//! 然后重新搜索同步字，因此丢字节后最多损坏一帧。
+
+use anyhow::{bail, Result};
+
+use crate::dirty::Rect;
+
+/// 同步字。
+pub const MAGIC: [u8; 4] = *b"USCR";
+/// 当前协议版本。
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 20;
+pub const CRC_LEN: usize = 4;
+/// 负载长度上限，超过即视为包头损坏。
+pub const MAX_PAYLOAD_LEN: usize = 4 * 1024 * 1024;
+const MAX_SEQ_GAP: u32 = 1 << 16;
+
+/// 大端 RGB565，其他格式见 [`crate::format::PixelFormat`]。
+pub const FORMAT_RGB565: u8 = 0;
+
+/// 局部更新负载开头的矩形头长度：x、y、宽、高各 2 字节。
+pub const RECT_HEADER_LEN: usize = 8;
+
+/// 标志位：负载经过 LZ4 压缩，见 [`crate::compress`]。
+pub const FLAG_LZ4: u8 = 0x01;
+/// 标志位：本帧的最后一个包，设备收到后即可刷新显示。整帧包总是带有此标志。
+pub const FLAG_FRAME_END: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Command {
+    /// 整帧画面。
+    Frame = 0x01,
+    /// 局部更新：负载为矩形头加矩形内的像素，包头的宽高仍是整屏尺寸。
+    PartialFrame = 0x02,
+    /// 索引色的调色板：每种颜色 3 字节 RGB，最多 256 种。在使用索引色的帧之前发送。
+    Palette = 0x03,
+    /// 主机发起握手，负载为空。
+    Hello = 0x10,
+    /// 设备应答握手，负载见 [`crate::handshake`]。
+    HelloReply = 0x11,
+}
+
+impl TryFrom<u8> for Command {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Command> {
+        match value {
+            0x01 => Ok(Command::Frame),
+            0x02 => Ok(Command::PartialFrame),
+            0x03 => Ok(Command::Palette),
+            0x10 => Ok(Command::Hello),
+            0x11 => Ok(Command::HelloReply),
+            _ => bail!("未知命令: {:#04x}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub command: Command,
+    pub flags: u8,
+    pub format: u8,
+    pub width: u16,
+    pub height: u16,
+    pub seq: u32,
+    pub payload_len: u32,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut out = [0u8; HEADER_LEN];
+        out[0..4].copy_from_slice(&MAGIC);
+        out[4] = self.version;
+        out[5] = self.command as u8;
+        out[6] = self.flags;
+        out[7] = self.format;
+        out[8..10].copy_from_slice(&self.width.to_be_bytes());
+        out[10..12].copy_from_slice(&self.height.to_be_bytes());
+        out[12..16].copy_from_slice(&self.seq.to_be_bytes());
+        out[16..20].copy_from_slice(&self.payload_len.to_be_bytes());
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Header> {
+        if data.len() < HEADER_LEN {
+            bail!("包头长度不足: {}", data.len());
+        }
+        if data[0..4] != MAGIC {
+            bail!("同步字不匹配");
+        }
+        if data[4] != VERSION {
+            bail!("不支持的协议版本: {}", data[4]);
+        }
+        let header = Header {
+            version: data[4],
+            command: Command::try_from(data[5])?,
+            flags: data[6],
+            format: data[7],
+            width: u16::from_be_bytes([data[8], data[9]]),
+            height: u16::from_be_bytes([data[10], data[11]]),
+            seq: u32::from_be_bytes([data[12], data[13], data[14], data[15]]),
+            payload_len: u32::from_be_bytes([data[16], data[17], data[18], data[19]]),
+        };
+        if header.payload_len as usize > MAX_PAYLOAD_LEN {
+            bail!("负载长度过大: {}", header.payload_len);
+        }
+        Ok(header)
+    }
+}
+
+/// 编码局部更新的矩形头。
+pub fn encode_rect(rect: &Rect) -> [u8; RECT_HEADER_LEN] {
+    let mut out = [0u8; RECT_HEADER_LEN];
+    out[0..2].copy_from_slice(&(rect.x as u16).to_be_bytes());
+    out[2..4].copy_from_slice(&(rect.y as u16).to_be_bytes());
+    out[4..6].copy_from_slice(&(rect.width as u16).to_be_bytes());
+    out[6..8].copy_from_slice(&(rect.height as u16).to_be_bytes());
+    out
+}
+
+/// 解析局部更新负载，返回矩形和其后的像素数据。
+pub fn decode_rect(payload: &[u8]) -> Result<(Rect, &[u8])> {
+    if payload.len() < RECT_HEADER_LEN {
+        bail!("局部更新负载长度不足: {}", payload.len());
+    }
+    let field = |i: usize| u16::from_be_bytes([payload[i], payload[i + 1]]) as u32;
+    let rect = Rect::new(field(0), field(2), field(4), field(6));
+    Ok((rect, &payload[RECT_HEADER_LEN..]))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub header: Header,
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    pub fn new(command: Command, width: u16, height: u16, seq: u32, payload: Vec<u8>) -> Packet {
+        Packet {
+            header: Header {
+                version: VERSION,
+                command,
+                flags: 0,
+                format: FORMAT_RGB565,
+                width,
+                height,
+                seq,
+                payload_len: payload.len() as u32,
+            },
+            payload,
+        }
+    }
+
+    pub fn with_flags(mut self, flags: u8) -> Packet {
+        self.header.flags = flags;
+        self
+    }
+
+    pub fn with_format(mut self, format: u8) -> Packet {
+        self.header.format = format;
+        self
+    }
+
+    /// 还原后的负载，压缩的负载会先解压。
+    pub fn decoded_payload(&self) -> Result<Vec<u8>> {
+        if self.header.flags & FLAG_LZ4 != 0 {
+            crate::compress::decompress_lz4(&self.payload)
+        } else {
+            Ok(self.payload.clone())
+        }
+    }
+
+    /// 序列化为线上字节：包头 + 负载 + CRC32。
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.payload.len() + CRC_LEN);
+        out.extend_from_slice(&self.header.encode());
+        out.extend_from_slice(&self.payload);
+        let crc = crc32fast::hash(&out);
+        out.extend_from_slice(&crc.to_be_bytes());
+        out
+    }
+}
+
+/// 解码统计。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecoderStats {
+    /// 成功解出的包数。
+    pub packets: u64,
+    /// CRC 校验失败次数。
+    pub crc_errors: u64,
+    /// 为重新同步而丢弃的字节数。
+    pub skipped_bytes: u64,
+    /// 根据序号推算出的丢包数。
+    pub lost_packets: u64,
+}
+
+/// 流式解包器，可以从任意位置开始接收并自动重新同步。
+#[derive(Debug, Default)]
+pub struct PacketDecoder {
+    buf: Vec<u8>,
+    last_seq: Option<u32>,
+    stats: DecoderStats,
+}
+
+impl PacketDecoder {
+    pub fn new() -> PacketDecoder {
+        PacketDecoder::default()
+    }
+
+    pub fn stats(&self) -> DecoderStats {
+        self.stats
+    }
+
+    /// 追加收到的字节。
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// 取出下一个完整且校验通过的包，数据不足时返回 `None`。
+    pub fn next_packet(&mut self) -> Option<Packet> {
+        loop {
+            self.sync();
+            if self.buf.len() < HEADER_LEN {
+                return None;
+            }
+            let header = match Header::decode(&self.buf) {
+                Ok(header) => header,
+                Err(_) => {
+                    self.skip(1);
+                    continue;
+                }
+            };
+            let total = HEADER_LEN + header.payload_len as usize + CRC_LEN;
+            if self.buf.len() < total {
+                return None;
+            }
+            let body_len = total - CRC_LEN;
+            let expected = u32::from_be_bytes([
+                self.buf[body_len],
+                self.buf[body_len + 1],
+                self.buf[body_len + 2],
+                self.buf[body_len + 3],
+            ]);
+            if crc32fast::hash(&self.buf[..body_len]) != expected {
+                self.stats.crc_errors += 1;
+                self.skip(1);
+                continue;
+            }
+
+            let payload = self.buf[HEADER_LEN..body_len].to_vec();
+            self.buf.drain(..total);
+            if let Some(last) = self.last_seq {
+                // 序号大幅回退视为发送端重启，不计入丢包
+                let gap = header.seq.wrapping_sub(last);
+                if gap > 1 && gap < MAX_SEQ_GAP {
+                    self.stats.lost_packets += (gap - 1) as u64;
+                }
+            }
+            self.last_seq = Some(header.seq);
+            self.stats.packets += 1;
+            return Some(Packet { header, payload });
+        }
+    }
+
+    /// 丢弃同步字之前的字节；末尾可能是半个同步字，予以保留。
+    fn sync(&mut self) {
+        match self.buf.windows(MAGIC.len()).position(|w| w == MAGIC) {
+            Some(0) => {}
+            Some(pos) => self.skip(pos),
+            None => {
+                let keep = (MAGIC.len() - 1).min(self.buf.len());
+                self.skip(self.buf.len() - keep);
+            }
+        }
+    }
+
+    fn skip(&mut self, n: usize) {
+        self.buf.drain(..n);
+        self.stats.skipped_bytes += n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(seq: u32, payload: &[u8]) -> Packet {
+        Packet::new(Command::Frame, 320, 240, seq, payload.to_vec()).with_flags(FLAG_FRAME_END)
+    }
+
+    fn drain(decoder: &mut PacketDecoder) -> Vec<Packet> {
+        std::iter::from_fn(|| decoder.next_packet()).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let original = packet(7, b"hello").with_format(3);
+        let bytes = original.encode();
+        assert_eq!(bytes.len(), HEADER_LEN + 5 + CRC_LEN);
+        assert_eq!(&bytes[..4], &MAGIC);
+
+        let mut decoder = PacketDecoder::new();
+        decoder.push(&bytes);
+        assert_eq!(decoder.next_packet(), Some(original));
+        assert_eq!(decoder.next_packet(), None);
+        assert_eq!(decoder.stats().packets, 1);
+    }
+
+    #[test]
+    fn rejects_crc_mismatch() {
+        let mut bytes = packet(0, b"payload").encode();
+        bytes[HEADER_LEN + 2] ^= 0xFF;
+        let mut decoder = PacketDecoder::new();
+        decoder.push(&bytes);
+        assert_eq!(decoder.next_packet(), None);
+        assert_eq!(decoder.stats().crc_errors, 1);
+        assert_eq!(decoder.stats().packets, 0);
+    }
+
+    #[test]
+    fn resyncs_after_garbage_and_dropped_bytes() {
+        let mut stream = b"noise USC".to_vec();
+        stream.extend(packet(0, b"first").encode());
+        // 第二个包丢了一个字节，只影响它自己
+        let mut broken = packet(1, b"second").encode();
+        broken.remove(HEADER_LEN + 1);
+        stream.extend(broken);
+        stream.extend(packet(2, b"third").encode());
+
+        let mut decoder = PacketDecoder::new();
+        decoder.push(&stream);
+        let payloads: Vec<Vec<u8>> = drain(&mut decoder).into_iter().map(|p| p.payload).collect();
+        assert_eq!(payloads, [b"first".to_vec(), b"third".to_vec()]);
+        assert!(decoder.stats().skipped_bytes > 0);
+    }
+
+    #[test]
+    fn reassembles_split_packets() {
+        let bytes = [packet(0, &[1; 100]).encode(), packet(1, &[2; 3]).encode()].concat();
+        let mut decoder = PacketDecoder::new();
+        let mut packets = Vec::new();
+        for chunk in bytes.chunks(7) {
+            decoder.push(chunk);
+            packets.extend(drain(&mut decoder));
+        }
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].payload, vec![1; 100]);
+        assert_eq!(packets[1].payload, vec![2; 3]);
+        assert_eq!(decoder.stats().skipped_bytes, 0);
+    }
+
+    #[test]
+    fn counts_lost_packets() {
+        let mut decoder = PacketDecoder::new();
+        for seq in [0, 1, 4, 5] {
+            decoder.push(&packet(seq, b"x").encode());
+        }
+        assert_eq!(drain(&mut decoder).len(), 4);
+        assert_eq!(decoder.stats().lost_packets, 2);
+
+        // 序号回到 0 视为发送端重启
+        decoder.push(&packet(0, b"x").encode());
+        assert!(decoder.next_packet().is_some());
+        assert_eq!(decoder.stats().lost_packets, 2);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut header = packet(0, b"").header.encode();
+        header[4] = VERSION + 1;
+        assert!(Header::decode(&header).is_err());
+        let mut header = packet(0, b"").header.encode();
+        header[16..20].copy_from_slice(&(MAX_PAYLOAD_LEN as u32 + 1).to_be_bytes());
+        assert!(Header::decode(&header).is_err());
+        assert!(Header::decode(&header[..10]).is_err());
+    }
+
+    #[test]
+    fn rect_round_trip() {
+        let rect = Rect::new(16, 8, 32, 4);
+        let mut payload = encode_rect(&rect).to_vec();
+        payload.extend([9, 9]);
+        let (decoded, pixels) = decode_rect(&payload).unwrap();
+        assert_eq!(decoded, rect);
+        assert_eq!(pixels, &[9, 9]);
+        assert!(decode_rect(&payload[..4]).is_err());
+    }
+}
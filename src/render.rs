@@ -0,0 +1,123 @@
+//! 把解码后的图片变成屏幕画面的参数。
+//!
+//! 缓存按 [`Target`] 和 [`RenderOptions`] 区分同一张图片的不同渲染结果。
+
+use std::fmt;
+use std::sync::Arc;
+
+use image::imageops::FilterType;
+use image::RgbaImage;
+
+use crate::alpha::{self, Matte};
+use crate::calibration::Calibration;
+use crate::dither::Dither;
+use crate::fit::Fit;
+use crate::format::Encoder;
+use crate::transform::Orientation;
+
+/// 渲染参数。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// 缩放算法。
+    pub filter: FilterType,
+    pub fit: Fit,
+    /// 降低色深时的抖动方式。
+    pub dither: Dither,
+    /// 透明像素下方的底色。
+    pub matte: Matte,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            filter: FilterType::Lanczos3,
+            fit: Fit::default(),
+            dither: Dither::default(),
+            matte: Matte::default(),
+        }
+    }
+}
+
+impl RenderOptions {
+    /// 把图片按目标的逻辑尺寸渲染并变换到显存方向，像素值已按目标格式抖动。
+    pub fn render(&self, image: &RgbaImage, target: &Target) -> RgbaImage {
+        let (width, height) = target.logical_size();
+        let canvas = if alpha::is_opaque(image) {
+            self.fit.apply(image, width, height, self.filter)
+        } else {
+            // 预乘后再缩放，透明像素的颜色不会渗到边缘
+            let premultiplied = alpha::premultiply(image);
+            let mut canvas = self.fit.apply(&premultiplied, width, height, self.filter);
+            self.matte.flatten(&mut canvas);
+            canvas
+        };
+        target.prepare(canvas, self.dither)
+    }
+}
+
+/// 用于缓存键和日志的简短描述。
+impl fmt::Display for RenderOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}-{}-{}-{}",
+            self.filter, self.fit, self.dither, self.matte
+        )
+    }
+}
+
+/// 画面的去处：显存尺寸、像素格式、屏幕的安装方向和颜色校准。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub width: u32,
+    pub height: u32,
+    pub encoder: Encoder,
+    pub orientation: Orientation,
+    pub calibration: Arc<Calibration>,
+}
+
+impl Target {
+    pub fn new(width: u32, height: u32, encoder: Encoder) -> Target {
+        Target {
+            width,
+            height,
+            encoder,
+            orientation: Orientation::default(),
+            calibration: Arc::default(),
+        }
+    }
+
+    pub fn with_orientation(mut self, orientation: Orientation) -> Target {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn with_calibration(mut self, calibration: Arc<Calibration>) -> Target {
+        self.calibration = calibration;
+        self
+    }
+
+    /// 显存尺寸 `(宽, 高)`。
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// 内容排版的尺寸，见 [`Orientation::logical_size`]。
+    pub fn logical_size(&self) -> (u32, u32) {
+        self.orientation.logical_size(self.width, self.height)
+    }
+
+    /// 把按逻辑尺寸排版的画面变换到显存方向、校准颜色并按目标格式抖动。
+    pub fn prepare(&self, image: RgbaImage, dither: Dither) -> RgbaImage {
+        let mut image = self.orientation.apply(image);
+        self.calibration.apply(&mut image);
+        // 在显存方向上抖动，图案与物理像素对齐
+        self.encoder.dither(&mut image, dither);
+        image
+    }
+
+    /// 按目标格式编码。
+    pub fn encode(&self, image: &RgbaImage) -> Vec<u8> {
+        self.encoder.encode(image)
+    }
+}
@@ -0,0 +1,482 @@
+use std::sync::Arc;
+
+use anyhow::{ensure, Result};
+use image::RgbaImage;
+use log::{info, warn};
+
+use crate::calibration::Calibration;
+use crate::compress::{compress_lz4, Compression, CompressionStats};
+use crate::dirty::{self, DirtyConfig, Rect};
+use crate::dither::Dither;
+use crate::format::{Encoder, PixelFormat};
+use crate::handshake::{self, DeviceInfo, HANDSHAKE_TIMEOUT};
+use crate::palette::Palette;
+use crate::protocol::{encode_rect, Command, Packet, FLAG_FRAME_END, FLAG_LZ4};
+use crate::render::Target;
+use crate::transform::Orientation;
+use crate::transport::Transport;
+
+/// 建立连接时的可配置参数。
+#[derive(Debug, Clone)]
+pub struct ScreenOptions {
+    /// 设备未应答握手时假定的分辨率。
+    pub fallback_size: (u32, u32),
+    pub compression: Compression,
+    /// 局部更新的代价模型，`None` 表示总是发送整帧。
+    pub dirty: Option<DirtyConfig>,
+    /// 像素格式，`None` 表示按设备能力自动选择。
+    pub format: Option<PixelFormat>,
+    /// `indexed8` 格式使用的调色板，连接后发送给设备。
+    pub palette: Arc<Palette>,
+    /// 屏幕的安装方向，画面在主机端变换后再发送。
+    pub orientation: Orientation,
+    /// 面板的颜色校准。
+    pub calibration: Arc<Calibration>,
+}
+
+impl Default for ScreenOptions {
+    fn default() -> ScreenOptions {
+        let legacy = DeviceInfo::legacy();
+        ScreenOptions {
+            fallback_size: (legacy.width, legacy.height),
+            compression: Compression::default(),
+            dirty: Some(DirtyConfig::default()),
+            format: None,
+            palette: Arc::default(),
+            orientation: Orientation::default(),
+            calibration: Arc::default(),
+        }
+    }
+}
+
+/// 一块已连接的 USB 屏幕。
+pub struct Screen {
+    transport: Box<dyn Transport>,
+    info: DeviceInfo,
+    width: u32,
+    height: u32,
+    seq: u32,
+    compression: Compression,
+    stats: CompressionStats,
+    dirty: Option<DirtyConfig>,
+    encoder: Encoder,
+    orientation: Orientation,
+    calibration: Arc<Calibration>,
+    /// 最近一次发出的整帧数据，用于计算脏矩形。
+    last_frame: Option<Vec<u8>>,
+    frames_since_keyframe: u32,
+    /// 写入输出通道的总字节数，含包头和校验。
+    bytes_sent: u64,
+}
+
+impl Screen {
+    /// 查找并打开第一个 RP2040 屏幕。
+    #[cfg(feature = "usb-serial")]
+    pub fn open() -> Result<Screen> {
+        Screen::open_matching(
+            &crate::device::DeviceMatcher::rp2040(),
+            crate::device::BAUD_RATE,
+        )
+    }
+
+    /// 打开第一个符合匹配条件的串口屏幕。
+    #[cfg(feature = "usb-serial")]
+    pub fn open_matching(matcher: &crate::device::DeviceMatcher, baud_rate: u32) -> Result<Screen> {
+        Screen::open_with(matcher, baud_rate, &ScreenOptions::default())
+    }
+
+    /// 同 [`Screen::open_matching`]，按给定参数配置屏幕。
+    #[cfg(feature = "usb-serial")]
+    pub fn open_with(
+        matcher: &crate::device::DeviceMatcher,
+        baud_rate: u32,
+        options: &ScreenOptions,
+    ) -> Result<Screen> {
+        let port = matcher.open(baud_rate)?;
+        let transport = crate::transport::SerialTransport::new(port);
+        Screen::connect_with(Box::new(transport), options)
+    }
+
+    /// 按路径打开串口屏幕，例如模拟器创建的伪终端。
+    #[cfg(feature = "usb-serial")]
+    pub fn open_path(path: &str, baud_rate: u32) -> Result<Screen> {
+        let transport = crate::transport::SerialTransport::open(path, baud_rate)?;
+        Screen::connect(Box::new(transport))
+    }
+
+    /// 通过任意输出通道握手并按设备应答配置屏幕。
+    ///
+    /// 设备未应答握手时按 [`DeviceInfo::legacy`] 的默认参数工作。
+    pub fn connect(transport: Box<dyn Transport>) -> Result<Screen> {
+        Screen::connect_with(transport, &ScreenOptions::default())
+    }
+
+    /// 同 [`Screen::connect`]，按给定参数配置屏幕。
+    pub fn connect_with(
+        mut transport: Box<dyn Transport>,
+        options: &ScreenOptions,
+    ) -> Result<Screen> {
+        let info = match handshake::handshake(transport.as_mut(), HANDSHAKE_TIMEOUT)? {
+            Some(info) => {
+                info!("设备 {} 握手成功: {}", transport.name(), info);
+                info
+            }
+            None => {
+                warn!("设备 {} 未应答握手，使用默认参数", transport.name());
+                let (width, height) = options.fallback_size;
+                DeviceInfo {
+                    width,
+                    height,
+                    ..DeviceInfo::legacy()
+                }
+            }
+        };
+        let format = PixelFormat::select(&info, options.format)?;
+        let mut screen = Screen::with_info(transport, info)?;
+        screen.compression = options.compression;
+        screen.dirty = options.dirty;
+        screen.encoder = Encoder::new(format, options.palette.clone());
+        screen.orientation = options.orientation;
+        screen.calibration = options.calibration.clone();
+        // 握手包占用了序号 0
+        screen.seq = 1;
+        info!("使用像素格式 {}", format);
+        if format == PixelFormat::Indexed8 {
+            let palette = options.palette.to_bytes();
+            screen.send_packet(Command::Palette, palette, false)?;
+        }
+        Ok(screen)
+    }
+
+    /// 跳过握手，直接按给定的设备参数构造屏幕，使用设备支持的编号最小的像素格式。
+    pub fn with_info(transport: Box<dyn Transport>, info: DeviceInfo) -> Result<Screen> {
+        let format = PixelFormat::select(&info, None)?;
+        Ok(Screen {
+            transport,
+            info,
+            width: info.width,
+            height: info.height,
+            seq: 0,
+            compression: Compression::default(),
+            stats: CompressionStats::default(),
+            dirty: Some(DirtyConfig::default()),
+            encoder: Encoder::new(format, Arc::default()),
+            orientation: Orientation::default(),
+            calibration: Arc::default(),
+            last_frame: None,
+            frames_since_keyframe: 0,
+            bytes_sent: 0,
+        })
+    }
+
+    /// 握手得到的设备参数。
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// 屏幕分辨率 `(宽, 高)`，即显存的尺寸。
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// 按安装方向排版内容时的尺寸，竖装时宽高与 [`Screen::size`] 互换。
+    pub fn logical_size(&self) -> (u32, u32) {
+        self.orientation.logical_size(self.width, self.height)
+    }
+
+    /// 设置安装方向，下一帧起生效。
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    /// 设置颜色校准，下一帧起生效。
+    pub fn set_calibration(&mut self, calibration: Arc<Calibration>) {
+        self.calibration = calibration;
+    }
+
+    /// 当前使用的像素格式及调色板。
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    /// 画面的去处：分辨率、像素格式、安装方向和颜色校准。
+    pub fn target(&self) -> Target {
+        Target::new(self.width, self.height, self.encoder.clone())
+            .with_orientation(self.orientation)
+            .with_calibration(self.calibration.clone())
+    }
+
+    /// 输出通道名称。
+    pub fn name(&self) -> String {
+        self.transport.name()
+    }
+
+    /// 设置压缩策略，下一帧起生效。设备不支持 LZ4 时始终不压缩。
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// 设置局部更新的代价模型，`None` 表示总是发送整帧。设备不支持局部更新时此设置无效。
+    pub fn set_dirty_config(&mut self, dirty: Option<DirtyConfig>) {
+        self.dirty = dirty;
+        self.last_frame = None;
+    }
+
+    /// 丢弃上一帧记录，下一帧强制整帧发送，例如设备重连之后。
+    pub fn invalidate(&mut self) {
+        self.last_frame = None;
+    }
+
+    /// 到目前为止的压缩统计。
+    pub fn stats(&self) -> CompressionStats {
+        self.stats
+    }
+
+    /// 到目前为止写入输出通道的字节数，含包头和校验。
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// 发送一帧画面，图片尺寸必须与 [`Screen::logical_size`] 一致，
+    /// 按安装方向变换并校准颜色后发送，不抖动。
+    pub fn draw(&mut self, image: &RgbaImage) -> Result<()> {
+        ensure!(
+            image.dimensions() == self.logical_size(),
+            "图片尺寸 {:?} 与屏幕尺寸 {:?} 不一致",
+            image.dimensions(),
+            self.logical_size()
+        );
+        let target = self.target();
+        let pixels = target.encode(&target.prepare(image.clone(), Dither::None));
+        self.draw_pixels(&pixels)
+    }
+
+    /// 发送一帧已按当前像素格式编码的数据，长度必须与屏幕尺寸一致。
+    pub fn draw_pixels(&mut self, pixels: &[u8]) -> Result<()> {
+        ensure!(
+            pixels.len() == self.encoder.frame_len(self.width, self.height),
+            "{} 数据长度 {} 与屏幕尺寸 {:?} 不一致",
+            self.encoder.format(),
+            pixels.len(),
+            self.size()
+        );
+        match self.dirty_rects(pixels) {
+            Some((rects, bytes_per_pixel)) => {
+                for (i, rect) in rects.iter().enumerate() {
+                    let mut payload = encode_rect(rect).to_vec();
+                    payload.extend(dirty::extract(pixels, self.width, bytes_per_pixel, rect));
+                    let last = i + 1 == rects.len();
+                    self.send_packet(Command::PartialFrame, payload, last)?;
+                }
+                self.frames_since_keyframe += 1;
+            }
+            None => {
+                self.send_packet(Command::Frame, pixels.to_vec(), true)?;
+                self.frames_since_keyframe = 0;
+            }
+        }
+        if self.dirty.is_some() {
+            self.last_frame = Some(pixels.to_vec());
+        }
+        Ok(())
+    }
+
+    /// 需要局部更新时返回脏矩形（可能为空）和每像素字节数，需要整帧发送时返回 `None`。
+    fn dirty_rects(&self, frame: &[u8]) -> Option<(Vec<Rect>, u32)> {
+        if !self.info.partial_update {
+            return None;
+        }
+        let bytes_per_pixel = self.encoder.format().bytes_per_pixel()?;
+        let config = self.dirty.as_ref()?;
+        let last = self.last_frame.as_ref()?;
+        if last.len() != frame.len()
+            || (config.keyframe_interval > 0
+                && self.frames_since_keyframe + 1 >= config.keyframe_interval)
+        {
+            return None;
+        }
+        let rects = dirty::diff(
+            last,
+            frame,
+            self.width,
+            self.height,
+            bytes_per_pixel,
+            config,
+        );
+        let dirty_cost: u64 = rects.iter().map(|r| config.cost(r, bytes_per_pixel)).sum();
+        let full_cost = config.cost(&Rect::new(0, 0, self.width, self.height), bytes_per_pixel);
+        if dirty_cost as f64 > full_cost as f64 * config.full_frame_ratio {
+            return None;
+        }
+        Some((rects, bytes_per_pixel))
+    }
+
+    fn send_packet(&mut self, command: Command, raw: Vec<u8>, frame_end: bool) -> Result<()> {
+        let raw_len = raw.len();
+        let (mut flags, payload) = self.compress(raw);
+        let compressed = flags & FLAG_LZ4 != 0;
+        self.stats.record(raw_len, payload.len(), compressed);
+        if frame_end {
+            flags |= FLAG_FRAME_END;
+        }
+        let packet = Packet::new(
+            command,
+            self.width as u16,
+            self.height as u16,
+            self.seq,
+            payload,
+        )
+        .with_flags(flags)
+        .with_format(self.encoder.format().id());
+        self.seq = self.seq.wrapping_add(1);
+        let encoded = packet.encode();
+        self.transport.send(&encoded)?;
+        self.bytes_sent += encoded.len() as u64;
+        Ok(())
+    }
+
+    /// 按压缩策略处理负载，返回包头标志位和实际负载。
+    fn compress(&self, raw: Vec<u8>) -> (u8, Vec<u8>) {
+        if !self.info.lz4 {
+            return (0, raw);
+        }
+        match self.compression {
+            Compression::None => (0, raw),
+            Compression::Lz4 => (FLAG_LZ4, compress_lz4(&raw)),
+            Compression::Auto => {
+                let compressed = compress_lz4(&raw);
+                if compressed.len() < raw.len() {
+                    (FLAG_LZ4, compressed)
+                } else {
+                    (0, raw)
+                }
+            }
+        }
+    }
+
+    /// 刷新缓冲并关闭输出通道。
+    pub fn close(mut self) -> Result<()> {
+        self.transport.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{decode_rect, PacketDecoder};
+    use crate::transport::MemoryTransport;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    fn partial_screen(transport: &MemoryTransport, dirty: DirtyConfig) -> Screen {
+        let info = DeviceInfo {
+            width: WIDTH,
+            height: HEIGHT,
+            partial_update: true,
+            ..DeviceInfo::legacy()
+        };
+        let mut screen = Screen::with_info(Box::new(transport.clone()), info).unwrap();
+        screen.set_compression(Compression::None);
+        screen.set_dirty_config(Some(dirty));
+        screen
+    }
+
+    fn sent(transport: &MemoryTransport) -> Vec<Packet> {
+        let mut decoder = PacketDecoder::new();
+        decoder.push(&transport.take());
+        std::iter::from_fn(|| decoder.next_packet()).collect()
+    }
+
+    fn frame(seed: u8) -> Vec<u8> {
+        vec![seed; (WIDTH * HEIGHT * 2) as usize]
+    }
+
+    #[test]
+    fn sends_partial_frames_with_frame_end_on_last() {
+        let transport = MemoryTransport::new();
+        let mut screen = partial_screen(&transport, DirtyConfig::default());
+        let first = frame(0);
+        screen.draw_pixels(&first).unwrap();
+        assert_eq!(sent(&transport)[0].header.command, Command::Frame);
+
+        let mut second = first.clone();
+        second[0] = 1;
+        *second.last_mut().unwrap() = 1;
+        screen.draw_pixels(&second).unwrap();
+        let packets = sent(&transport);
+        assert_eq!(packets.len(), 2);
+        let mut rebuilt = first.clone();
+        for (i, packet) in packets.iter().enumerate() {
+            assert_eq!(packet.header.command, Command::PartialFrame);
+            let last = i + 1 == packets.len();
+            assert_eq!(packet.header.flags & FLAG_FRAME_END != 0, last);
+            let (rect, pixels) = decode_rect(&packet.payload).unwrap();
+            dirty::blit(&mut rebuilt, WIDTH, 2, &rect, pixels);
+        }
+        assert_eq!(rebuilt, second);
+
+        // 没有变化时什么都不发
+        screen.draw_pixels(&second).unwrap();
+        assert!(sent(&transport).is_empty());
+    }
+
+    #[test]
+    fn falls_back_to_full_frame_when_cheaper() {
+        let transport = MemoryTransport::new();
+        let mut screen = partial_screen(&transport, DirtyConfig::default());
+        screen.draw_pixels(&frame(0)).unwrap();
+        screen.draw_pixels(&frame(1)).unwrap();
+        let packets = sent(&transport);
+        assert_eq!(packets.len(), 2);
+        assert!(packets.iter().all(|p| p.header.command == Command::Frame));
+    }
+
+    #[test]
+    fn sends_keyframe_at_interval() {
+        let transport = MemoryTransport::new();
+        let dirty = DirtyConfig {
+            keyframe_interval: 3,
+            ..DirtyConfig::default()
+        };
+        let mut screen = partial_screen(&transport, dirty);
+        let mut pixels = frame(0);
+        let mut commands = Vec::new();
+        for i in 0..7 {
+            pixels[i] = pixels[i].wrapping_add(1);
+            screen.draw_pixels(&pixels).unwrap();
+            commands.extend(sent(&transport).iter().map(|p| p.header.command));
+        }
+        use Command::{Frame, PartialFrame};
+        assert_eq!(
+            commands,
+            [
+                Frame,
+                PartialFrame,
+                PartialFrame,
+                Frame,
+                PartialFrame,
+                PartialFrame,
+                Frame
+            ]
+        );
+    }
+
+    #[test]
+    fn always_sends_full_frames_without_device_support() {
+        let transport = MemoryTransport::new();
+        let info = DeviceInfo {
+            width: WIDTH,
+            height: HEIGHT,
+            ..DeviceInfo::legacy()
+        };
+        let mut screen = Screen::with_info(Box::new(transport.clone()), info).unwrap();
+        let mut pixels = frame(0);
+        screen.draw_pixels(&pixels).unwrap();
+        pixels[0] = 9;
+        screen.draw_pixels(&pixels).unwrap();
+        assert!(sent(&transport)
+            .iter()
+            .all(|p| p.header.command == Command::Frame));
+    }
+}
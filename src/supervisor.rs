@@ -0,0 +1,279 @@
+//! 设备掉线重连。
+//!
+//! [`Supervisor`] 持有当前连接的屏幕；发送失败后由调用方报告错误，
+//! 下一次取屏幕时按退避间隔反复尝试重新打开，直到设备重新出现。
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{debug, info, warn};
+
+use crate::screen::Screen;
+
+/// 连接状态。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    /// 尚未连接。
+    Idle,
+    /// 正在尝试打开设备，附带已失败的次数。
+    Connecting(u32),
+    /// 已连接，附带设备名称。
+    Connected(String),
+    /// 连接丢失，附带错误描述。
+    Disconnected(String),
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Idle => write!(f, "未连接"),
+            State::Connecting(0) => write!(f, "连接中"),
+            State::Connecting(attempts) => write!(f, "连接中（已失败 {} 次）", attempts),
+            State::Connected(name) => write!(f, "已连接 {}", name),
+            State::Disconnected(err) => write!(f, "已断开: {}", err),
+        }
+    }
+}
+
+/// 指数退避。
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Backoff {
+        Backoff {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    /// 返回本次等待时间，并把下次等待时间翻倍（不超过上限）。
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff::new(Duration::from_millis(250), Duration::from_secs(5))
+    }
+}
+
+type Connector = Box<dyn FnMut() -> Result<Screen> + Send>;
+
+/// 负责连接、掉线检测和重连的屏幕持有者。
+pub struct Supervisor {
+    connect: Connector,
+    backoff: Backoff,
+    state: State,
+    screen: Option<Screen>,
+    /// `try_screen` 下次允许尝试连接的时间。
+    next_attempt: Option<Instant>,
+    /// 本轮连续失败的次数。
+    attempts: u32,
+}
+
+impl Supervisor {
+    /// `connect` 每次被调用时尝试打开一次设备。
+    pub fn new(connect: impl FnMut() -> Result<Screen> + Send + 'static) -> Supervisor {
+        Supervisor {
+            connect: Box::new(connect),
+            backoff: Backoff::default(),
+            state: State::Idle,
+            screen: None,
+            next_attempt: None,
+            attempts: 0,
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Supervisor {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// 返回已连接的屏幕；未连接时阻塞重试直到连接成功。
+    pub fn screen(&mut self) -> &mut Screen {
+        while self.screen.is_none() {
+            if let Err(delay) = self.attempt() {
+                thread::sleep(delay);
+            }
+        }
+        self.screen.as_mut().unwrap()
+    }
+
+    /// 非阻塞版本的 [`Supervisor::screen`]：未连接时最多尝试一次，
+    /// 失败后在退避时间内直接返回 `None`。适合不能被单块屏幕拖住的场景。
+    pub fn try_screen(&mut self) -> Option<&mut Screen> {
+        if self.screen.is_none() {
+            if self.next_attempt.is_some_and(|t| Instant::now() < t) {
+                return None;
+            }
+            if let Err(delay) = self.attempt() {
+                self.next_attempt = Some(Instant::now() + delay);
+                return None;
+            }
+        }
+        self.screen.as_mut()
+    }
+
+    /// 报告发送失败：丢弃当前连接，下次 [`Supervisor::screen`] 时重连。
+    pub fn report_error(&mut self, err: &anyhow::Error) {
+        if self.screen.take().is_some() {
+            self.set_state(State::Disconnected(err.to_string()));
+        }
+    }
+
+    /// 尝试连接一次，失败时返回下次重试前应等待的时间。
+    fn attempt(&mut self) -> Result<(), Duration> {
+        if self.attempts == 0 {
+            self.set_state(State::Connecting(0));
+        }
+        match (self.connect)() {
+            Ok(screen) => {
+                self.backoff.reset();
+                self.attempts = 0;
+                self.next_attempt = None;
+                self.set_state(State::Connected(screen.name()));
+                self.screen = Some(screen);
+                Ok(())
+            }
+            Err(err) => {
+                self.attempts += 1;
+                let delay = self.backoff.next_delay();
+                // 只在第一次失败时告警，避免设备长时间拔出时刷屏
+                if self.attempts == 1 {
+                    warn!("打开设备失败: {}，{:?} 后重试", err, delay);
+                } else {
+                    debug!("打开设备失败: {}，{:?} 后重试", err, delay);
+                }
+                self.state = State::Connecting(self.attempts);
+                Err(delay)
+            }
+        }
+    }
+
+    fn set_state(&mut self, state: State) {
+        info!("设备状态: {} -> {}", self.state, state);
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use anyhow::bail;
+
+    use crate::handshake::DeviceInfo;
+    use crate::transport::MemoryTransport;
+
+    const INITIAL: Duration = Duration::from_millis(20);
+    const MAX: Duration = Duration::from_millis(80);
+
+    /// 前 `failures` 次连接失败，之后返回内存屏幕；返回值记录调用次数。
+    fn flaky(failures: u32) -> (Supervisor, Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let supervisor = Supervisor::new(move || {
+            if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                bail!("设备不存在");
+            }
+            let info = DeviceInfo {
+                width: 4,
+                height: 2,
+                ..DeviceInfo::legacy()
+            };
+            Screen::with_info(Box::new(MemoryTransport::new()), info)
+        })
+        .with_backoff(Backoff::new(INITIAL, MAX));
+        (supervisor, calls)
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        let mut backoff = Backoff::new(INITIAL, MAX);
+        let delays: Vec<u64> = (0..5)
+            .map(|_| backoff.next_delay().as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [20, 40, 80, 80, 80]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), INITIAL);
+    }
+
+    #[test]
+    fn try_screen_waits_out_the_backoff_window() {
+        let (mut supervisor, calls) = flaky(2);
+        assert!(supervisor.try_screen().is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(supervisor.state(), &State::Connecting(1));
+
+        // 退避时间内不再尝试
+        assert!(supervisor.try_screen().is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        thread::sleep(INITIAL);
+        assert!(supervisor.try_screen().is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(supervisor.state(), &State::Connecting(2));
+
+        thread::sleep(INITIAL * 2);
+        assert!(supervisor.try_screen().is_some());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(supervisor.state(), &State::Connected("memory".to_string()));
+        // 连接成功后退避恢复初始值
+        assert_eq!(supervisor.backoff.current, INITIAL);
+        assert_eq!(supervisor.attempts, 0);
+    }
+
+    #[test]
+    fn send_error_drops_screen_and_reconnects() {
+        let (mut supervisor, calls) = flaky(0);
+        supervisor.screen();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        supervisor.report_error(&anyhow::anyhow!("写入超时"));
+        assert_eq!(
+            supervisor.state(),
+            &State::Disconnected("写入超时".to_string())
+        );
+        assert!(supervisor.screen.is_none());
+        // 没有连接时重复报告不改变状态
+        supervisor.report_error(&anyhow::anyhow!("又一次"));
+        assert_eq!(
+            supervisor.state(),
+            &State::Disconnected("写入超时".to_string())
+        );
+
+        assert!(supervisor.try_screen().is_some());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn screen_blocks_until_connected() {
+        let (mut supervisor, calls) = flaky(3);
+        let started = Instant::now();
+        supervisor.screen();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        // 依次等待 20、40、80ms
+        assert!(started.elapsed() >= INITIAL + INITIAL * 2 + MAX);
+    }
+}
@@ -0,0 +1,303 @@
+//! 画面的几何变换。
+//!
+//! [`Orientation`] 描述屏幕的安装方向。内容先按逻辑尺寸排版（竖装的
+//! 320x240 屏幕为 240x320），再翻转、旋转到设备的显存方向后编码。
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use image::{imageops, RgbaImage};
+
+/// 顺时针旋转角度。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u32) -> Result<Rotation> {
+        match degrees % 360 {
+            0 => Ok(Rotation::Deg0),
+            90 => Ok(Rotation::Deg90),
+            180 => Ok(Rotation::Deg180),
+            270 => Ok(Rotation::Deg270),
+            _ => bail!("旋转角度只能是 0/90/180/270: {}", degrees),
+        }
+    }
+
+    pub fn degrees(&self) -> u32 {
+        match self {
+            Rotation::Deg0 => 0,
+            Rotation::Deg90 => 90,
+            Rotation::Deg180 => 180,
+            Rotation::Deg270 => 270,
+        }
+    }
+
+    /// 旋转后宽高是否互换。
+    pub fn swaps_axes(&self) -> bool {
+        matches!(self, Rotation::Deg90 | Rotation::Deg270)
+    }
+
+    /// 旋转前尺寸为 `(width, height)` 时，旋转后的尺寸。
+    pub fn rotated_size(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    pub fn apply(&self, image: &RgbaImage) -> RgbaImage {
+        match self {
+            Rotation::Deg0 => image.clone(),
+            Rotation::Deg90 => imageops::rotate90(image),
+            Rotation::Deg180 => imageops::rotate180(image),
+            Rotation::Deg270 => imageops::rotate270(image),
+        }
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Rotation> {
+        Rotation::from_degrees(s.trim().parse()?)
+    }
+}
+
+/// 镜像翻转。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flip {
+    #[default]
+    None,
+    /// 左右翻转。
+    Horizontal,
+    /// 上下翻转。
+    Vertical,
+    /// 左右、上下都翻转，等同于旋转 180 度。
+    Both,
+}
+
+impl Flip {
+    pub fn apply(&self, image: RgbaImage) -> RgbaImage {
+        match self {
+            Flip::None => image,
+            Flip::Horizontal => imageops::flip_horizontal(&image),
+            Flip::Vertical => imageops::flip_vertical(&image),
+            Flip::Both => imageops::rotate180(&image),
+        }
+    }
+}
+
+impl fmt::Display for Flip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Flip::None => "none",
+            Flip::Horizontal => "horizontal",
+            Flip::Vertical => "vertical",
+            Flip::Both => "both",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Flip {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Flip> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Flip::None,
+            "horizontal" | "h" | "x" => Flip::Horizontal,
+            "vertical" | "v" | "y" => Flip::Vertical,
+            "both" | "hv" | "xy" => Flip::Both,
+            other => bail!(
+                "未知的翻转方式: {}（可选 none、horizontal、vertical、both）",
+                other
+            ),
+        })
+    }
+}
+
+/// 屏幕的安装方向：先翻转，再顺时针旋转。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub flip: Flip,
+}
+
+impl Orientation {
+    pub fn new(rotation: Rotation, flip: Flip) -> Orientation {
+        Orientation { rotation, flip }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.rotation == Rotation::Deg0 && self.flip == Flip::None
+    }
+
+    /// 显存尺寸为 `(width, height)` 时内容的排版尺寸，90 和 270 度时宽高互换。
+    pub fn logical_size(&self, width: u32, height: u32) -> (u32, u32) {
+        self.rotation.rotated_size(width, height)
+    }
+
+    /// 把按逻辑尺寸排版的画面变换到显存方向。
+    pub fn apply(&self, image: RgbaImage) -> RgbaImage {
+        let image = self.flip.apply(image);
+        match self.rotation {
+            Rotation::Deg0 => image,
+            rotation => rotation.apply(&image),
+        }
+    }
+}
+
+/// 用于缓存键和日志的简短描述。
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rotate{}-flip-{}", self.rotation.degrees(), self.flip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    use crate::handshake::DeviceInfo;
+    use crate::protocol::PacketDecoder;
+    use crate::screen::Screen;
+    use crate::transport::MemoryTransport;
+
+    const MARK: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const ROTATIONS: [Rotation; 4] = [
+        Rotation::Deg0,
+        Rotation::Deg90,
+        Rotation::Deg180,
+        Rotation::Deg270,
+    ];
+
+    /// 3x2 的黑色画面，左上角做标记。
+    fn marked() -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(3, 2, Rgba([0, 0, 0, 255]));
+        image.put_pixel(0, 0, MARK);
+        image
+    }
+
+    fn mark_position(image: &RgbaImage) -> (u32, u32) {
+        let found: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, p)| **p == MARK)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(found.len(), 1);
+        found[0]
+    }
+
+    #[test]
+    fn logical_size_swaps_for_quarter_turns() {
+        let sizes: Vec<(u32, u32)> = ROTATIONS
+            .iter()
+            .map(|r| Orientation::new(*r, Flip::None).logical_size(240, 320))
+            .collect();
+        assert_eq!(sizes, [(240, 320), (320, 240), (240, 320), (320, 240)]);
+        // 翻转不影响尺寸
+        let flipped = Orientation::new(Rotation::Deg90, Flip::Both);
+        assert_eq!(flipped.logical_size(240, 320), (320, 240));
+    }
+
+    #[test]
+    fn rotates_clockwise() {
+        let cases = [
+            (Rotation::Deg0, (3, 2), (0, 0)),
+            (Rotation::Deg90, (2, 3), (1, 0)),
+            (Rotation::Deg180, (3, 2), (2, 1)),
+            (Rotation::Deg270, (2, 3), (0, 2)),
+        ];
+        for (rotation, size, mark) in cases {
+            let out = rotation.apply(&marked());
+            assert_eq!(out.dimensions(), size, "{:?}", rotation);
+            assert_eq!(out.dimensions(), rotation.rotated_size(3, 2));
+            assert_eq!(mark_position(&out), mark, "{:?}", rotation);
+        }
+    }
+
+    #[test]
+    fn flips_before_rotating() {
+        let cases = [
+            (Flip::None, (0, 0)),
+            (Flip::Horizontal, (2, 0)),
+            (Flip::Vertical, (0, 1)),
+            (Flip::Both, (2, 1)),
+        ];
+        for (flip, mark) in cases {
+            let out = flip.apply(marked());
+            assert_eq!(out.dimensions(), (3, 2));
+            assert_eq!(mark_position(&out), mark, "{}", flip);
+        }
+        // 先左右翻转到 (2, 0)，再顺时针 90 度到 (1, 2)
+        let orientation = Orientation::new(Rotation::Deg90, Flip::Horizontal);
+        let out = orientation.apply(marked());
+        assert_eq!(out.dimensions(), (2, 3));
+        assert_eq!(mark_position(&out), (1, 2));
+        assert!(Orientation::default().is_identity());
+        assert!(!orientation.is_identity());
+    }
+
+    #[test]
+    fn parses_degrees_and_flips() {
+        for rotation in ROTATIONS {
+            let parsed: Rotation = rotation.degrees().to_string().parse().unwrap();
+            assert_eq!(parsed, rotation);
+        }
+        assert_eq!(" 450 ".parse::<Rotation>().unwrap(), Rotation::Deg90);
+        for bad in ["45", "-90", "ninety", ""] {
+            assert!(bad.parse::<Rotation>().is_err(), "{}", bad);
+        }
+        let err = Rotation::from_degrees(45).unwrap_err().to_string();
+        assert!(err.contains("0/90/180/270"), "{}", err);
+
+        for flip in [Flip::None, Flip::Horizontal, Flip::Vertical, Flip::Both] {
+            assert_eq!(flip.to_string().parse::<Flip>().unwrap(), flip);
+        }
+        assert_eq!("H".parse::<Flip>().unwrap(), Flip::Horizontal);
+        assert_eq!("xy".parse::<Flip>().unwrap(), Flip::Both);
+        assert!("diagonal".parse::<Flip>().is_err());
+        assert_eq!(
+            Orientation::new(Rotation::Deg270, Flip::Vertical).to_string(),
+            "rotate270-flip-vertical"
+        );
+    }
+
+    #[test]
+    fn rotated_screen_accepts_logical_frames() {
+        // 显存 4x2，顺时针旋转 90 度安装后按 2x4 排版
+        let transport = MemoryTransport::new();
+        let info = DeviceInfo {
+            width: 4,
+            height: 2,
+            ..DeviceInfo::legacy()
+        };
+        let mut screen = Screen::with_info(Box::new(transport.clone()), info).unwrap();
+        screen.set_orientation(Orientation::new(Rotation::Deg90, Flip::None));
+        assert_eq!(screen.logical_size(), (2, 4));
+        assert!(screen.draw(&RgbaImage::new(4, 2)).is_err());
+        assert!(transport.contents().is_empty());
+
+        let mut image = RgbaImage::from_pixel(2, 4, Rgba([0, 0, 0, 255]));
+        image.put_pixel(0, 0, MARK);
+        screen.draw(&image).unwrap();
+
+        let mut decoder = PacketDecoder::new();
+        decoder.push(&transport.take());
+        let packet = decoder.next_packet().unwrap();
+        assert_eq!((packet.header.width, packet.header.height), (4, 2));
+        // 逻辑画面的左上角转到显存第一行的最右边
+        let payload = packet.decoded_payload().unwrap();
+        assert_eq!(&payload[6..8], [0xF8, 0x00]);
+        assert_eq!(payload.iter().filter(|b| **b != 0).count(), 1);
+    }
+}
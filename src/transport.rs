@@ -0,0 +1,306 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+#[cfg(feature = "usb-serial")]
+use serialport::SerialPort;
+
+/// 画面字节流的输出通道。
+pub trait Transport: Send {
+    /// 写出全部字节。
+    fn send(&mut self, data: &[u8]) -> Result<()>;
+
+    /// 刷新尚未写出的缓冲。
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// 在 `timeout` 内读取设备发回的数据，返回读到的字节数；超时或通道不可读时返回 0。
+    fn recv(&mut self, _buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        Ok(0)
+    }
+
+    /// 用于日志的通道名称。
+    fn name(&self) -> String;
+}
+
+/// 把超时类错误视为“没有数据”。
+fn timed_out(err: io::Error) -> Result<usize> {
+    match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Ok(0),
+        _ => Err(err.into()),
+    }
+}
+
+/// 串口写超时；设备卡住或被拔出时写操作在此时间后报错。
+#[cfg(feature = "usb-serial")]
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 串口通道，即原来直连 RP2040 的方式。
+#[cfg(feature = "usb-serial")]
+pub struct SerialTransport {
+    port: Box<dyn SerialPort>,
+}
+
+#[cfg(feature = "usb-serial")]
+impl SerialTransport {
+    pub fn new(port: Box<dyn SerialPort>) -> SerialTransport {
+        SerialTransport { port }
+    }
+
+    /// 按路径打开串口。
+    pub fn open(path: &str, baud_rate: u32) -> Result<SerialTransport> {
+        let port = serialport::new(path, baud_rate)
+            .timeout(WRITE_TIMEOUT)
+            .open()?;
+        Ok(SerialTransport::new(port))
+    }
+}
+
+#[cfg(feature = "usb-serial")]
+impl Transport for SerialTransport {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.port.write_all(data)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.port.flush()?;
+        Ok(())
+    }
+
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        // serialport 的读写共用一个超时，读完恢复写超时
+        self.port.set_timeout(timeout)?;
+        let result = self.port.read(buf);
+        self.port.set_timeout(WRITE_TIMEOUT)?;
+        result.or_else(timed_out)
+    }
+
+    fn name(&self) -> String {
+        self.port.name().unwrap_or_else(|| "serial".to_string())
+    }
+}
+
+/// TCP 通道，用于把画面转发到远端。
+pub struct TcpTransport {
+    stream: TcpStream,
+    peer: String,
+}
+
+impl TcpTransport {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<TcpTransport> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let peer = stream.peer_addr()?.to_string();
+        Ok(TcpTransport { stream, peer })
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.stream.write_all(data)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.stream.read(buf).or_else(timed_out)
+    }
+
+    fn name(&self) -> String {
+        format!("tcp://{}", self.peer)
+    }
+}
+
+/// 文件或管道通道，用于录制画面。
+pub struct FileTransport {
+    writer: Box<dyn Write + Send>,
+    name: String,
+}
+
+impl FileTransport {
+    /// 创建（或截断）文件；对命名管道同样适用。
+    pub fn create(path: impl AsRef<Path>) -> Result<FileTransport> {
+        let path = path.as_ref();
+        Ok(FileTransport {
+            writer: Box::new(io::BufWriter::new(File::create(path)?)),
+            name: path.display().to_string(),
+        })
+    }
+
+    /// 写到标准输出，方便接管道。
+    pub fn stdout() -> FileTransport {
+        FileTransport {
+            writer: Box::new(io::stdout()),
+            name: "stdout".to_string(),
+        }
+    }
+}
+
+impl Transport for FileTransport {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// 内存通道，克隆出的句柄共享同一块缓冲，便于测试时检查发出的数据。
+#[derive(Clone, Default)]
+pub struct MemoryTransport {
+    sent: Arc<Mutex<Vec<u8>>>,
+    replies: Arc<Mutex<VecDeque<u8>>>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    /// 到目前为止发出的全部字节。
+    pub fn contents(&self) -> Vec<u8> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// 取出并清空已发出的字节。
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+
+    /// 预置设备应答，之后的 `recv` 会依次读到这些字节。
+    pub fn push_reply(&self, data: &[u8]) {
+        self.replies.lock().unwrap().extend(data);
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.sent.lock().unwrap().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn recv(&mut self, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        let mut replies = self.replies.lock().unwrap();
+        let n = buf.len().min(replies.len());
+        for (dst, src) in buf.iter_mut().zip(replies.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+
+    fn name(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+    use crate::handshake::DeviceInfo;
+    use crate::protocol::{Command, Packet, PacketDecoder, FLAG_FRAME_END, FORMAT_RGB565};
+    use crate::screen::Screen;
+
+    fn decode_all(data: &[u8]) -> Vec<Packet> {
+        let mut decoder = PacketDecoder::new();
+        decoder.push(data);
+        let packets: Vec<Packet> = std::iter::from_fn(|| decoder.next_packet()).collect();
+        assert_eq!(decoder.stats().skipped_bytes, 0);
+        packets
+    }
+
+    fn small_screen(transport: &MemoryTransport) -> Screen {
+        let info = DeviceInfo {
+            width: 4,
+            height: 2,
+            ..DeviceInfo::legacy()
+        };
+        Screen::with_info(Box::new(transport.clone()), info).unwrap()
+    }
+
+    #[test]
+    fn clones_share_buffers() {
+        let transport = MemoryTransport::new();
+        let mut handle = transport.clone();
+        handle.send(b"abc").unwrap();
+        handle.send(b"de").unwrap();
+        assert_eq!(transport.contents(), b"abcde");
+        assert_eq!(transport.take(), b"abcde");
+        assert!(transport.contents().is_empty());
+
+        transport.push_reply(&[1, 2, 3]);
+        let mut buf = [0u8; 2];
+        assert_eq!(handle.recv(&mut buf, Duration::ZERO).unwrap(), 2);
+        assert_eq!(buf, [1, 2]);
+        assert_eq!(handle.recv(&mut buf, Duration::ZERO).unwrap(), 1);
+        assert_eq!(handle.recv(&mut buf, Duration::ZERO).unwrap(), 0);
+    }
+
+    #[test]
+    fn screen_sends_decodable_frames() {
+        let transport = MemoryTransport::new();
+        let mut screen = small_screen(&transport);
+        let first: Vec<u8> = (0..16).collect();
+        let second = vec![0xAB; 16];
+        screen.draw_pixels(&first).unwrap();
+        screen.draw_pixels(&second).unwrap();
+        assert_eq!(screen.bytes_sent(), transport.contents().len() as u64);
+
+        let packets = decode_all(&transport.take());
+        assert_eq!(packets.len(), 2);
+        for (seq, (packet, pixels)) in packets.iter().zip([&first, &second]).enumerate() {
+            assert_eq!(packet.header.command, Command::Frame);
+            assert_eq!(packet.header.seq, seq as u32);
+            assert_eq!((packet.header.width, packet.header.height), (4, 2));
+            assert_eq!(packet.header.format, FORMAT_RGB565);
+            assert_ne!(packet.header.flags & FLAG_FRAME_END, 0);
+            assert_eq!(&packet.decoded_payload().unwrap(), pixels);
+        }
+    }
+
+    #[test]
+    fn screen_draw_encodes_rgb565() {
+        let transport = MemoryTransport::new();
+        let mut screen = small_screen(&transport);
+        let mut image = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 0, 255]));
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(3, 1, Rgba([0, 0, 255, 255]));
+        screen.draw(&image).unwrap();
+
+        let packets = decode_all(&transport.take());
+        let payload = packets[0].decoded_payload().unwrap();
+        assert_eq!(payload.len(), 16);
+        assert_eq!(&payload[0..2], &[0xF8, 0x00]);
+        assert_eq!(&payload[14..16], &[0x00, 0x1F]);
+        assert!(payload[2..14].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn screen_rejects_wrong_sizes() {
+        let transport = MemoryTransport::new();
+        let mut screen = small_screen(&transport);
+        assert!(screen.draw_pixels(&[0; 15]).is_err());
+        assert!(screen.draw(&RgbaImage::new(2, 4)).is_err());
+        assert!(transport.contents().is_empty());
+    }
+}
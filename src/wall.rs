@@ -0,0 +1,374 @@
+//! 拼接屏：把多块屏幕排成网格，当作一块大画布显示。
+//!
+//! 画布尺寸包含屏幕之间的边框宽度，边框后面的像素不会显示，
+//! 这样跨越边框的直线看起来仍然是连续的。
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use image::imageops::FilterType;
+use image::{imageops, RgbaImage};
+use log::{debug, error, info, warn};
+
+use crate::animation::Media;
+use crate::cache::FrameCache;
+use crate::dirty::Rect;
+use crate::dither::Dither;
+use crate::format::{Encoder, PixelFormat};
+use crate::pacing::Pacer;
+use crate::player::{rounds, PlayOptions};
+use crate::render::Target;
+use crate::supervisor::Supervisor;
+use crate::transform::Rotation;
+
+/// 网格中的一块屏幕。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WallTile {
+    pub alias: String,
+    pub col: u32,
+    pub row: u32,
+    /// 屏幕相对画布的安装角度，切片会先旋转再发送。
+    pub rotation: Rotation,
+}
+
+/// 拼接屏布局。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WallLayout {
+    pub cols: u32,
+    pub rows: u32,
+    /// 每块屏幕在画布上占据的尺寸（旋转前）。
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// 相邻屏幕之间的横向、纵向边框宽度（像素）。
+    pub bezel_x: u32,
+    pub bezel_y: u32,
+    pub tiles: Vec<WallTile>,
+}
+
+impl WallLayout {
+    pub fn new(cols: u32, rows: u32, tile_width: u32, tile_height: u32) -> WallLayout {
+        WallLayout {
+            cols,
+            rows,
+            tile_width,
+            tile_height,
+            bezel_x: 0,
+            bezel_y: 0,
+            tiles: Vec::new(),
+        }
+    }
+
+    /// 检查每块屏幕都落在网格内且位置不重复。
+    pub fn validate(&self) -> Result<()> {
+        if self.cols == 0 || self.rows == 0 {
+            bail!("拼接屏网格不能为空: {}x{}", self.cols, self.rows);
+        }
+        for (i, tile) in self.tiles.iter().enumerate() {
+            if tile.col >= self.cols || tile.row >= self.rows {
+                bail!(
+                    "屏幕 {} 的位置 ({}, {}) 超出 {}x{} 网格",
+                    tile.alias,
+                    tile.col,
+                    tile.row,
+                    self.cols,
+                    self.rows
+                );
+            }
+            if self.tiles[..i]
+                .iter()
+                .any(|t| (t.col, t.row) == (tile.col, tile.row))
+            {
+                bail!("网格位置 ({}, {}) 被重复分配", tile.col, tile.row);
+            }
+        }
+        Ok(())
+    }
+
+    /// 包含边框在内的画布尺寸。
+    pub fn canvas_size(&self) -> (u32, u32) {
+        (
+            self.cols * self.tile_width + (self.cols - 1) * self.bezel_x,
+            self.rows * self.tile_height + (self.rows - 1) * self.bezel_y,
+        )
+    }
+
+    /// 某块屏幕在画布上对应的区域。
+    pub fn tile_rect(&self, tile: &WallTile) -> Rect {
+        Rect::new(
+            tile.col * (self.tile_width + self.bezel_x),
+            tile.row * (self.tile_height + self.bezel_y),
+            self.tile_width,
+            self.tile_height,
+        )
+    }
+
+    /// 把画布切成每块屏幕的画面，顺序与 `tiles` 一致。
+    pub fn split(&self, canvas: &RgbaImage) -> Vec<RgbaImage> {
+        self.tiles
+            .iter()
+            .map(|tile| {
+                let rect = self.tile_rect(tile);
+                let view = imageops::crop_imm(canvas, rect.x, rect.y, rect.width, rect.height);
+                tile.rotation.apply(&view.to_image())
+            })
+            .collect()
+    }
+}
+
+/// 以拼接屏方式循环播放图片。`supervisors` 与 `layout.tiles` 一一对应。
+///
+/// 每一帧的所有切片并行发送，全部发送完毕后才进入下一帧；
+/// 掉线的屏幕被跳过并在后台重连，不会拖住其他屏幕。动画按文件中的帧延时和循环次数播放。
+pub fn play(
+    layout: &WallLayout,
+    supervisors: &mut [Supervisor],
+    images: &[String],
+    options: &PlayOptions,
+    cache: &FrameCache,
+) -> Result<()> {
+    layout.validate()?;
+    if supervisors.len() != layout.tiles.len() {
+        bail!(
+            "屏幕数量 {} 与布局中的 {} 块不一致",
+            supervisors.len(),
+            layout.tiles.len()
+        );
+    }
+    if images.is_empty() {
+        info!("[wall] 没有可播放的图片");
+        return Ok(());
+    }
+    let (width, height) = layout.canvas_size();
+    // 各块屏幕的像素格式可能不同，画布不抖动，以 rgb888 无损缓存，切片后按各自的格式处理
+    let target = Target::new(
+        width,
+        height,
+        Encoder::new(PixelFormat::Rgb888, Arc::default()),
+    );
+    info!(
+        "[wall] 画布尺寸 {}x{}，共 {} 块屏幕",
+        width,
+        height,
+        layout.tiles.len()
+    );
+
+    let mut wall = Wall {
+        layout,
+        supervisors,
+        warned: vec![false; layout.tiles.len()],
+        pacer: Pacer::new(),
+        filter: options.filter,
+    };
+    let mut index = 0;
+    loop {
+        let path = &images[index];
+        let mut render = options.render_for(path);
+        let dither = render.dither;
+        render.dither = Dither::None;
+        let media = cache
+            .load(path, &target, &render)
+            .with_context(|| format!("无法解码 {}", path))?;
+        let canvas = |pixels: &[u8]| {
+            target
+                .encoder
+                .decode(pixels, width, height)
+                .context("缓存的画布尺寸不符")
+        };
+        match &*media {
+            Media::Still { pixels, .. } => {
+                wall.show(&canvas(pixels)?, dither, options.hold_for(path));
+            }
+            Media::Animated(animation) => {
+                let frames = animation
+                    .frames
+                    .iter()
+                    .map(|frame| Ok((canvas(&frame.pixels)?, frame.delay)))
+                    .collect::<Result<Vec<_>>>()?;
+                let rounds = rounds(animation.loop_count, images.len(), options.repeat);
+                let mut round = 0;
+                while rounds.is_none_or(|n| round < n) {
+                    for (frame, delay) in &frames {
+                        wall.show(frame, dither, *delay);
+                    }
+                    round += 1;
+                }
+            }
+        }
+
+        index = (index + 1) % images.len();
+        if index == 0 {
+            debug!("[wall] 缓存统计: {}", cache.stats());
+        }
+        if index == 0 && !options.repeat {
+            info!("[wall] 播放列表已播完");
+            return Ok(());
+        }
+    }
+}
+
+/// 播放中的拼接屏。
+struct Wall<'a> {
+    layout: &'a WallLayout,
+    supervisors: &'a mut [Supervisor],
+    /// 每块屏幕只提示一次尺寸不一致。
+    warned: Vec<bool>,
+    pacer: Pacer,
+    filter: FilterType,
+}
+
+impl Wall<'_> {
+    /// 把画布切片并行发给各块屏幕，然后让它显示 `duration`。
+    fn show(&mut self, canvas: &RgbaImage, dither: Dither, duration: Duration) {
+        let parts = self.layout.split(canvas);
+        let filter = self.filter;
+        thread::scope(|scope| {
+            let targets = self.supervisors.iter_mut().zip(self.warned.iter_mut());
+            for (((supervisor, warned), tile), part) in targets.zip(&self.layout.tiles).zip(parts) {
+                scope.spawn(move || {
+                    let Some(screen) = supervisor.try_screen() else {
+                        return;
+                    };
+                    let part = if part.dimensions() == screen.logical_size() {
+                        part
+                    } else {
+                        if !*warned {
+                            warn!(
+                                "[{}] 切片尺寸 {:?} 与屏幕 {:?} 不一致，已缩放",
+                                tile.alias,
+                                part.dimensions(),
+                                screen.logical_size()
+                            );
+                            *warned = true;
+                        }
+                        let (w, h) = screen.logical_size();
+                        imageops::resize(&part, w, h, filter)
+                    };
+                    let target = screen.target();
+                    let frame = target.encode(&target.prepare(part, dither));
+                    if let Err(err) = screen.draw_pixels(&frame) {
+                        error!("[{}] 发送切片失败: {:?}", tile.alias, err);
+                        supervisor.report_error(&err);
+                    }
+                });
+            }
+        });
+        self.pacer.wait(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn tile(alias: &str, col: u32, row: u32, rotation: Rotation) -> WallTile {
+        WallTile {
+            alias: alias.to_string(),
+            col,
+            row,
+            rotation,
+        }
+    }
+
+    /// 2x2 块 4x3 的屏幕，横向边框 2、纵向边框 1，画布 10x7。
+    fn small_layout() -> WallLayout {
+        let mut layout = WallLayout::new(2, 2, 4, 3);
+        (layout.bezel_x, layout.bezel_y) = (2, 1);
+        layout
+    }
+
+    /// 每个像素记录自己的坐标。
+    fn canvas(layout: &WallLayout) -> RgbaImage {
+        let (width, height) = layout.canvas_size();
+        RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, 0, 255]))
+    }
+
+    #[test]
+    fn canvas_includes_bezels() {
+        let mut layout = WallLayout::new(2, 2, 320, 240);
+        assert_eq!(layout.canvas_size(), (640, 480));
+        (layout.bezel_x, layout.bezel_y) = (10, 8);
+        assert_eq!(layout.canvas_size(), (650, 488));
+        // 只有一列、一行时边框不占位置
+        let mut single = WallLayout::new(1, 1, 320, 240);
+        (single.bezel_x, single.bezel_y) = (10, 8);
+        assert_eq!(single.canvas_size(), (320, 240));
+    }
+
+    #[test]
+    fn tile_rects_skip_bezel_gaps() {
+        let mut layout = WallLayout::new(2, 2, 320, 240);
+        (layout.bezel_x, layout.bezel_y) = (10, 8);
+        let rect = |col, row| layout.tile_rect(&tile("t", col, row, Rotation::Deg0));
+        assert_eq!(rect(0, 0), Rect::new(0, 0, 320, 240));
+        assert_eq!(rect(1, 0), Rect::new(330, 0, 320, 240));
+        assert_eq!(rect(0, 1), Rect::new(0, 248, 320, 240));
+        assert_eq!(rect(1, 1), Rect::new(330, 248, 320, 240));
+        let (width, height) = layout.canvas_size();
+        assert_eq!((330 + 320, 248 + 240), (width, height));
+    }
+
+    #[test]
+    fn splits_in_tile_order() {
+        let mut layout = small_layout();
+        layout.tiles = vec![
+            tile("br", 1, 1, Rotation::Deg0),
+            tile("tl", 0, 0, Rotation::Deg0),
+            tile("tr", 1, 0, Rotation::Deg0),
+        ];
+        let canvas = canvas(&layout);
+        let parts = layout.split(&canvas);
+        assert_eq!(parts.len(), 3);
+        for (part, (x0, y0)) in parts.iter().zip([(6, 4), (0, 0), (6, 0)]) {
+            assert_eq!(part.dimensions(), (4, 3));
+            for (x, y, pixel) in part.enumerate_pixels() {
+                assert_eq!(pixel, canvas.get_pixel(x0 + x, y0 + y));
+            }
+        }
+    }
+
+    #[test]
+    fn rotates_slices_for_mounted_screens() {
+        let mut layout = small_layout();
+        layout.tiles = vec![
+            tile("cw", 0, 0, Rotation::Deg90),
+            tile("ccw", 1, 1, Rotation::Deg270),
+            tile("flip", 1, 0, Rotation::Deg180),
+        ];
+        let canvas = canvas(&layout);
+        let parts = layout.split(&canvas);
+        assert_eq!(parts[0].dimensions(), (3, 4));
+        assert_eq!(parts[1].dimensions(), (3, 4));
+        assert_eq!(parts[2].dimensions(), (4, 3));
+        // 顺时针 90 度：切片左下角转到左上角
+        assert_eq!(parts[0].get_pixel(0, 0), canvas.get_pixel(0, 2));
+        // 270 度：切片右上角转到左上角
+        assert_eq!(parts[1].get_pixel(0, 0), canvas.get_pixel(9, 4));
+        assert_eq!(parts[2].get_pixel(0, 0), canvas.get_pixel(9, 2));
+    }
+
+    #[test]
+    fn validate_rejects_bad_positions() {
+        let mut layout = small_layout();
+        layout.tiles = vec![
+            tile("a", 0, 0, Rotation::Deg0),
+            tile("b", 1, 1, Rotation::Deg0),
+        ];
+        layout.validate().unwrap();
+
+        layout.tiles.push(tile("c", 2, 0, Rotation::Deg0));
+        let err = layout.validate().unwrap_err().to_string();
+        assert!(err.contains("超出 2x2 网格"), "{}", err);
+
+        layout.tiles[2] = tile("c", 0, 2, Rotation::Deg0);
+        assert!(layout.validate().is_err());
+
+        layout.tiles[2] = tile("c", 1, 1, Rotation::Deg0);
+        let err = layout.validate().unwrap_err().to_string();
+        assert!(err.contains("(1, 1) 被重复分配"), "{}", err);
+
+        assert!(WallLayout::new(0, 2, 4, 3).validate().is_err());
+    }
+}